@@ -0,0 +1,1015 @@
+use std::{
+    collections::HashSet,
+    fmt::Display,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+use apca::{data::v2::Feed, ApiInfo};
+use chrono::{DateTime, NaiveDate, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use itertools::Itertools;
+use num_decimal::Num;
+
+use crate::{
+    backend::{BarAdjustment, PriceSource},
+    config::StrategyConfig,
+    import,
+    scrape::ScrapePolicy,
+    sectors::SectorCap,
+    sizing::{AtrRiskSizing, EquitySizing},
+    stats::{num_to_f64, PriceInput},
+    strategy::{
+        BuyAndHold, EntryRule, HoldLimit, MeanReversion, MovingAverageCrossover, ProfitTargets,
+        Strategy,
+    },
+    Symbol, TimePeriod,
+};
+
+const PAPER_API_BASE_URL: &str = "https://paper-api.alpaca.markets";
+
+/// What to do with the positions that are already open when the program starts.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum StartupLiquidation {
+    /// keep every position
+    #[default]
+    None,
+    /// sell the positions that aren't on the watch list
+    Unwatched,
+    /// sell every position
+    All,
+}
+
+/// What to do with the open positions when the market is about to close.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum DayEndPolicy {
+    /// sell everything, so that nothing is held overnight
+    #[default]
+    SellAll,
+    /// keep every position until the strategy sells it
+    HoldAll,
+    /// keep the positions overnight, but sell everything before a weekend or a holiday
+    HoldWeeknights,
+}
+
+/// What kind of order positions are closed with. Hard stops always go out at market.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ExitOrder {
+    #[default]
+    Market,
+    /// a limit at the price the exit was decided at, going to market if it doesn't fill in time
+    Limit,
+}
+
+impl FromStr for ExitOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "market" => Ok(Self::Market),
+            "limit" => Ok(Self::Limit),
+            _ => Err("expected `market` or `limit`".to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Args {
+    /// a saved response body to run through the endpoint parsers instead of trading
+    pub(crate) replay: Option<PathBuf>,
+    /// the endpoint whose parser `replay` should use. all of them are tried if this is empty
+    pub(crate) endpoint: Option<String>,
+    /// save the bars of `symbols` between `start` and `end` to this file instead of trading
+    pub(crate) download: Option<PathBuf>,
+    /// comma separated symbols to download
+    pub(crate) symbols: Option<String>,
+    pub(crate) start: Option<NaiveDate>,
+    pub(crate) end: Option<NaiveDate>,
+    /// run the strategy over the bars in this file, as saved by `download`, instead of trading
+    pub(crate) backtest: Option<PathBuf>,
+    /// write the report of `backtest` to this file as JSON
+    pub(crate) backtest_out: Option<PathBuf>,
+    /// print the open positions instead of trading
+    pub(crate) list_positions: bool,
+    /// check every flag and config file, then exit without connecting to anything
+    pub(crate) validate_config: bool,
+    /// credentials to use instead of the ones in the environment
+    pub(crate) api_key_id: Option<String>,
+    pub(crate) api_secret: Option<String>,
+    pub(crate) base_url: Option<String>,
+    /// a JSON file with the knobs of the strategy, for whatever isn't given as a flag
+    pub(crate) config: Option<PathBuf>,
+    /// the name of the strategy to trade with
+    pub(crate) strategy: Option<String>,
+    /// what the ids of this instance's orders start with, to share the account with other ones
+    pub(crate) strategy_tag: Option<String>,
+    /// the periods of the moving averages used by `ma-cross`
+    pub(crate) fast: Option<usize>,
+    pub(crate) slow: Option<usize>,
+    /// how many of the oldest bars only settle the indicators of `mean-reversion`
+    pub(crate) rsi_warmup: Option<usize>,
+    pub(crate) bollinger_warmup: Option<usize>,
+    pub(crate) min_evaluation_bars: Option<usize>,
+    pub(crate) startup_liquidation: StartupLiquidation,
+    /// how many scans only log what they would trade after starting
+    pub(crate) warmup_ticks: Option<usize>,
+    /// a broker's CSV of the positions, to take their cost basis and age from
+    pub(crate) import_positions: Option<PathBuf>,
+    /// keep the orders that are already open instead of cancelling them
+    pub(crate) resume: bool,
+    pub(crate) day_end_policy: DayEndPolicy,
+    /// how many trading days `mean-reversion` may hold a position, instead of its time limit
+    pub(crate) hold_days: Option<usize>,
+    /// only count the time the market was open towards `mean-reversion`'s time limit
+    pub(crate) hold_trading_time: bool,
+    /// the fraction of a position `mean-reversion` sells at its first profit target
+    pub(crate) scale_out: Option<Num>,
+    /// comma separated `pct:fraction` rungs of `mean-reversion`'s profit ladder
+    pub(crate) profit_ladder: Option<String>,
+    /// the most bars to fetch per symbol
+    pub(crate) bar_limit: Option<usize>,
+    /// comma separated feeds to get bars from, falling through to the next when one has nothing
+    pub(crate) feeds: Option<String>,
+    /// the bars the strategy trades on, and the longer ones that have to confirm each buy
+    pub(crate) period: Option<TimePeriod>,
+    pub(crate) confirm_period: Option<TimePeriod>,
+    /// the lowest rsi of the confirmation bars that `mean-reversion` still buys at
+    pub(crate) confirm_rsi_min: Option<f64>,
+    /// whether `mean-reversion` widens its rsi range for volatile symbols
+    pub(crate) adaptive_rsi: bool,
+    /// which value of each bar the indicators are computed from
+    pub(crate) price_input: PriceInput,
+    /// which signals `mean-reversion` buys on
+    pub(crate) entry_rule: EntryRule,
+    /// the profit targets of `mean-reversion`, in percent of the buy in price
+    pub(crate) take_profit_pct: Option<Num>,
+    pub(crate) stop_loss_pct: Option<Num>,
+    /// how many percent below the buy in price any position is closed at, indicators or not
+    pub(crate) hard_stop_pct: Option<Num>,
+    /// keep the bars between scans and only fetch the ones since the last scan
+    pub(crate) incremental_bars: bool,
+    /// which corporate actions the bars are adjusted for
+    pub(crate) adjustment: BarAdjustment,
+    /// get prices and the latest bar from snapshots instead of the latest trades
+    pub(crate) snapshots: bool,
+    pub(crate) price_source: PriceSource,
+    /// comma separated `HH:MM-HH:MM` times of day at the exchange to buy within
+    pub(crate) trading_window: Option<String>,
+    /// the timezone to log the market hours in, instead of the exchange's
+    pub(crate) tz: Option<Tz>,
+    /// how many seconds old the market clock may get before it's asked for again
+    pub(crate) max_clock_age: Option<f64>,
+    /// where to serve the latest indicators of each symbol over http
+    pub(crate) metrics_addr: Option<SocketAddr>,
+    /// keep the prices of the watched stocks up to date over a websocket instead of polling them
+    pub(crate) stream_prices: bool,
+    /// a file to append every order update to
+    pub(crate) event_log: Option<PathBuf>,
+    /// a file to append every decision of the strategy to, along with the conditions behind it
+    pub(crate) rationale_log: Option<PathBuf>,
+    /// a file with the sector of each symbol, and how many positions a sector may hold
+    pub(crate) sectors: Option<PathBuf>,
+    pub(crate) max_per_sector: Option<usize>,
+    /// the widest spread to buy into, in percent of the mid price
+    pub(crate) max_spread_pct: Option<Num>,
+    /// the most of the equity that a single position may be worth, in percent
+    pub(crate) max_concentration_pct: Option<Num>,
+    /// size buys as this fraction of the equity split between this many positions
+    pub(crate) risk_fraction: Option<Num>,
+    pub(crate) max_positions: Option<usize>,
+    /// size buys so that hitting a stop this many atrs below the entry loses this fraction of the
+    /// equity
+    pub(crate) atr_risk: Option<Num>,
+    pub(crate) atr_stop_multiple: Option<Num>,
+    /// the price band symbols have to be in to be bought
+    pub(crate) min_price: Option<Num>,
+    pub(crate) max_price: Option<Num>,
+    /// the least equity to open positions with, checked when the market opens
+    pub(crate) min_equity: Option<Num>,
+    /// warn about buying back a symbol within this many days of selling it at a loss, and whether
+    /// to skip those buys altogether
+    pub(crate) wash_sale_days: Option<u64>,
+    pub(crate) skip_wash_sales: bool,
+    /// buy stocks that allow it in fractions of a share instead of whole shares
+    pub(crate) fractional_shares: bool,
+    /// the most orders to have open at once, and whether to check the buying power before each buy
+    pub(crate) max_open_orders: Option<usize>,
+    pub(crate) recheck_buying_power: bool,
+    /// the most buys to submit within a minute
+    pub(crate) max_orders_per_minute: Option<usize>,
+    /// submit orders as limits this many basis points through the price, instead of at market
+    pub(crate) limit_offset_bps: Option<Num>,
+    pub(crate) exit_order: ExitOrder,
+    /// how many seconds a limit exit gets to fill before going to market
+    pub(crate) exit_limit_timeout: Option<f64>,
+    /// how many seconds to wait for each order to fill
+    pub(crate) confirm_timeout: Option<f64>,
+    /// how many seconds to wait before looking at a symbol that isn't held again
+    pub(crate) min_scan_interval: Option<f64>,
+    /// what to do when scraping the watch list comes back short
+    pub(crate) scrape_policy: ScrapePolicy,
+    /// scrape the watch list again before the first scan of every day, and whether to sell the
+    /// symbols that dropped off it
+    pub(crate) refresh_universe: bool,
+    pub(crate) liquidate_dropped: bool,
+    /// how many of the scraped symbols to watch
+    pub(crate) watch_limit: Option<usize>,
+    /// how many of the symbols that aren't held to look at per tick
+    pub(crate) scan_batch: Option<usize>,
+    /// comma separated symbols to never trade, on top of the ones in the `BLACKLIST` variable
+    pub(crate) blacklist: Option<String>,
+}
+
+/// What the args that need checking come out as, once they're all known to be good. Files are
+/// only read the once, while validating.
+pub(crate) struct Validated {
+    pub(crate) strategy: Box<dyn Strategy>,
+    pub(crate) sector_cap: Option<SectorCap>,
+    pub(crate) equity_sizing: Option<EquitySizing>,
+    pub(crate) max_concentration_pct: Num,
+    pub(crate) atr_risk_sizing: Option<AtrRiskSizing>,
+    pub(crate) wash_sale_window: Option<Duration>,
+    pub(crate) max_clock_age: Option<chrono::Duration>,
+    pub(crate) limit_offset_bps: Option<Num>,
+    pub(crate) strategy_tag: Option<String>,
+    pub(crate) trading_windows: Vec<(NaiveTime, NaiveTime)>,
+    pub(crate) feeds: Vec<Feed>,
+    pub(crate) api_info: Option<ApiInfo>,
+    /// `None` unless asked to `--download`
+    pub(crate) download_range: Option<(Vec<Symbol>, DateTime<Utc>, DateTime<Utc>)>,
+    /// `None` unless asked to `--import-positions`
+    pub(crate) imported_positions: Option<Vec<import::ImportedPosition>>,
+}
+
+impl Args {
+    pub(crate) fn from_env() -> Result<Self, String> {
+        Self::with_config(std::env::args().skip(1).collect())
+    }
+
+    // the config file only fills in what isn't on the command line, so it's merged first and the
+    // flags are parsed on top of it
+    fn with_config(args: Vec<String>) -> Result<Self, String> {
+        let mut result = Self::default();
+        if let Some(idx) = args.iter().position(|arg| arg == "--config") {
+            let path = value("--config", args.get(idx + 1).cloned())?;
+            result.merge(StrategyConfig::load(Path::new(&path))?);
+        }
+
+        result.parse(args)
+    }
+
+    fn parse<I>(self, args: I) -> Result<Self, String>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let mut result = self;
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--replay" => result.replay = Some(value(&arg, args.next())?.into()),
+                "--endpoint" => result.endpoint = Some(value(&arg, args.next())?),
+                "--download" => result.download = Some(value(&arg, args.next())?.into()),
+                "--symbols" => result.symbols = Some(value(&arg, args.next())?),
+                "--start" => result.start = Some(parse(&arg, args.next())?),
+                "--end" => result.end = Some(parse(&arg, args.next())?),
+                "--backtest" => result.backtest = Some(value(&arg, args.next())?.into()),
+                "--backtest-out" => result.backtest_out = Some(value(&arg, args.next())?.into()),
+                "--list-positions" => result.list_positions = true,
+                "--api-key-id" => result.api_key_id = Some(value(&arg, args.next())?),
+                "--api-secret" => result.api_secret = Some(value(&arg, args.next())?),
+                "--base-url" => result.base_url = Some(value(&arg, args.next())?),
+                "--validate-config" => result.validate_config = true,
+                "--config" => result.config = Some(value(&arg, args.next())?.into()),
+                "--strategy" => result.strategy = Some(value(&arg, args.next())?),
+                "--strategy-tag" => result.strategy_tag = Some(value(&arg, args.next())?),
+                "--fast" => result.fast = Some(parse(&arg, args.next())?),
+                "--slow" => result.slow = Some(parse(&arg, args.next())?),
+                "--rsi-warmup" => result.rsi_warmup = Some(parse(&arg, args.next())?),
+                "--bollinger-warmup" => result.bollinger_warmup = Some(parse(&arg, args.next())?),
+                "--min-evaluation-bars" => {
+                    result.min_evaluation_bars = Some(parse(&arg, args.next())?)
+                }
+                "--no-startup-liquidation" => result.startup_liquidation = StartupLiquidation::None,
+                "--liquidate-unwatched" => {
+                    result.startup_liquidation = StartupLiquidation::Unwatched
+                }
+                "--liquidate-all" => result.startup_liquidation = StartupLiquidation::All,
+                "--import-positions" => {
+                    result.import_positions = Some(value(&arg, args.next())?.into())
+                }
+                "--resume" => result.resume = true,
+                "--warmup-ticks" => result.warmup_ticks = Some(parse(&arg, args.next())?),
+                "--hold-overnight" => result.day_end_policy = DayEndPolicy::HoldAll,
+                "--hold-weeknights" => result.day_end_policy = DayEndPolicy::HoldWeeknights,
+                "--hold-days" => result.hold_days = Some(parse(&arg, args.next())?),
+                "--hold-trading-time" => result.hold_trading_time = true,
+                "--profit-ladder" => result.profit_ladder = Some(value(&arg, args.next())?),
+                "--scale-out" => result.scale_out = Some(parse(&arg, args.next())?),
+                "--bar-limit" => result.bar_limit = Some(parse(&arg, args.next())?),
+                "--feeds" => result.feeds = Some(value(&arg, args.next())?),
+                "--period" => result.period = Some(parse(&arg, args.next())?),
+                "--confirm-period" => result.confirm_period = Some(parse(&arg, args.next())?),
+                "--confirm-rsi-min" => result.confirm_rsi_min = Some(parse(&arg, args.next())?),
+                "--adaptive-rsi" => result.adaptive_rsi = true,
+                "--indicator-input" => result.price_input = parse(&arg, args.next())?,
+                "--entry-rule" => result.entry_rule = parse(&arg, args.next())?,
+                "--take-profit-pct" => result.take_profit_pct = Some(parse(&arg, args.next())?),
+                "--stop-loss-pct" => result.stop_loss_pct = Some(parse(&arg, args.next())?),
+                "--hard-stop-pct" => result.hard_stop_pct = Some(parse(&arg, args.next())?),
+                "--incremental-bars" => result.incremental_bars = true,
+                "--adjustment" => result.adjustment = parse(&arg, args.next())?,
+                "--snapshots" => result.snapshots = true,
+                "--price-source" => result.price_source = parse(&arg, args.next())?,
+                "--trading-window" => result.trading_window = Some(value(&arg, args.next())?),
+                "--tz" => result.tz = Some(parse(&arg, args.next())?),
+                "--max-clock-age" => result.max_clock_age = Some(parse(&arg, args.next())?),
+                "--metrics-addr" => result.metrics_addr = Some(parse(&arg, args.next())?),
+                "--stream-prices" => result.stream_prices = true,
+                "--event-log" => result.event_log = Some(value(&arg, args.next())?.into()),
+                "--rationale-log" => result.rationale_log = Some(value(&arg, args.next())?.into()),
+                "--sectors" => result.sectors = Some(value(&arg, args.next())?.into()),
+                "--max-per-sector" => result.max_per_sector = Some(parse(&arg, args.next())?),
+                "--risk-fraction" => result.risk_fraction = Some(parse(&arg, args.next())?),
+                "--max-positions" => result.max_positions = Some(parse(&arg, args.next())?),
+                "--atr-risk" => result.atr_risk = Some(parse(&arg, args.next())?),
+                "--atr-stop-multiple" => result.atr_stop_multiple = Some(parse(&arg, args.next())?),
+                "--min-price" => result.min_price = Some(parse(&arg, args.next())?),
+                "--min-equity" => result.min_equity = Some(parse(&arg, args.next())?),
+                "--wash-sale-days" => result.wash_sale_days = Some(parse(&arg, args.next())?),
+                "--skip-wash-sales" => result.skip_wash_sales = true,
+                "--max-price" => result.max_price = Some(parse(&arg, args.next())?),
+                "--max-spread-pct" => result.max_spread_pct = Some(parse(&arg, args.next())?),
+                "--max-concentration-pct" => {
+                    result.max_concentration_pct = Some(parse(&arg, args.next())?)
+                }
+                "--blacklist" => result.blacklist = Some(value(&arg, args.next())?),
+                "--max-open-orders" => result.max_open_orders = Some(parse(&arg, args.next())?),
+                "--recheck-buying-power" => result.recheck_buying_power = true,
+                "--fractional-shares" => result.fractional_shares = true,
+                "--max-orders-per-minute" => {
+                    result.max_orders_per_minute = Some(parse(&arg, args.next())?)
+                }
+                "--limit-offset-bps" => result.limit_offset_bps = Some(parse(&arg, args.next())?),
+                "--exit-order" => result.exit_order = parse(&arg, args.next())?,
+                "--exit-limit-timeout" => {
+                    result.exit_limit_timeout = Some(parse(&arg, args.next())?)
+                }
+                "--confirm-timeout" => result.confirm_timeout = Some(parse(&arg, args.next())?),
+                "--scrape-policy" => result.scrape_policy = parse(&arg, args.next())?,
+                "--refresh-universe" => result.refresh_universe = true,
+                "--liquidate-dropped" => result.liquidate_dropped = true,
+                "--watch-limit" => result.watch_limit = Some(parse(&arg, args.next())?),
+                "--scan-batch" => result.scan_batch = Some(parse(&arg, args.next())?),
+                "--min-scan-interval" => result.min_scan_interval = Some(parse(&arg, args.next())?),
+                _ => match arg.strip_prefix("--no-") {
+                    Some(switch) => result.turn_off(switch)?,
+                    None => return Err(format!("unknown argument `{arg}`")),
+                },
+            }
+        }
+
+        Ok(result)
+    }
+
+    // the switches can be turned off again, e.g. when the config turned them on
+    fn turn_off(&mut self, switch: &str) -> Result<(), String> {
+        match switch {
+            "hold-overnight" => self.hold_overnight(DayEndPolicy::HoldAll, false),
+            "hold-weeknights" => self.hold_overnight(DayEndPolicy::HoldWeeknights, false),
+            "hold-trading-time" => self.hold_trading_time = false,
+            "adaptive-rsi" => self.adaptive_rsi = false,
+            "incremental-bars" => self.incremental_bars = false,
+            "snapshots" => self.snapshots = false,
+            "stream-prices" => self.stream_prices = false,
+            "skip-wash-sales" => self.skip_wash_sales = false,
+            "fractional-shares" => self.fractional_shares = false,
+            "recheck-buying-power" => self.recheck_buying_power = false,
+            "refresh-universe" => self.refresh_universe = false,
+            "liquidate-dropped" => self.liquidate_dropped = false,
+            _ => return Err(format!("unknown argument `--no-{switch}`")),
+        }
+
+        Ok(())
+    }
+
+    // turning off a way of holding positions overnight only goes back to selling them when it's
+    // the one that was on
+    fn hold_overnight(&mut self, policy: DayEndPolicy, on: bool) {
+        if on {
+            self.day_end_policy = policy;
+        } else if self.day_end_policy == policy {
+            self.day_end_policy = DayEndPolicy::SellAll;
+        }
+    }
+
+    /// Takes every knob the config sets, switches that it sets to `false` included.
+    pub(crate) fn merge(&mut self, config: StrategyConfig) {
+        fill(&mut self.strategy, config.strategy);
+        fill(&mut self.strategy_tag, config.strategy_tag);
+        fill(&mut self.fast, config.fast);
+        fill(&mut self.slow, config.slow);
+        fill(&mut self.rsi_warmup, config.rsi_warmup);
+        fill(&mut self.bollinger_warmup, config.bollinger_warmup);
+        fill(&mut self.min_evaluation_bars, config.min_evaluation_bars);
+        fill(&mut self.warmup_ticks, config.warmup_ticks);
+        if let Some(on) = config.hold_overnight {
+            self.hold_overnight(DayEndPolicy::HoldAll, on);
+        }
+        if let Some(on) = config.hold_weeknights {
+            self.hold_overnight(DayEndPolicy::HoldWeeknights, on);
+        }
+        fill(&mut self.hold_days, config.hold_days);
+        set(&mut self.hold_trading_time, config.hold_trading_time);
+        fill(&mut self.scale_out, config.scale_out);
+        fill(&mut self.profit_ladder, config.profit_ladder);
+        fill(&mut self.bar_limit, config.bar_limit);
+        fill(&mut self.feeds, config.feeds);
+        fill(&mut self.period, config.period);
+        fill(&mut self.confirm_period, config.confirm_period);
+        fill(&mut self.confirm_rsi_min, config.confirm_rsi_min);
+        set(&mut self.adaptive_rsi, config.adaptive_rsi);
+        set(&mut self.price_input, config.indicator_input);
+        set(&mut self.entry_rule, config.entry_rule);
+        fill(&mut self.take_profit_pct, config.take_profit_pct);
+        fill(&mut self.stop_loss_pct, config.stop_loss_pct);
+        fill(&mut self.hard_stop_pct, config.hard_stop_pct);
+        set(&mut self.incremental_bars, config.incremental_bars);
+        set(&mut self.adjustment, config.adjustment);
+        set(&mut self.snapshots, config.snapshots);
+        set(&mut self.price_source, config.price_source);
+        fill(&mut self.trading_window, config.trading_window);
+        fill(&mut self.tz, config.tz);
+        fill(&mut self.max_clock_age, config.max_clock_age);
+        set(&mut self.stream_prices, config.stream_prices);
+        fill(&mut self.max_per_sector, config.max_per_sector);
+        fill(&mut self.max_spread_pct, config.max_spread_pct);
+        fill(
+            &mut self.max_concentration_pct,
+            config.max_concentration_pct,
+        );
+        fill(&mut self.risk_fraction, config.risk_fraction);
+        fill(&mut self.max_positions, config.max_positions);
+        fill(&mut self.atr_risk, config.atr_risk);
+        fill(&mut self.atr_stop_multiple, config.atr_stop_multiple);
+        fill(&mut self.min_price, config.min_price);
+        fill(&mut self.max_price, config.max_price);
+        fill(&mut self.min_equity, config.min_equity);
+        fill(&mut self.wash_sale_days, config.wash_sale_days);
+        set(&mut self.skip_wash_sales, config.skip_wash_sales);
+        set(&mut self.fractional_shares, config.fractional_shares);
+        fill(&mut self.max_open_orders, config.max_open_orders);
+        set(&mut self.recheck_buying_power, config.recheck_buying_power);
+        fill(
+            &mut self.max_orders_per_minute,
+            config.max_orders_per_minute,
+        );
+        fill(&mut self.limit_offset_bps, config.limit_offset_bps);
+        set(&mut self.exit_order, config.exit_order);
+        fill(&mut self.exit_limit_timeout, config.exit_limit_timeout);
+        fill(&mut self.confirm_timeout, config.confirm_timeout);
+        fill(&mut self.min_scan_interval, config.min_scan_interval);
+        set(&mut self.scrape_policy, config.scrape_policy);
+        set(&mut self.refresh_universe, config.refresh_universe);
+        set(&mut self.liquidate_dropped, config.liquidate_dropped);
+        fill(&mut self.watch_limit, config.watch_limit);
+        fill(&mut self.scan_batch, config.scan_batch);
+        fill(&mut self.blacklist, config.blacklist);
+    }
+
+    /// the account given on the command line, or `None` if the environment should be used
+    pub(crate) fn api_info(&self) -> Result<Option<ApiInfo>, String> {
+        match (&self.api_key_id, &self.api_secret) {
+            (Some(key_id), Some(secret)) => ApiInfo::from_parts(
+                self.base_url.as_deref().unwrap_or(PAPER_API_BASE_URL),
+                key_id,
+                secret,
+            )
+            .map(Some)
+            .map_err(|why| format!("invalid api info: {why}")),
+            (None, None) if self.base_url.is_none() => Ok(None),
+            _ => Err("`--api-key-id` and `--api-secret` must be given together".to_string()),
+        }
+    }
+
+    /// the symbols to download and the time range to download them over
+    pub(crate) fn download_range(
+        &self,
+    ) -> Result<(Vec<Symbol>, DateTime<Utc>, DateTime<Utc>), String> {
+        let symbols = self
+            .symbols
+            .as_deref()
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|symbol| !symbol.is_empty())
+            .map(Symbol::from)
+            .collect::<Vec<_>>();
+        if symbols.is_empty() {
+            return Err("`--download` needs `--symbols`".to_string());
+        }
+
+        let (Some(start), Some(end)) = (self.start, self.end) else {
+            return Err("`--download` needs `--start` and `--end`".to_string());
+        };
+        if start >= end {
+            return Err("`--start` must be before `--end`".to_string());
+        }
+
+        Ok((
+            symbols,
+            Utc.from_utc_datetime(&start.and_hms_opt(0, 0, 0).unwrap()),
+            Utc.from_utc_datetime(&end.and_hms_opt(0, 0, 0).unwrap()),
+        ))
+    }
+
+    /// the feeds given with `--feeds`, or just IEX, which every account has access to
+    pub(crate) fn feeds(&self) -> Result<Vec<Feed>, String> {
+        let Some(feeds) = &self.feeds else {
+            return Ok(vec![Feed::IEX]);
+        };
+
+        let feeds = feeds
+            .split(',')
+            .map(str::trim)
+            .filter(|feed| !feed.is_empty())
+            .map(|feed| match feed {
+                "iex" => Ok(Feed::IEX),
+                "sip" => Ok(Feed::SIP),
+                _ => Err(format!("unknown feed `{feed}`, expected `iex` or `sip`")),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if feeds.is_empty() {
+            return Err("`--feeds` needs at least one feed".to_string());
+        }
+
+        Ok(feeds)
+    }
+
+    /// the symbols given with `--blacklist` and in the `BLACKLIST` environment variable
+    pub(crate) fn blacklist(&self) -> HashSet<Symbol> {
+        let from_env = std::env::var("BLACKLIST").unwrap_or_default();
+
+        from_env
+            .split(',')
+            .chain(self.blacklist.as_deref().unwrap_or_default().split(','))
+            .map(str::trim)
+            .filter(|symbol| !symbol.is_empty())
+            .map(Symbol::from)
+            .collect()
+    }
+
+    pub(crate) fn equity_sizing(
+        &self,
+        strategy: &dyn Strategy,
+    ) -> Result<Option<EquitySizing>, String> {
+        // some strategies size every buy by equity, unless told otherwise
+        let default_risk_fraction = strategy.risk_fraction();
+
+        match (&self.risk_fraction, self.max_positions) {
+            (_, Some(0)) => Err("`--max-positions` must be at least 1".to_string()),
+            (Some(risk_fraction), _)
+                if *risk_fraction <= Num::from(0) || *risk_fraction > Num::from(1) =>
+            {
+                Err("`--risk-fraction` must be above 0 and at most 1".to_string())
+            }
+            (Some(risk_fraction), Some(max_positions)) => Ok(Some(EquitySizing {
+                risk_fraction: risk_fraction.clone(),
+                max_positions,
+            })),
+            (None, Some(max_positions)) => match default_risk_fraction {
+                Some(risk_fraction) => Ok(Some(EquitySizing {
+                    risk_fraction,
+                    max_positions,
+                })),
+                None => Err(
+                    "`--risk-fraction` and `--max-positions` must be given together".to_string(),
+                ),
+            },
+            (None, None) if default_risk_fraction.is_some() => Err(format!(
+                "`--strategy {}` needs `--max-positions` to split the equity between",
+                self.strategy.as_deref().unwrap_or_default()
+            )),
+            (None, None) => Ok(None),
+            _ => Err("`--risk-fraction` and `--max-positions` must be given together".to_string()),
+        }
+    }
+
+    /// How much of the equity a single position may be worth, in percent. It defaults to the
+    /// strategy's own cap, or the slice of the equity that every buy is sized at if that's more.
+    pub(crate) fn max_concentration_pct(&self, strategy: &dyn Strategy) -> Result<Num, String> {
+        let slice_pct = self
+            .equity_sizing(strategy)?
+            .map(|equity_sizing| equity_sizing.slice_pct());
+
+        match &self.max_concentration_pct {
+            Some(pct) if *pct <= Num::from(0) || *pct > Num::from(100) => {
+                Err("`--max-concentration-pct` must be above 0 and at most 100".to_string())
+            }
+            Some(pct) => match slice_pct {
+                // every buy would be trimmed down to the cap, and never be as big as it's sized
+                Some(slice_pct) if *pct < slice_pct => Err(format!(
+                    "`--max-concentration-pct` is below the {:.2}% of the equity that every buy is sized at",
+                    num_to_f64(&slice_pct)
+                )),
+                _ => Ok(pct.clone()),
+            },
+            None => {
+                let default = strategy.max_concentration_pct();
+                Ok(slice_pct.map_or(default.clone(), |slice_pct| slice_pct.max(default)))
+            }
+        }
+    }
+
+    pub(crate) fn atr_risk_sizing(&self) -> Result<Option<AtrRiskSizing>, String> {
+        match (&self.atr_risk, &self.atr_stop_multiple) {
+            (Some(_), _) if self.risk_fraction.is_some() => {
+                Err("`--atr-risk` and `--risk-fraction` can't be used together".to_string())
+            }
+            (Some(risk_per_trade), atr_multiple) => Ok(Some(AtrRiskSizing {
+                risk_per_trade: risk_per_trade.clone(),
+                atr_multiple: atr_multiple.clone().unwrap_or_else(|| Num::from(2)),
+                atr_period: 14,
+            })),
+            (None, Some(_)) => Err("`--atr-stop-multiple` needs `--atr-risk`".to_string()),
+            (None, None) => Ok(None),
+        }
+    }
+
+    pub(crate) fn wash_sale_window(&self) -> Result<Option<Duration>, String> {
+        match self.wash_sale_days {
+            Some(0) => Err("`--wash-sale-days` must be at least 1".to_string()),
+            Some(days) => days
+                .checked_mul(24 * 60 * 60)
+                .map(|secs| Some(Duration::from_secs(secs)))
+                .ok_or_else(|| "`--wash-sale-days` is too long".to_string()),
+            None if self.skip_wash_sales => {
+                Err("`--skip-wash-sales` needs `--wash-sale-days`".to_string())
+            }
+            None => Ok(None),
+        }
+    }
+
+    pub(crate) fn max_clock_age(&self) -> Result<Option<chrono::Duration>, String> {
+        let Some(max_clock_age) = self.max_clock_age else {
+            return Ok(None);
+        };
+
+        Duration::try_from_secs_f64(max_clock_age)
+            .ok()
+            .filter(|age| !age.is_zero())
+            .and_then(|age| chrono::Duration::from_std(age).ok())
+            .map(Some)
+            .ok_or_else(|| "`--max-clock-age` must be a positive number of seconds".to_string())
+    }
+
+    pub(crate) fn limit_offset_bps(&self) -> Result<Option<Num>, String> {
+        match &self.limit_offset_bps {
+            Some(bps) if *bps <= Num::from(0) => {
+                Err("`--limit-offset-bps` must be positive".to_string())
+            }
+            bps => Ok(bps.clone()),
+        }
+    }
+
+    // the tag goes in front of a timestamp and a counter in the client order id, which alpaca caps
+    // at 128 characters
+    pub(crate) fn strategy_tag(&self) -> Result<Option<String>, String> {
+        let Some(tag) = &self.strategy_tag else {
+            return Ok(None);
+        };
+
+        if tag.is_empty()
+            || tag.len() > 64
+            || !tag
+                .chars()
+                .all(|ch| ch.is_ascii_alphanumeric() || ch == '_')
+        {
+            return Err(format!(
+                "invalid tag `{tag}` for `--strategy-tag`, expected up to 64 letters, digits or `_`"
+            ));
+        }
+
+        Ok(Some(tag.clone()))
+    }
+
+    pub(crate) fn sector_cap(&self) -> Result<Option<SectorCap>, String> {
+        match (&self.sectors, self.max_per_sector) {
+            (Some(path), Some(max_positions)) => SectorCap::load(path, max_positions).map(Some),
+            (None, None) => Ok(None),
+            _ => Err("`--sectors` and `--max-per-sector` must be given together".to_string()),
+        }
+    }
+
+    fn profit_targets(&self) -> ProfitTargets {
+        let mut profit_targets = ProfitTargets::from(Num::new(9, 10)..Num::new(15, 10));
+
+        if let Some(take_profit_pct) = &self.take_profit_pct {
+            profit_targets.take_profit_pct = take_profit_pct.clone();
+        }
+        if let Some(stop_loss_pct) = &self.stop_loss_pct {
+            profit_targets.stop_loss_pct = stop_loss_pct.clone();
+        }
+
+        profit_targets
+    }
+
+    fn scale_out(&self) -> Result<Option<Num>, String> {
+        match &self.scale_out {
+            Some(fraction) if *fraction <= Num::from(0) || *fraction > Num::from(1) => {
+                Err("`--scale-out` must be above 0 and at most 1".to_string())
+            }
+            fraction => Ok(fraction.clone()),
+        }
+    }
+
+    // e.g. `5:0.33,10:0.33,20:1` sells a third at +5%, another third at +10% and the rest at +20%
+    fn profit_ladder(&self) -> Result<Vec<(f64, Num)>, String> {
+        let Some(profit_ladder) = &self.profit_ladder else {
+            return Ok(Vec::new());
+        };
+
+        profit_ladder
+            .split(',')
+            .map(str::trim)
+            .filter(|rung| !rung.is_empty())
+            .map(|rung| -> Result<(f64, Num), String> {
+                let invalid = || format!("invalid rung `{rung}` for `--profit-ladder`");
+                let (pct, fraction) = rung.split_once(':').ok_or_else(invalid)?;
+
+                let fraction: Num = fraction.trim().parse().map_err(|_| invalid())?;
+                if fraction <= Num::from(0) || fraction > Num::from(1) {
+                    return Err(format!(
+                        "the fraction of rung `{rung}` for `--profit-ladder` must be above 0 and at most 1"
+                    ));
+                }
+
+                Ok((pct.trim().parse().map_err(|_| invalid())?, fraction))
+            })
+            .collect()
+    }
+
+    /// the times of day at the exchange that buys are allowed within. empty if they always are
+    pub(crate) fn trading_windows(&self) -> Result<Vec<(NaiveTime, NaiveTime)>, String> {
+        let Some(trading_window) = &self.trading_window else {
+            return Ok(Vec::new());
+        };
+
+        trading_window
+            .split(',')
+            .map(str::trim)
+            .filter(|window| !window.is_empty())
+            .map(|window| -> Result<(NaiveTime, NaiveTime), String> {
+                let invalid = || format!("invalid window `{window}` for `--trading-window`");
+                let (start, end) = window.split_once('-').ok_or_else(invalid)?;
+                let start =
+                    NaiveTime::parse_from_str(start.trim(), "%H:%M").map_err(|_| invalid())?;
+                let end = NaiveTime::parse_from_str(end.trim(), "%H:%M").map_err(|_| invalid())?;
+
+                if start >= end {
+                    return Err(format!(
+                        "`{window}` of `--trading-window` ends before it starts"
+                    ));
+                }
+
+                Ok((start, end))
+            })
+            .collect()
+    }
+
+    fn hold_limit(&self) -> Result<HoldLimit, String> {
+        match self.hold_days {
+            // positions would be sold at the end of the day before the limit is ever reached
+            Some(_) if self.day_end_policy == DayEndPolicy::SellAll => {
+                Err("`--hold-days` needs `--hold-overnight`".to_string())
+            }
+            Some(_) if self.hold_trading_time => {
+                Err("`--hold-days` and `--hold-trading-time` can't be used together".to_string())
+            }
+            Some(days) => Ok(HoldLimit::TradingDays(days)),
+            // the time the market is closed for is only known for the positions held through a
+            // close, and without holding overnight it would be the same as the wall clock time
+            None if self.hold_trading_time && self.day_end_policy == DayEndPolicy::SellAll => Err(
+                "`--hold-trading-time` needs `--hold-overnight` or `--hold-weeknights`".to_string(),
+            ),
+            None if self.hold_trading_time => {
+                Ok(HoldLimit::TradingTime(Duration::from_secs(60 * 30)))
+            }
+            None => Ok(HoldLimit::Time(Duration::from_secs(60 * 30))),
+        }
+    }
+
+    pub(crate) fn strategy(&self) -> Result<Box<dyn Strategy>, String> {
+        match self.strategy.as_deref().unwrap_or("mean-reversion") {
+            "mean-reversion" => Ok(Box::new(MeanReversion {
+                rsi_range: 30.0..70.0,
+                hold_limit: self.hold_limit()?,
+                profit_targets: self.profit_targets(),
+                scale_out: self.scale_out()?,
+                profit_ladder: self.profit_ladder()?,
+                rsi_warmup: self.rsi_warmup.unwrap_or(0),
+                bollinger_warmup: self.bollinger_warmup.unwrap_or(0),
+                min_evaluation_bars: self.min_evaluation_bars.unwrap_or(1),
+                adaptive_rsi: self.adaptive_rsi,
+                entry_rule: self.entry_rule,
+                confirm_rsi_min: self.confirm_rsi_min.unwrap_or(50.0),
+                price_input: self.price_input,
+                cache: Default::default(),
+                trailing_highs: Default::default(),
+            })),
+            "ma-cross" => {
+                let fast = self.fast.unwrap_or(20);
+                let slow = self.slow.unwrap_or(50);
+
+                if fast >= slow {
+                    return Err("`--fast` must be shorter than `--slow`".to_string());
+                }
+
+                Ok(Box::new(MovingAverageCrossover {
+                    fast,
+                    slow,
+                    price_input: self.price_input,
+                }))
+            }
+            // everything would be sold again at the end of the first day
+            "buy-and-hold" if self.day_end_policy == DayEndPolicy::SellAll => {
+                Err("`--strategy buy-and-hold` needs `--hold-overnight`".to_string())
+            }
+            "buy-and-hold" => Ok(Box::new(BuyAndHold)),
+            other => Err(format!("unknown strategy `{other}`")),
+        }
+    }
+
+    /// Checks everything that would stop a run, or make it trade nonsense, without any network
+    /// access. Either the config is good to go, or every problem with it is returned.
+    pub(crate) fn validate(&self) -> Result<Validated, Vec<String>> {
+        let mut problems = Vec::new();
+
+        let strategy = checked(self.strategy(), &mut problems);
+        // the sizing depends on the strategy, and can only be checked along with a valid one
+        let (equity_sizing, max_concentration_pct) = match &strategy {
+            Some(strategy) => (
+                checked(self.equity_sizing(strategy.as_ref()), &mut problems),
+                checked(self.max_concentration_pct(strategy.as_ref()), &mut problems),
+            ),
+            None => (None, None),
+        };
+        // only looked at by `mean-reversion`, but never right for any strategy
+        checked(self.scale_out(), &mut problems);
+        checked(self.profit_ladder(), &mut problems);
+
+        let sector_cap = checked(self.sector_cap(), &mut problems);
+        let atr_risk_sizing = checked(self.atr_risk_sizing(), &mut problems);
+        let wash_sale_window = checked(self.wash_sale_window(), &mut problems);
+        let limit_offset_bps = checked(self.limit_offset_bps(), &mut problems);
+        let max_clock_age = checked(self.max_clock_age(), &mut problems);
+        let strategy_tag = checked(self.strategy_tag(), &mut problems);
+        let trading_windows = checked(self.trading_windows(), &mut problems);
+        let feeds = checked(self.feeds(), &mut problems);
+        let api_info = checked(self.api_info(), &mut problems);
+        let download_range = match &self.download {
+            Some(_) => checked(self.download_range().map(Some), &mut problems),
+            None => Some(None),
+        };
+        let imported_positions = match &self.import_positions {
+            Some(path) => checked(import::load_positions_csv(path).map(Some), &mut problems),
+            None => Some(None),
+        };
+
+        let zero = Num::from(0);
+        let one = Num::from(1);
+        let positive = [
+            ("--take-profit-pct", &self.take_profit_pct),
+            ("--stop-loss-pct", &self.stop_loss_pct),
+            ("--hard-stop-pct", &self.hard_stop_pct),
+            ("--max-spread-pct", &self.max_spread_pct),
+            ("--atr-stop-multiple", &self.atr_stop_multiple),
+            ("--min-equity", &self.min_equity),
+        ];
+        for (flag, value) in positive {
+            if value.as_ref().map_or(false, |value| *value <= zero) {
+                problems.push(format!("`{flag}` must be positive"));
+            }
+        }
+
+        if self
+            .atr_risk
+            .as_ref()
+            .map_or(false, |atr_risk| *atr_risk <= zero || *atr_risk > one)
+        {
+            problems.push("`--atr-risk` must be above 0 and at most 1".to_string());
+        }
+
+        if self.backtest_out.is_some() && self.backtest.is_none() {
+            problems.push("`--backtest-out` needs `--backtest`".to_string());
+        }
+
+        if let (Some(min_price), Some(max_price)) = (&self.min_price, &self.max_price) {
+            if min_price > max_price {
+                problems.push("`--min-price` is above `--max-price`".to_string());
+            }
+        }
+
+        if let Some(confirm_rsi_min) = self.confirm_rsi_min {
+            if !(0.0..=100.0).contains(&confirm_rsi_min) {
+                problems.push("`--confirm-rsi-min` must be between 0 and 100".to_string());
+            }
+        }
+
+        let listed = [
+            ("--blacklist", &self.blacklist),
+            ("--symbols", &self.symbols),
+        ];
+        for (flag, symbols) in listed {
+            let symbols = symbols.as_deref().unwrap_or_default().split(',');
+            for symbol in symbols.map(str::trim).filter(|symbol| !symbol.is_empty()) {
+                if !is_well_formed_symbol(symbol) {
+                    problems.push(format!("`{symbol}` of `{flag}` isn't a valid symbol"));
+                }
+            }
+        }
+
+        let validated = (move || {
+            Some(Validated {
+                strategy: strategy?,
+                sector_cap: sector_cap?,
+                equity_sizing: equity_sizing?,
+                max_concentration_pct: max_concentration_pct?,
+                atr_risk_sizing: atr_risk_sizing?,
+                wash_sale_window: wash_sale_window?,
+                max_clock_age: max_clock_age?,
+                limit_offset_bps: limit_offset_bps?,
+                strategy_tag: strategy_tag?,
+                trading_windows: trading_windows?,
+                feeds: feeds?,
+                api_info: api_info?,
+                download_range: download_range?,
+                imported_positions: imported_positions?,
+            })
+        })();
+
+        match validated {
+            Some(validated) if problems.is_empty() => Ok(validated),
+            // the strategy fails on the same knobs that are checked on their own
+            _ => Err(problems.into_iter().unique().collect()),
+        }
+    }
+}
+
+// the value if it's good, and otherwise the problem with it
+fn checked<T>(result: Result<T, String>, problems: &mut Vec<String>) -> Option<T> {
+    match result {
+        Ok(value) => Some(value),
+        Err(why) => {
+            problems.push(why);
+            None
+        }
+    }
+}
+
+// tickers are letters with the odd class share dot, e.g. `BRK.B`, and cryptos are pairs of them
+fn is_well_formed_symbol(symbol: &str) -> bool {
+    let is_ticker = |ticker: &str| {
+        !ticker.is_empty()
+            && ticker.len() <= 10
+            && ticker
+                .chars()
+                .all(|ch| ch.is_ascii_alphabetic() || ch == '.')
+    };
+
+    match symbol.split_once('/') {
+        Some((base, quote)) => is_ticker(base) && is_ticker(quote),
+        None => is_ticker(symbol),
+    }
+}
+
+// takes the value of a knob, if it's given at all
+fn set<T>(knob: &mut T, value: Option<T>) {
+    if let Some(value) = value {
+        *knob = value;
+    }
+}
+
+fn fill<T>(knob: &mut Option<T>, value: Option<T>) {
+    if value.is_some() {
+        *knob = value;
+    }
+}
+
+fn value(flag: &str, value: Option<String>) -> Result<String, String> {
+    value.ok_or_else(|| format!("`{flag}` expects a value"))
+}
+
+fn parse<T>(flag: &str, value: Option<String>) -> Result<T, String>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    let value = self::value(flag, value)?;
+    value
+        .parse()
+        .map_err(|why| format!("invalid value `{value}` for `{flag}`: {why}"))
+}
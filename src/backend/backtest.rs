@@ -0,0 +1,423 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use apca::{
+    api::v2::{
+        clock::Clock,
+        order::{Amount, Side, Status},
+    },
+    data::v2::Feed,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Datelike, NaiveTime, TimeZone, Utc, Weekday};
+use num_decimal::Num;
+
+use crate::{
+    bars,
+    wait::{TimeSource, EXCHANGE_TZ},
+    AccountState, Symbol, TimePeriod,
+};
+
+use super::{clock, endpoints::LastTrade, AssetClass, Backend, PriceSource, Snapshot, Stats};
+
+/// A backend that trades against bars it was given up front, with a made up account and a clock
+/// that only moves when it's told to. Nothing goes over the network, so whole strategies can be
+/// run offline.
+///
+/// Orders fill in full at the close of the latest bar that has ended, and the market keeps
+/// regular hours on every weekday. Holidays and early closes aren't known about.
+pub(crate) struct BacktestBackend {
+    /// the bars of every symbol, oldest first
+    bars: HashMap<Symbol, Vec<bars::Bar>>,
+    bar_length: chrono::Duration,
+    now: Mutex<DateTime<Utc>>,
+    /// the time the clock keeps up with on its own, if it isn't only moved with `advance_to`
+    time: Option<Box<dyn TimeSource>>,
+    cash: Mutex<Num>,
+    /// the equity at the end of the last session
+    last_equity: Mutex<Num>,
+    account: AccountState,
+}
+
+impl BacktestBackend {
+    pub(crate) fn new(
+        mut bars: HashMap<Symbol, Vec<bars::Bar>>,
+        bar_length: chrono::Duration,
+        start: DateTime<Utc>,
+        cash: Num,
+    ) -> Self {
+        for bars in bars.values_mut() {
+            bars.sort_by_key(|bar| bar.time);
+        }
+
+        Self {
+            bars,
+            bar_length,
+            now: Mutex::new(start),
+            time: None,
+            cash: Mutex::new(cash.clone()),
+            last_equity: Mutex::new(cash),
+            account: AccountState::new(Default::default()),
+        }
+    }
+
+    /// Keeps the clock up with the time source, so that the backend can be driven by a `Ticker`.
+    #[cfg(test)]
+    pub(crate) fn following(self, time: Box<dyn TimeSource>) -> Self {
+        Self {
+            time: Some(time),
+            ..self
+        }
+    }
+
+    /// Moves the clock forward. Only the bars that have ended by then can be seen.
+    pub(crate) fn advance_to(&self, time: DateTime<Utc>) {
+        let mut now = self.now.lock().unwrap();
+        *now = (*now).max(time);
+    }
+
+    pub(crate) fn cash(&self) -> Num {
+        self.cash.lock().unwrap().clone()
+    }
+
+    /// When each of the bars ends, in order and without repeats. Those are the times there's
+    /// something new to scan.
+    pub(crate) fn bar_ends(&self) -> Vec<DateTime<Utc>> {
+        let mut ends = self
+            .bars
+            .values()
+            .flatten()
+            .map(|bar| bar.time + self.bar_length)
+            .collect::<Vec<_>>();
+        ends.sort();
+        ends.dedup();
+        ends
+    }
+
+    // the bars of the symbol that have ended by now
+    fn visible_bars(&self, symbol: &Symbol) -> &[bars::Bar] {
+        let Some(bars) = self.bars.get(symbol) else {
+            return &[];
+        };
+
+        let now = self.now();
+        let ended = bars.partition_point(|bar| bar.time + self.bar_length <= now);
+        &bars[..ended]
+    }
+
+    fn price(&self, symbol: &Symbol) -> Option<Num> {
+        self.visible_bars(symbol)
+            .last()
+            .map(|bar| bar.close.clone())
+    }
+
+    fn prices(&self) -> HashMap<Symbol, Num> {
+        self.bars
+            .keys()
+            .filter_map(|symbol| Some((symbol.clone(), self.price(symbol)?)))
+            .collect()
+    }
+
+    // fills the whole order at the current price, as long as there's the cash or the shares for it
+    fn fill(&self, symbol: Symbol, side: Side, amount: Amount) {
+        let Some(price) = self.price(&symbol) else {
+            tracing::warn!("{symbol} has no price yet, skipping the order");
+            return;
+        };
+
+        let quantity = match amount {
+            Amount::Quantity { quantity } => quantity,
+            Amount::Notional { notional } => notional / price.clone(),
+        };
+        let mut cash = self.cash.lock().unwrap();
+
+        let quantity = match side {
+            Side::Buy => {
+                if quantity.clone() * price.clone() > *cash {
+                    tracing::warn!("not enough cash to buy {symbol}, skipping the order");
+                    return;
+                }
+                quantity
+            }
+            _ => {
+                let owned = self
+                    .account
+                    .positions
+                    .get(&symbol)
+                    .map_or_else(Num::default, |pos| pos.owned.clone());
+                quantity.min(owned)
+            }
+        };
+
+        if quantity.is_zero() {
+            return;
+        }
+
+        match side {
+            Side::Buy => *cash -= quantity.clone() * price.clone(),
+            _ => *cash += quantity.clone() * price.clone(),
+        }
+
+        self.account.apply_fill(
+            symbol,
+            side,
+            &quantity,
+            Some(&price),
+            Status::Filled,
+            self.now(),
+        );
+    }
+
+    fn current_equity(&self) -> Num {
+        self.cash() + self.account.total_owned_value(&self.prices())
+    }
+}
+
+// the regular session of the day at the exchange, if it's a weekday
+fn session(day: chrono::NaiveDate) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+    if matches!(day.weekday(), Weekday::Sat | Weekday::Sun) {
+        return None;
+    }
+
+    let at = |hour, min| {
+        EXCHANGE_TZ
+            .from_local_datetime(&day.and_time(NaiveTime::from_hms_opt(hour, min, 0).unwrap()))
+            .single()
+            .map(|time| time.with_timezone(&Utc))
+    };
+
+    Some((at(9, 30)?, at(16, 0)?))
+}
+
+// the clock for regular market hours, as alpaca would return it at the time
+fn clock_at(now: DateTime<Utc>) -> Clock {
+    let today = now.with_timezone(&EXCHANGE_TZ).date_naive();
+
+    let (open, close) = today
+        .iter_days()
+        .filter_map(session)
+        .find(|(_, close)| now < *close)
+        .unwrap();
+
+    if now < open {
+        return clock(false, now, open, close);
+    }
+
+    let (next_open, _) = (today + chrono::Duration::days(1))
+        .iter_days()
+        .filter_map(session)
+        .next()
+        .unwrap();
+
+    clock(true, now, next_open, close)
+}
+
+#[async_trait]
+impl Backend for BacktestBackend {
+    fn now(&self) -> DateTime<Utc> {
+        let now = *self.now.lock().unwrap();
+        self.time.as_ref().map_or(now, |time| now.max(time.now()))
+    }
+
+    // limits are only ever put at or through the price, so they fill at it like market orders
+    async fn submit_order(
+        &self,
+        symbol: Symbol,
+        side: Side,
+        amount: Amount,
+        _limit_price: Option<Num>,
+    ) {
+        self.fill(symbol, side, amount);
+    }
+
+    // every order fills right away, so there's never one open
+    async fn cancel_all_open_orders(&self) {}
+
+    async fn clock_now(&self) -> Clock {
+        clock_at(self.now())
+    }
+
+    async fn all_active_assets(&self, class: AssetClass) -> Vec<Symbol> {
+        let mut symbols = self
+            .bars
+            .keys()
+            .filter(|symbol| match symbol {
+                Symbol::Stock { .. } => class != AssetClass::Crypto,
+                Symbol::Crypto { .. } => class != AssetClass::Equity,
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        symbols.sort();
+        symbols
+    }
+
+    async fn all_latest_prices(
+        &self,
+        symbols: Vec<Symbol>,
+        _source: PriceSource,
+    ) -> HashMap<Symbol, Num> {
+        symbols
+            .into_iter()
+            .filter_map(|symbol| {
+                let price = self.price(&symbol)?;
+                Some((symbol, price))
+            })
+            .collect()
+    }
+
+    async fn snapshots(&self, symbols: Vec<Symbol>) -> HashMap<Symbol, Snapshot> {
+        symbols
+            .into_iter()
+            .filter_map(|symbol| {
+                let bar = self.visible_bars(&symbol).last()?.clone();
+                let snapshot = Snapshot {
+                    latest_trade: Some(LastTrade {
+                        timestamp: bar.time + self.bar_length,
+                        price: bar.close.clone(),
+                        size: bar.volume.clone(),
+                    }),
+                    latest_quote: None,
+                    minute_bar: Some(bar),
+                    daily_bar: None,
+                };
+                Some((symbol, snapshot))
+            })
+            .collect()
+    }
+
+    async fn latest_bars(
+        &self,
+        symbol: Symbol,
+        period: TimePeriod,
+        _feeds: &[Feed],
+        min_bars: usize,
+        bar_limit: Option<usize>,
+    ) -> Vec<bars::Bar> {
+        let bars = self.visible_bars(&symbol);
+
+        let since = self.now() - period.to_chrono();
+        let in_period = bars.len() - bars.partition_point(|bar| bar.time < since);
+        // reaches further back if the period alone doesn't have enough bars
+        let mut len = in_period.max(min_bars).min(bars.len());
+        if let Some(limit) = bar_limit {
+            len = len.min(limit);
+        }
+
+        bars[bars.len() - len..].to_vec()
+    }
+
+    async fn final_stats(&self) -> Stats {
+        let journal = self.account.take_journal();
+        let current_equity = self.current_equity();
+        let last_equity = std::mem::replace(
+            &mut *self.last_equity.lock().unwrap(),
+            current_equity.clone(),
+        );
+
+        Stats {
+            current_equity,
+            last_equity,
+            trades: journal.trades,
+            wins: journal.wins,
+            losses: journal.losses,
+            average_hold: journal.average_hold(),
+            hold_histogram: journal.hold_histogram(),
+            realized: journal.realized_by_symbol(),
+        }
+    }
+
+    async fn equity(&self) -> Num {
+        self.current_equity()
+    }
+
+    async fn buying_power(&self) -> Num {
+        self.cash()
+    }
+
+    async fn open(&self) {}
+
+    async fn close(&self) {}
+
+    fn account_data(&self) -> &AccountState {
+        &self.account
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::new_bar;
+
+    fn time(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc)
+    }
+
+    fn backend() -> BacktestBackend {
+        let bars = [(10, "2023-06-05T14:00:00Z"), (12, "2023-06-05T14:01:00Z")]
+            .into_iter()
+            .map(|(close, at)| {
+                let close = Num::from(close);
+                new_bar(time(at), &close, &close, &close, &close, &Num::from(100))
+            })
+            .collect();
+
+        BacktestBackend::new(
+            HashMap::from([(Symbol::from("AAPL"), bars)]),
+            chrono::Duration::minutes(1),
+            time("2023-06-05T14:00:30Z"),
+            Num::from(100),
+        )
+    }
+
+    #[tokio::test]
+    async fn bars_only_show_up_once_they_ended() {
+        let backend = backend();
+        let symbol = Symbol::from("AAPL");
+        assert!(backend.price(&symbol).is_none());
+
+        backend.advance_to(time("2023-06-05T14:01:00Z"));
+        assert_eq!(backend.price(&symbol), Some(Num::from(10)));
+
+        backend.advance_to(time("2023-06-05T14:05:00Z"));
+        assert_eq!(backend.price(&symbol), Some(Num::from(12)));
+    }
+
+    #[tokio::test]
+    async fn orders_fill_against_the_cash() {
+        let backend = backend();
+        let symbol = Symbol::from("AAPL");
+        backend.advance_to(time("2023-06-05T14:01:00Z"));
+
+        backend
+            .submit_order(symbol.clone(), Side::Buy, Amount::quantity(20), None)
+            .await;
+        assert_eq!(backend.cash(), Num::from(100));
+
+        backend
+            .submit_order(symbol.clone(), Side::Buy, Amount::quantity(5), None)
+            .await;
+        assert_eq!(backend.cash(), Num::from(50));
+
+        backend.advance_to(time("2023-06-05T14:05:00Z"));
+        assert_eq!(backend.equity().await, Num::from(110));
+
+        backend
+            .submit_order(symbol, Side::Sell, Amount::quantity(10), None)
+            .await;
+        assert_eq!(backend.cash(), Num::from(110));
+    }
+
+    #[test]
+    fn market_keeps_regular_hours() {
+        // a monday, in daylight saving time
+        let open = clock_at(time("2023-06-05T15:00:00Z"));
+        assert!(open.open);
+        assert_eq!(open.next_close, time("2023-06-05T20:00:00Z"));
+        assert_eq!(open.next_open, time("2023-06-06T13:30:00Z"));
+
+        // a friday after the close
+        let closed = clock_at(time("2023-06-09T21:00:00Z"));
+        assert!(!closed.open);
+        assert_eq!(closed.next_open, time("2023-06-12T13:30:00Z"));
+        assert_eq!(closed.next_close, time("2023-06-12T20:00:00Z"));
+    }
+}
@@ -1,11 +1,20 @@
-use std::collections::BTreeMap;
-
-use apca::data::v2::Feed;
+use std::{collections::BTreeMap, fmt::Debug, path::Path};
+
+use apca::{
+    api::v2::order,
+    data::v2::{
+        bars::{Adjustment, TimeFrame},
+        Feed,
+    },
+};
 use chrono::{DateTime, Utc};
 use http::Method;
+use http_endpoint::Endpoint;
 use num_decimal::Num;
 use serde::{Deserialize, Serialize, Serializer};
 
+use crate::bars::Bar;
+
 #[derive(Debug, thiserror::Error)]
 pub(crate) enum ConversionError {
     /// A variant used when a JSON conversion failed.
@@ -16,13 +25,37 @@ pub(crate) enum ConversionError {
     UrlEncode(#[from] serde_urlencoded::ser::Error),
 }
 
+// set to keep the last response body of some endpoints in `response.json`, which can be fed
+// back through the parsers with `--replay`
+const DUMP_RESPONSES_VAR: &str = "DUMP_RESPONSES";
+
+// a body that can't be dumped is only warned about, the parse goes on without it
+fn dump_response(body: &[u8]) {
+    if std::env::var_os(DUMP_RESPONSES_VAR).is_none() {
+        return;
+    }
+
+    let txt = match std::str::from_utf8(body) {
+        Ok(txt) => txt,
+        Err(why) => {
+            tracing::warn!("couldn't dump the response, it isn't UTF-8: {why}");
+            return;
+        }
+    };
+    if let Err(why) = std::fs::write("response.json", txt) {
+        tracing::warn!("couldn't dump the response: {why}");
+    }
+}
+
 const DATA_BASE_URL: &str = "https://data.alpaca.markets";
 
+/// A GET request to be made to the /v1beta3/crypto/us/trades endpoint.
 #[derive(Debug, serde::Serialize)]
 pub(crate) struct CryptoTradesReq {
-    #[serde(skip)]
+    /// The pair to retrieve the trades of, e.g. `BTC/USD`.
+    #[serde(rename = "symbols")]
     symbol: String,
-    /// The maximum number of trades to be returned for each symbol.
+    /// The maximum number of trades to be returned.
     ///
     /// It can be between 1 and 10000. Defaults to 1000 if the provided
     /// value is None.
@@ -34,18 +67,24 @@ pub(crate) struct CryptoTradesReq {
     /// Filter trades equal to or before this time.
     #[serde(rename = "end")]
     pub end: DateTime<Utc>,
-    /// The data feed to use.
-    ///
-    /// Defaults to [`IEX`][Feed::IEX] for free users and
-    /// [`SIP`][Feed::SIP] for users with an unlimited subscription.
-    #[serde(rename = "feed")]
-    pub feed: String,
     /// If provided we will pass a page token to continue where we left off.
     #[serde(rename = "page_token", skip_serializing_if = "Option::is_none")]
     pub page_token: Option<String>,
 }
 
-/// A market data trade as returned by the /v2/stocks/{symbol}/trades endpoint.
+impl CryptoTradesReq {
+    pub(crate) fn new(symbol: impl Into<String>, start: DateTime<Utc>, end: DateTime<Utc>) -> Self {
+        Self {
+            symbol: symbol.into(),
+            limit: None,
+            start,
+            end,
+            page_token: None,
+        }
+    }
+}
+
+/// A trade of a crypto pair as returned by the /v1beta3/crypto/us/trades endpoint.
 #[derive(Clone, Debug, Serialize, Deserialize, Eq, PartialEq)]
 #[non_exhaustive]
 pub(crate) struct CryptoTrade {
@@ -55,9 +94,9 @@ pub(crate) struct CryptoTrade {
     /// The price of the trade.
     #[serde(rename = "p")]
     pub price: Num,
-    /// The size of the trade.
+    /// The size of the trade, which can be a fraction of a coin.
     #[serde(rename = "s")]
-    pub size: usize,
+    pub size: Num,
 }
 
 /// A collection of trades as returned by the API. This is one page of trades.
@@ -65,10 +104,7 @@ pub(crate) struct CryptoTrade {
 #[non_exhaustive]
 pub(crate) struct CryptoTrades {
     /// The list of returned trades.
-    #[serde(deserialize_with = "vec_from_str")]
     pub trades: Vec<CryptoTrade>,
-    /// The symbol the trades correspond to.
-    pub symbol: String,
     /// The token to provide to a request to get the next page of trades for this request.
     pub next_page_token: Option<String>,
 }
@@ -92,14 +128,130 @@ http_endpoint::EndpointDef! {
         Some(DATA_BASE_URL.into())
     }
 
-    fn path(input: &Self::Input) -> http_endpoint::Str {
-        format!("/v2/stocks/{}/trades", input.symbol).into()
+    fn path(_: &Self::Input) -> http_endpoint::Str {
+        "/v1beta3/crypto/us/trades".into()
     }
 
     fn parse(body: &[u8]) -> Result<Self::Output, Self::ConversionError> {
-        let txt = std::str::from_utf8(body).unwrap();
-        std::fs::write("response.json", txt).unwrap();
-        serde_json::from_slice::<Self::Output>(body).map_err(Self::ConversionError::from)
+        // the trades are keyed by pair, and only the one pair is ever asked for
+        #[derive(Deserialize)]
+        struct Response {
+            #[serde(default)]
+            trades: BTreeMap<String, Vec<CryptoTrade>>,
+            next_page_token: Option<String>,
+        }
+
+        dump_response(body);
+        serde_json::from_slice::<Response>(body)
+            .map(|response| CryptoTrades {
+                trades: response.trades.into_values().flatten().collect(),
+                next_page_token: response.next_page_token,
+            })
+            .map_err(Self::ConversionError::from)
+    }
+
+    fn parse_err(body: &[u8]) -> Result<Self::ApiError, Vec<u8>> {
+        serde_json::from_slice::<Self::ApiError>(body).map_err(|_| body.to_vec())
+    }
+
+    fn query(input: &Self::Input) -> Result<Option<http_endpoint::Str>, Self::ConversionError> {
+        Ok(Some(serde_urlencoded::to_string(input)?.into()))
+    }
+}
+
+/// A GET request to be made to the /v1beta3/crypto/us/bars endpoint.
+#[derive(Debug, serde::Serialize)]
+pub(crate) struct CryptoBarsReq {
+    /// The pair to retrieve the bars of, e.g. `BTC/USD`.
+    #[serde(rename = "symbols")]
+    symbol: String,
+    /// The time frame of a single bar.
+    #[serde(rename = "timeframe")]
+    pub timeframe: BarTimeFrame,
+    /// The maximum number of bars to be returned.
+    ///
+    /// It can be between 1 and 10000. Defaults to 1000 if the provided
+    /// value is None.
+    #[serde(rename = "limit")]
+    pub limit: Option<usize>,
+    /// Filter bars equal to or after this time.
+    #[serde(rename = "start")]
+    pub start: DateTime<Utc>,
+    /// Filter bars equal to or before this time.
+    #[serde(rename = "end")]
+    pub end: DateTime<Utc>,
+    /// If provided we will pass a page token to continue where we left off.
+    #[serde(rename = "page_token", skip_serializing_if = "Option::is_none")]
+    pub page_token: Option<String>,
+}
+
+impl CryptoBarsReq {
+    pub(crate) fn new(
+        symbol: impl Into<String>,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        timeframe: BarTimeFrame,
+    ) -> Self {
+        Self {
+            symbol: symbol.into(),
+            timeframe,
+            limit: None,
+            start,
+            end,
+            page_token: None,
+        }
+    }
+}
+
+/// A collection of bars of a crypto pair as returned by the API. This is one page of bars.
+#[derive(Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub(crate) struct CryptoBars {
+    /// The list of returned bars.
+    pub bars: Vec<Bar>,
+    /// The token to provide to a request to get the next page of bars for this request.
+    pub next_page_token: Option<String>,
+}
+
+http_endpoint::EndpointDef! {
+    pub(crate) GetCryptoBars(CryptoBarsReq),
+
+    Ok => CryptoBars, [
+        /* 200 */ OK,
+    ],
+    Err => GetCryptoBarsErr, [
+        NOT_FOUND => NotFound,
+        BAD_REQUEST => InvalidInput,
+        FORBIDDEN => NotPermitted,
+        TOO_MANY_REQUESTS => RateLimitExceeded,
+    ],
+    ConversionErr => ConversionError,
+    ApiErr => apca::ApiError,
+
+    fn base_url() -> Option<http_endpoint::Str> {
+        Some(DATA_BASE_URL.into())
+    }
+
+    fn path(_: &Self::Input) -> http_endpoint::Str {
+        "/v1beta3/crypto/us/bars".into()
+    }
+
+    fn parse(body: &[u8]) -> Result<Self::Output, Self::ConversionError> {
+        // the bars are keyed by pair, and only the one pair is ever asked for
+        #[derive(Deserialize)]
+        struct Response {
+            #[serde(default)]
+            bars: BTreeMap<String, Vec<Bar>>,
+            next_page_token: Option<String>,
+        }
+
+        dump_response(body);
+        serde_json::from_slice::<Response>(body)
+            .map(|response| CryptoBars {
+                bars: response.bars.into_values().flatten().collect(),
+                next_page_token: response.next_page_token,
+            })
+            .map_err(Self::ConversionError::from)
     }
 
     fn parse_err(body: &[u8]) -> Result<Self::ApiError, Vec<u8>> {
@@ -146,8 +298,49 @@ http_endpoint::EndpointDef! {
     }
 
     fn parse(body: &[u8]) -> Result<Self::Output, Self::ConversionError> {
-        let txt = std::str::from_utf8(body).unwrap();
-        std::fs::write("response.json", txt).unwrap();
+        dump_response(body);
+        serde_json::from_slice::<Self::Output>(body).map_err(Self::ConversionError::from)
+    }
+
+    fn parse_err(body: &[u8]) -> Result<Self::ApiError, Vec<u8>> {
+        serde_json::from_slice::<Self::ApiError>(body).map_err(|_| body.to_vec())
+    }
+}
+
+/// The order size limits of an asset, as returned by the /v2/assets/{symbol} endpoint. Only crypto
+/// assets have them.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+#[non_exhaustive]
+pub(crate) struct AssetLots {
+    /// The smallest quantity that can be ordered.
+    #[serde(default)]
+    pub min_order_size: Option<Num>,
+    /// The step every ordered quantity has to be a multiple of.
+    #[serde(default)]
+    pub min_trade_increment: Option<Num>,
+}
+
+http_endpoint::EndpointDef! {
+    pub(crate) GetAssetLots(String),
+
+    Ok => AssetLots, [
+        /* 200 */ OK,
+    ],
+    Err => GetAssetLotsErr, [
+        NOT_FOUND => NotFound,
+        FORBIDDEN => NotPermitted,
+        TOO_MANY_REQUESTS => RateLimitExceeded,
+    ],
+    ConversionErr => ConversionError,
+    ApiErr => apca::ApiError,
+
+    fn path(input: &Self::Input) -> http_endpoint::Str {
+        // crypto pairs have a slash in them
+        format!("/v2/assets/{}", input.replace('/', "%2F")).into()
+    }
+
+    fn parse(body: &[u8]) -> Result<Self::Output, Self::ConversionError> {
+        dump_response(body);
         serde_json::from_slice::<Self::Output>(body).map_err(Self::ConversionError::from)
     }
 
@@ -156,6 +349,115 @@ http_endpoint::EndpointDef! {
     }
 }
 
+http_endpoint::EndpointDef! {
+    pub(crate) ClosePosition(String),
+
+    Ok => order::Order, [
+        /* 200 */ OK,
+    ],
+    Err => ClosePositionErr, [
+        NOT_FOUND => NotFound,
+        FORBIDDEN => NotPermitted,
+        TOO_MANY_REQUESTS => RateLimitExceeded,
+    ],
+    ConversionErr => ConversionError,
+    ApiErr => apca::ApiError,
+
+    #[inline]
+    fn method() -> Method {
+        Method::DELETE
+    }
+
+    fn path(input: &Self::Input) -> http_endpoint::Str {
+        // positions go by the pair without its slash, e.g. `BTCUSD`
+        format!("/v2/positions/{}", input.replace('/', "")).into()
+    }
+
+    fn parse(body: &[u8]) -> Result<Self::Output, Self::ConversionError> {
+        dump_response(body);
+        serde_json::from_slice::<Self::Output>(body).map_err(Self::ConversionError::from)
+    }
+
+    fn parse_err(body: &[u8]) -> Result<Self::ApiError, Vec<u8>> {
+        serde_json::from_slice::<Self::ApiError>(body).map_err(|_| body.to_vec())
+    }
+}
+
+/// A GET request to be made to the /v1beta3/crypto/us/latest/trades endpoint.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct LatestCryptoTradesReq {
+    /// The pairs to retrieve the last trade for, e.g. `BTC/USD`.
+    #[serde(rename = "symbols", serialize_with = "string_slice_to_str")]
+    pub symbols: Vec<String>,
+}
+
+impl LatestCryptoTradesReq {
+    pub fn new<I, S>(symbols: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            symbols: symbols.into_iter().map(S::into).collect(),
+        }
+    }
+}
+
+/// The latest trade of a crypto pair. Unlike stocks, the size can be fractional, so it's left out.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct LatestCryptoTrade {
+    /// Time of the trade.
+    #[serde(rename = "t")]
+    pub timestamp: DateTime<Utc>,
+    /// The price of the trade.
+    #[serde(rename = "p")]
+    pub price: Num,
+}
+
+http_endpoint::EndpointDef! {
+    pub(crate) GetLatestCryptoTrades(LatestCryptoTradesReq),
+
+    Ok => Vec<(String, LatestCryptoTrade)>, [
+        /* 200 */ OK,
+    ],
+    Err => GetLatestCryptoTradesErr, [
+        NOT_FOUND => NotFound,
+        BAD_REQUEST => InvalidInput,
+        FORBIDDEN => NotPermitted,
+        TOO_MANY_REQUESTS => RateLimitExceeded,
+    ],
+    ConversionErr => ConversionError,
+    ApiErr => apca::ApiError,
+
+    fn base_url() -> Option<http_endpoint::Str> {
+        Some(DATA_BASE_URL.into())
+    }
+
+    fn path(_: &Self::Input) -> http_endpoint::Str {
+        "/v1beta3/crypto/us/latest/trades".into()
+    }
+
+    fn query(input: &Self::Input) -> Result<Option<http_endpoint::Str>, Self::ConversionError> {
+        Ok(Some(serde_urlencoded::to_string(input)?.into()))
+    }
+
+    fn parse(body: &[u8]) -> Result<Self::Output, Self::ConversionError> {
+        #[derive(Deserialize)]
+        struct Response {
+            trades: BTreeMap<String, LatestCryptoTrade>,
+        }
+
+        serde_json::from_slice::<Response>(body)
+            .map(|response| response.trades.into_iter().collect())
+            .map_err(Self::ConversionError::from)
+    }
+
+    fn parse_err(body: &[u8]) -> Result<Self::ApiError, Vec<u8>> {
+        serde_json::from_slice::<Self::ApiError>(body).map_err(|_| body.to_vec())
+    }
+}
+
 /// A GET request to be made to the /v2/stocks/quotes/latest endpoint.
 #[derive(Clone, Debug, Eq, PartialEq, Serialize)]
 pub struct LastTradesReq {
@@ -203,7 +505,7 @@ pub struct LastTrade {
     pub price: Num,
     /// The size of the trade.
     #[serde(rename = "s")]
-    pub size: usize,
+    pub size: Num,
 }
 
 http_endpoint::EndpointDef! {
@@ -264,14 +566,416 @@ http_endpoint::EndpointDef! {
     }
 }
 
-/// Deserialize a `Vec` from a string that could contain a `null`.
-pub(crate) fn vec_from_str<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
-where
-    D: serde::Deserializer<'de>,
-    T: serde::Deserialize<'de>,
-{
-    let vec = <Option<Vec<T>> as serde::Deserialize>::deserialize(deserializer)?;
-    Ok(vec.unwrap_or_default())
+/// A GET request to be made to the /v2/stocks/quotes/latest endpoint.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct LastQuotesReq {
+    /// The symbols to retrieve the last quote for.
+    #[serde(rename = "symbols", serialize_with = "string_slice_to_str")]
+    pub symbols: Vec<String>,
+    /// The data feed to use.
+    #[serde(rename = "feed")]
+    pub feed: Option<Feed>,
+}
+
+/// A helper for initializing [`LastQuotesReq`] objects.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[allow(missing_copy_implementations)]
+pub struct LastQuotesReqInit {
+    /// See `LastQuotesReq::feed`.
+    pub feed: Option<Feed>,
+    #[doc(hidden)]
+    pub _non_exhaustive: (),
+}
+
+impl LastQuotesReqInit {
+    /// Create a [`LastQuotesReq`] from a `LastQuotesReqInit`.
+    #[inline]
+    pub fn init<I, S>(self, symbols: I) -> LastQuotesReq
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        LastQuotesReq {
+            symbols: symbols.into_iter().map(S::into).collect(),
+            feed: self.feed,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct LastQuote {
+    /// Time of the quote.
+    #[serde(rename = "t")]
+    pub timestamp: DateTime<Utc>,
+    /// The best price someone is asking for.
+    #[serde(rename = "ap")]
+    pub ask_price: Num,
+    /// The best price someone is bidding.
+    #[serde(rename = "bp")]
+    pub bid_price: Num,
+}
+
+impl LastQuote {
+    /// The price halfway between the bid and the ask, if there is both a bid and an ask.
+    pub fn mid_price(&self) -> Option<Num> {
+        if self.ask_price.is_zero() || self.bid_price.is_zero() {
+            return None;
+        }
+
+        Some((self.ask_price.clone() + self.bid_price.clone()) / Num::from(2))
+    }
+
+    /// How far apart the bid and the ask are, in percent of the mid price.
+    pub fn spread_pct(&self) -> Option<Num> {
+        let mid_price = self.mid_price()?;
+
+        Some((self.ask_price.clone() - self.bid_price.clone()) / mid_price * Num::from(100))
+    }
+}
+
+http_endpoint::EndpointDef! {
+    pub(crate) GetLastQuotes(LastQuotesReq),
+
+    Ok => Vec<(String, LastQuote)>, [
+        /* 200 */ OK,
+    ],
+    Err => GetLatestQuotesErr, [
+        NOT_FOUND => NotFound,
+        BAD_REQUEST => InvalidInput,
+        FORBIDDEN => NotPermitted,
+        TOO_MANY_REQUESTS => RateLimitExceeded,
+    ],
+    ConversionErr => ConversionError,
+    ApiErr => apca::ApiError,
+
+    fn base_url() -> Option<http_endpoint::Str> {
+        Some(DATA_BASE_URL.into())
+    }
+
+    fn path(_: &Self::Input) -> http_endpoint::Str {
+        "/v2/stocks/quotes/latest".into()
+    }
+
+    fn query(input: &Self::Input) -> Result<Option<http_endpoint::Str>, Self::ConversionError> {
+        Ok(Some(serde_urlencoded::to_string(input)?.into()))
+    }
+
+    fn parse(body: &[u8]) -> Result<Self::Output, Self::ConversionError> {
+        #[derive(Deserialize)]
+        struct Response {
+            quotes: BTreeMap<String, LastQuote>,
+        }
+
+        serde_json::from_slice::<Response>(body)
+            .map(|response| response.quotes.into_iter().collect())
+            .map_err(Self::ConversionError::from)
+    }
+
+    fn parse_err(body: &[u8]) -> Result<Self::ApiError, Vec<u8>> {
+        serde_json::from_slice::<Self::ApiError>(body).map_err(|_| body.to_vec())
+    }
+}
+
+/// A GET request to be made to the /v2/stocks/snapshots endpoint.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct SnapshotsReq {
+    /// The symbols to retrieve snapshots for.
+    #[serde(rename = "symbols", serialize_with = "string_slice_to_str")]
+    pub symbols: Vec<String>,
+    /// The data feed to use.
+    #[serde(rename = "feed")]
+    pub feed: Option<Feed>,
+}
+
+/// A helper for initializing [`SnapshotsReq`] objects.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[allow(missing_copy_implementations)]
+pub struct SnapshotsReqInit {
+    /// See `SnapshotsReq::feed`.
+    pub feed: Option<Feed>,
+    #[doc(hidden)]
+    pub _non_exhaustive: (),
+}
+
+impl SnapshotsReqInit {
+    /// Create a [`SnapshotsReq`] from a `SnapshotsReqInit`.
+    #[inline]
+    pub fn init<I, S>(self, symbols: I) -> SnapshotsReq
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        SnapshotsReq {
+            symbols: symbols.into_iter().map(S::into).collect(),
+            feed: self.feed,
+        }
+    }
+}
+
+/// The latest market data of a single symbol. Any of it may be missing if the symbol hasn't
+/// traded recently.
+#[derive(Clone, Debug, Deserialize)]
+#[non_exhaustive]
+pub struct Snapshot {
+    /// The most recent trade.
+    #[serde(rename = "latestTrade")]
+    pub latest_trade: Option<LastTrade>,
+    /// The most recent quote.
+    #[serde(rename = "latestQuote")]
+    pub latest_quote: Option<LastQuote>,
+    /// The most recent minute bar.
+    #[serde(rename = "minuteBar")]
+    pub minute_bar: Option<Bar>,
+    /// The bar of the current trading day so far.
+    #[serde(rename = "dailyBar")]
+    pub daily_bar: Option<Bar>,
+}
+
+http_endpoint::EndpointDef! {
+    pub(crate) GetSnapshots(SnapshotsReq),
+
+    Ok => Vec<(String, Snapshot)>, [
+        /* 200 */ OK,
+    ],
+    Err => GetSnapshotsErr, [
+        NOT_FOUND => NotFound,
+        BAD_REQUEST => InvalidInput,
+        FORBIDDEN => NotPermitted,
+        TOO_MANY_REQUESTS => RateLimitExceeded,
+    ],
+    ConversionErr => ConversionError,
+    ApiErr => apca::ApiError,
+
+    fn base_url() -> Option<http_endpoint::Str> {
+        Some(DATA_BASE_URL.into())
+    }
+
+    fn path(_: &Self::Input) -> http_endpoint::Str {
+        "/v2/stocks/snapshots".into()
+    }
+
+    fn query(input: &Self::Input) -> Result<Option<http_endpoint::Str>, Self::ConversionError> {
+        Ok(Some(serde_urlencoded::to_string(input)?.into()))
+    }
+
+    fn parse(body: &[u8]) -> Result<Self::Output, Self::ConversionError> {
+        // unlike the other endpoints, the snapshots aren't wrapped in an object.
+        // symbols without any data come back as `null`
+        serde_json::from_slice::<BTreeMap<String, Option<Snapshot>>>(body)
+            .map(|snapshots| {
+                snapshots
+                    .into_iter()
+                    .filter_map(|(symbol, snapshot)| Some((symbol, snapshot?)))
+                    .collect()
+            })
+            .map_err(Self::ConversionError::from)
+    }
+
+    fn parse_err(body: &[u8]) -> Result<Self::ApiError, Vec<u8>> {
+        serde_json::from_slice::<Self::ApiError>(body).map_err(|_| body.to_vec())
+    }
+}
+
+/// The length of a bar as a multiple of a minute, an hour or a day, e.g. `5Min` or `2Hour`. Alpaca
+/// aggregates up to 59 minutes, 23 hours or a single day into a bar.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) struct BarTimeFrame {
+    pub(crate) unit: TimeFrame,
+    pub(crate) multiple: u32,
+}
+
+impl BarTimeFrame {
+    pub(crate) fn length(self) -> chrono::Duration {
+        let unit = match self.unit {
+            TimeFrame::OneMinute => chrono::Duration::minutes(1),
+            TimeFrame::OneHour => chrono::Duration::hours(1),
+            TimeFrame::OneDay => chrono::Duration::days(1),
+        };
+        unit * self.multiple as i32
+    }
+}
+
+impl Serialize for BarTimeFrame {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let unit = match self.unit {
+            TimeFrame::OneMinute => "Min",
+            TimeFrame::OneHour => "Hour",
+            TimeFrame::OneDay => "Day",
+        };
+        serializer.collect_str(&format_args!("{}{unit}", self.multiple))
+    }
+}
+
+/// A GET request to be made to the /v2/stocks/bars endpoint.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct MultiBarsReq {
+    /// The symbols to retrieve bars for.
+    #[serde(rename = "symbols", serialize_with = "string_slice_to_str")]
+    pub symbols: Vec<String>,
+    /// The time frame of a single bar.
+    #[serde(rename = "timeframe")]
+    pub timeframe: BarTimeFrame,
+    /// Filter bars equal to or after this time.
+    #[serde(rename = "start")]
+    pub start: DateTime<Utc>,
+    /// Filter bars equal to or before this time.
+    #[serde(rename = "end")]
+    pub end: DateTime<Utc>,
+    /// The maximum number of bars to be returned across all symbols.
+    #[serde(rename = "limit", skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>,
+    /// The corporate actions, like splits, that past prices are adjusted for.
+    #[serde(rename = "adjustment", skip_serializing_if = "Option::is_none")]
+    pub adjustment: Option<Adjustment>,
+    /// The data feed to use.
+    #[serde(rename = "feed")]
+    pub feed: Option<Feed>,
+    /// If provided we will pass a page token to continue where we left off.
+    #[serde(rename = "page_token", skip_serializing_if = "Option::is_none")]
+    pub page_token: Option<String>,
+}
+
+/// A helper for initializing [`MultiBarsReq`] objects.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct MultiBarsReqInit {
+    /// See `MultiBarsReq::limit`.
+    pub limit: Option<usize>,
+    /// See `MultiBarsReq::adjustment`.
+    pub adjustment: Option<Adjustment>,
+    /// See `MultiBarsReq::feed`.
+    pub feed: Option<Feed>,
+    /// See `MultiBarsReq::page_token`.
+    pub page_token: Option<String>,
+    #[doc(hidden)]
+    pub _non_exhaustive: (),
+}
+
+impl MultiBarsReqInit {
+    /// Create a [`MultiBarsReq`] from a `MultiBarsReqInit`.
+    #[inline]
+    pub fn init<I, S>(
+        self,
+        symbols: I,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        timeframe: BarTimeFrame,
+    ) -> MultiBarsReq
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        MultiBarsReq {
+            symbols: symbols.into_iter().map(S::into).collect(),
+            timeframe,
+            start,
+            end,
+            limit: self.limit,
+            adjustment: self.adjustment,
+            feed: self.feed,
+            page_token: self.page_token,
+        }
+    }
+}
+
+/// The bars of several symbols as returned by the API. This is one page of bars.
+#[derive(Debug, Deserialize)]
+#[non_exhaustive]
+pub(crate) struct MultiBars {
+    /// A mapping from symbols to their bars. Only symbols with bars on this page are included.
+    pub bars: Option<BTreeMap<String, Vec<Bar>>>,
+    /// The token to provide to a request to get the next page of bars for this request.
+    pub next_page_token: Option<String>,
+}
+
+http_endpoint::EndpointDef! {
+    pub(crate) GetMultiBars(MultiBarsReq),
+
+    Ok => MultiBars, [
+        /* 200 */ OK,
+    ],
+    Err => GetMultiBarsErr, [
+        NOT_FOUND => NotFound,
+        BAD_REQUEST => InvalidInput,
+        FORBIDDEN => NotPermitted,
+        TOO_MANY_REQUESTS => RateLimitExceeded,
+    ],
+    ConversionErr => ConversionError,
+    ApiErr => apca::ApiError,
+
+    fn base_url() -> Option<http_endpoint::Str> {
+        Some(DATA_BASE_URL.into())
+    }
+
+    fn path(_: &Self::Input) -> http_endpoint::Str {
+        "/v2/stocks/bars".into()
+    }
+
+    fn query(input: &Self::Input) -> Result<Option<http_endpoint::Str>, Self::ConversionError> {
+        Ok(Some(serde_urlencoded::to_string(input)?.into()))
+    }
+
+    fn parse(body: &[u8]) -> Result<Self::Output, Self::ConversionError> {
+        serde_json::from_slice::<Self::Output>(body).map_err(Self::ConversionError::from)
+    }
+
+    fn parse_err(body: &[u8]) -> Result<Self::ApiError, Vec<u8>> {
+        serde_json::from_slice::<Self::ApiError>(body).map_err(|_| body.to_vec())
+    }
+}
+
+/// Runs a saved response body through the parser of the given endpoint, or through every parser
+/// if no endpoint is given, and prints what came out.
+pub(crate) fn replay(path: &Path, endpoint: Option<&str>) -> Result<(), String> {
+    fn print<E>(name: &str, body: &[u8])
+    where
+        E: Endpoint<ConversionError = ConversionError>,
+        E::Output: Debug,
+    {
+        match E::parse(body) {
+            Ok(output) => println!("{name}: {output:#?}"),
+            Err(why) => println!("{name}: {why} ({why:?})"),
+        }
+    }
+
+    let body = std::fs::read(path).map_err(|why| format!("couldn't read {path:?}: {why}"))?;
+
+    let parsers: &[(&str, fn(&str, &[u8]))] = &[
+        ("last-trades", print::<GetLastTrades>),
+        ("last-quotes", print::<GetLastQuotes>),
+        ("latest-crypto-trades", print::<GetLatestCryptoTrades>),
+        ("crypto-trades", print::<GetCryptoTrades>),
+        ("crypto-bars", print::<GetCryptoBars>),
+        ("cancel-all-orders", print::<CancelAllOrders>),
+        ("asset-lots", print::<GetAssetLots>),
+        ("close-position", print::<ClosePosition>),
+        ("multi-bars", print::<GetMultiBars>),
+        ("snapshots", print::<GetSnapshots>),
+    ];
+
+    let mut matched = false;
+    for (name, parser) in parsers {
+        if endpoint.map_or(true, |endpoint| endpoint == *name) {
+            parser(name, &body);
+            matched = true;
+        }
+    }
+
+    if !matched {
+        return Err(format!(
+            "unknown endpoint, expected one of: {}",
+            parsers
+                .iter()
+                .map(|(name, _)| *name)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+    }
+
+    Ok(())
 }
 
 /// Serialize a slice into a string of textual representations of the
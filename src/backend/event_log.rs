@@ -0,0 +1,59 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::Path,
+};
+
+use apca::api::v2::order::{self, Side};
+use chrono::{DateTime, Utc};
+use num_decimal::Num;
+use serde::Serialize;
+
+/// An append only log of every order update, one JSON object per line, so that a day of trading
+/// can be pieced back together afterwards.
+pub(super) struct EventLog {
+    file: File,
+}
+
+#[derive(Serialize)]
+struct OrderEvent<'a> {
+    /// when the update arrived
+    time: DateTime<Utc>,
+    id: &'a order::Id,
+    /// starts with the tag of the instance that submitted the order, if it had one
+    client_order_id: &'a str,
+    symbol: &'a str,
+    side: Side,
+    status: order::Status,
+    filled_quantity: &'a Num,
+    average_fill_price: Option<&'a Num>,
+}
+
+impl EventLog {
+    pub(super) fn open(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+
+        Ok(Self { file })
+    }
+
+    pub(super) fn record(&mut self, order: &order::Order) {
+        let event = OrderEvent {
+            time: Utc::now(),
+            id: &order.id,
+            client_order_id: &order.client_order_id,
+            symbol: &order.symbol,
+            side: order.side,
+            status: order.status,
+            filled_quantity: &order.filled_quantity,
+            average_fill_price: order.average_fill_price.as_ref(),
+        };
+
+        // losing the log shouldn't stop the trading
+        let result = serde_json::to_string(&event)
+            .map_err(io::Error::from)
+            .and_then(|line| writeln!(self.file, "{line}"));
+        if let Err(why) = result {
+            tracing::error!("couldn't write to the event log: {why}");
+        }
+    }
+}
@@ -1,4 +1,13 @@
-use std::{collections::HashMap, sync::Arc, time::Instant};
+use std::{
+    collections::{HashMap, VecDeque},
+    io,
+    path::Path,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, RwLock,
+    },
+    time::{Duration, Instant},
+};
 
 use apca::{
     api::v2::{
@@ -7,38 +16,265 @@ use apca::{
         assets,
         clock::{self, Clock},
         order::{self, Amount, Side, TimeInForce},
-        positions,
+        orders, positions,
     },
-    data::v2::{bars, Feed},
+    data::v2::Feed,
 };
 use async_trait::async_trait;
-use chrono::Utc;
+use chrono::{DateTime, TimeZone, Utc};
+use dashmap::DashMap;
+use itertools::Itertools;
 use num_decimal::Num;
 use tokio::sync::Mutex;
 
-use crate::{AccountState, Position, Symbol, TimePeriod};
+use crate::{
+    bars,
+    history::new_bar,
+    sizing,
+    stats::{format_quantity, num_to_f64},
+    AccountState, Position, Symbol, TimePeriod,
+};
 
-use super::{endpoints, watcher::LiveOrderWatcher, Backend, Stats};
+use super::{
+    endpoints, event_log::EventLog, prices::LivePriceStream, rate_limit::RateLimiter,
+    watcher::LiveOrderWatcher, AssetClass, Backend, BarAdjustment, BarTimeFrame, LastQuote,
+    PriceSource, Snapshot, Stats,
+};
 
 pub(super) struct LiveInner {
     pub(super) client: apca::Client,
     pub(super) account: AccountState,
+    pub(super) event_log: std::sync::Mutex<Option<EventLog>>,
+    /// what the client order ids of this instance's orders start with. `None` unless enabled with
+    /// `tag_orders`, in which case every order on the account is treated as ours
+    order_tag: std::sync::Mutex<Option<String>>,
+    /// how many orders were submitted so far, to keep the tagged client order ids unique
+    submitted_orders: AtomicU64,
+    /// the last trade price of every symbol on the price stream, while it's up
+    pub(super) streamed_prices: DashMap<Symbol, Num>,
+    rate_limiter: RateLimiter,
+    /// log every request, how it went and how long it took
+    http_trace: bool,
 }
 
+impl LiveInner {
+    // whether the order was submitted by this instance, as far as can be told
+    pub(super) fn is_own_order(&self, order: &order::Order) -> bool {
+        match &*self.order_tag.lock().unwrap() {
+            Some(tag) => order.client_order_id.starts_with(&format!("{tag}-")),
+            None => true,
+        }
+    }
+
+    // a client order id that tells which instance the order came from, or `None` to let alpaca
+    // make one up
+    fn client_order_id(&self) -> Option<String> {
+        let order_tag = self.order_tag.lock().unwrap();
+        let tag = order_tag.as_ref()?;
+        let count = self.submitted_orders.fetch_add(1, Ordering::Relaxed);
+
+        Some(format!("{tag}-{}-{count}", Utc::now().timestamp_millis()))
+    }
+
+    // a limit order if there's a limit price, and a market order otherwise
+    fn order_request(
+        &self,
+        symbol: &Symbol,
+        side: Side,
+        amount: Amount,
+        limit_price: Option<Num>,
+    ) -> order::OrderReq {
+        order::OrderReqInit {
+            type_: match limit_price {
+                Some(_) => order::Type::Limit,
+                None => order::Type::Market,
+            },
+            limit_price,
+            client_order_id: self.client_order_id(),
+            time_in_force: match symbol {
+                Symbol::Crypto { .. } => TimeInForce::UntilCanceled,
+                Symbol::Stock { .. } => TimeInForce::Day,
+            },
+            ..Default::default()
+        }
+        .init(symbol.request_symbol(), side, amount)
+    }
+
+    // polls the order until it's done or the timeout is up, whichever comes first
+    async fn wait_for(&self, mut order: order::Order, timeout: Duration) -> order::Order {
+        let started = Instant::now();
+
+        while !order.status.is_terminal() && started.elapsed() < timeout {
+            tokio::time::sleep(ORDER_POLL_INTERVAL).await;
+
+            order = self.reread_order(order).await;
+        }
+
+        order
+    }
+
+    // the order as it is now. if it can't be read, it's kept as it was to be polled again
+    async fn reread_order(&self, order: order::Order) -> order::Order {
+        match self.issue::<order::Get>(&order.id).await {
+            Ok(order) => order,
+            Err(why) => {
+                tracing::warn!("Couldn't check on order {}: {why}", order.id.0);
+                order
+            }
+        }
+    }
+
+    // a cancel fails when the order filled or ended on its own in the meantime, which whoever
+    // cancelled it finds out by reading the order again
+    async fn cancel_order(&self, id: order::Id) {
+        match self.issue::<order::Delete>(&id).await {
+            Ok(_) => tracing::debug!("Cancelled order {}", id.0),
+            Err(why) => tracing::debug!("Couldn't cancel order {}, it's likely done: {why}", id.0),
+        }
+    }
+
+    // every request should go through here, so that they all count towards the rate limit
+    async fn issue<E>(&self, input: &E::Input) -> Result<E::Output, apca::RequestError<E::Error>>
+    where
+        E: http_endpoint::Endpoint,
+    {
+        self.rate_limiter.acquire().await;
+
+        if !self.http_trace {
+            return self.client.issue::<E>(input).await;
+        }
+
+        let started = Instant::now();
+        let result = self.client.issue::<E>(input).await;
+
+        let query = match E::query(input) {
+            Ok(Some(query)) => format!("?{query}"),
+            _ => String::new(),
+        };
+        let outcome = match &result {
+            Ok(_) => "ok".to_string(),
+            Err(why) => why.to_string(),
+        };
+        tracing::debug!(
+            "{} {}{query} -> {outcome} in {:.0?}",
+            E::method(),
+            E::path(input),
+            started.elapsed()
+        );
+
+        result
+    }
+}
+
+// set to log every request to alpaca, e.g. to see the urls behind a failing request
+const HTTP_TRACE_VAR: &str = "WSW_HTTP_TRACE";
+
+// keeps the urls of batched requests from getting too long
+const MAX_SYMBOLS_PER_REQUEST: usize = 200;
+
+// the most bars alpaca will return in a single page
+const MAX_BARS_PER_PAGE: usize = 10000;
+
+// how many times the time range of a bars request gets pushed back when there aren't enough bars
+const MAX_BAR_EXTENSIONS: usize = 4;
+
+// a safety net against runaway trading, well above what a normal scan submits
+const DEFAULT_MAX_ORDERS_PER_MINUTE: usize = 30;
+
+// how often an order is checked on while waiting for it to fill
+const ORDER_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+// assets can be halted during the day, so their tradable status shouldn't be trusted for longer
+const ASSET_REFRESH_INTERVAL: Duration = Duration::from_secs(60 * 15);
+
 pub(crate) struct LiveBackend {
     inner: Arc<LiveInner>,
     watcher: Mutex<LiveOrderWatcher>,
+    /// `None` unless enabled with `stream_prices`
+    price_stream: Mutex<Option<LivePriceStream>>,
+    /// swapped out whole when the assets are refreshed, so they're never seen half filled
+    tradable: RwLock<HashMap<Symbol, bool>>,
+    fractionable: RwLock<HashMap<Symbol, bool>>,
+    /// held for the whole refresh, so that only one goes out at a time
+    assets_refreshed: Mutex<Option<Instant>>,
+    /// when each buy of the last minute was submitted
+    recent_orders: std::sync::Mutex<VecDeque<Instant>>,
+    max_orders_per_minute: usize,
+    adjustment: BarAdjustment,
+    /// the order size limits of the cryptos that were ordered so far
+    lots: DashMap<Symbol, endpoints::AssetLots>,
+    /// the bars of earlier scans, so that only the newer ones have to be fetched. `None` unless
+    /// enabled with `cache_bars`
+    bar_cache: Option<DashMap<BarCacheKey, CachedBars>>,
+}
+
+// the symbol along with the window and bar length of the period, the bars of a longer period
+// can't stand in for a shorter one
+type BarCacheKey = (Symbol, chrono::Duration, chrono::Duration);
+
+fn bar_cache_key(symbol: &Symbol, period: TimePeriod) -> BarCacheKey {
+    (symbol.clone(), period.to_chrono(), period.bar_length())
+}
+
+struct CachedBars {
+    /// the feed the bars came from, newer ones have to come from the same one
+    feed: Feed,
+    bars: Vec<bars::Bar>,
+}
+
+/// What an order of this instance filled, for picking its positions back up after a restart.
+struct OwnFill {
+    symbol: Symbol,
+    side: Side,
+    quantity: Num,
+    /// the average price of the fills
+    price: Num,
+    time: DateTime<Utc>,
+}
+
+// the positions that the fills leave open, replayed oldest first. a position is never more than
+// what's on the account, some of it may have been sold by hand
+fn positions_from_fills(
+    fills: Vec<OwnFill>,
+    on_account: &DashMap<Symbol, Position>,
+) -> Vec<(Symbol, Position)> {
+    let replayed = AccountState::new(DashMap::new());
+    for fill in fills {
+        replayed.apply_fill(
+            fill.symbol,
+            fill.side,
+            &fill.quantity,
+            Some(&fill.price),
+            order::Status::Filled,
+            fill.time,
+        );
+    }
+
+    replayed
+        .sorted_positions()
+        .into_iter()
+        .filter_map(|(symbol, mut pos)| {
+            let held = on_account.get(&symbol)?.owned.clone();
+            pos.owned = pos.owned.min(held);
+            (!pos.owned.is_zero()).then_some((symbol, pos))
+        })
+        .collect()
 }
 
 impl LiveBackend {
     pub(crate) async fn new() -> Self {
-        let api_info = apca::ApiInfo::from_env().unwrap();
+        Self::from_api_info(apca::ApiInfo::from_env().unwrap()).await
+    }
+
+    pub(crate) async fn from_api_info(api_info: apca::ApiInfo) -> Self {
         let client = apca::Client::new(api_info);
+        let rate_limiter = RateLimiter::new();
 
-        let now = Instant::now();
+        let now = Utc::now();
 
-        let account = AccountState {
-            positions: client
+        rate_limiter.acquire().await;
+        let account = AccountState::new(
+            client
                 .issue::<positions::Get>(&())
                 .await
                 .unwrap()
@@ -48,91 +284,669 @@ impl LiveBackend {
                         position.symbol.into(),
                         Position {
                             owned: position.quantity,
-                            buy_in_price: position.current_price.unwrap_or_default(),
-                            timestamp: now,
-                            order_in_progress: false,
+                            buy_in_price: position.average_entry_price,
+                            ..Position::new(now)
                         },
                     )
                 })
                 .collect(),
-        };
+        );
 
         tracing::debug!("account: {}", account);
 
-        let inner = Arc::new(LiveInner { client, account });
+        let inner = Arc::new(LiveInner {
+            client,
+            account,
+            event_log: Default::default(),
+            order_tag: Default::default(),
+            submitted_orders: AtomicU64::new(0),
+            streamed_prices: DashMap::new(),
+            rate_limiter,
+            http_trace: std::env::var_os(HTTP_TRACE_VAR).is_some(),
+        });
 
         Self {
             watcher: LiveOrderWatcher::new(inner.clone()).await.into(),
+            price_stream: Mutex::new(None),
             inner,
+            tradable: RwLock::new(HashMap::new()),
+            fractionable: RwLock::new(HashMap::new()),
+            assets_refreshed: Mutex::new(None),
+            recent_orders: Default::default(),
+            max_orders_per_minute: DEFAULT_MAX_ORDERS_PER_MINUTE,
+            adjustment: BarAdjustment::default(),
+            lots: DashMap::new(),
+            bar_cache: None,
         }
     }
-}
 
-#[async_trait]
-impl Backend for LiveBackend {
-    async fn submit_order(&self, symbol: Symbol, side: Side, amount: Amount) {
-        let amount_str = match &amount {
-            Amount::Quantity { quantity } => format!("{}", quantity),
-            Amount::Notional { notional } => format!("${}", notional),
-        };
+    /// Starts appending every order update to the file at `path`.
+    pub(crate) fn log_events_to(&self, path: &Path) -> io::Result<()> {
+        *self.inner.event_log.lock().unwrap() = Some(EventLog::open(path)?);
 
-        let request = order::OrderReqInit {
-            time_in_force: match symbol {
-                Symbol::Crypto { .. } => TimeInForce::UntilCanceled,
-                Symbol::Stock { .. } => TimeInForce::Day,
-            },
-            ..Default::default()
+        Ok(())
+    }
+
+    /// Starts the client order id of every order with the tag, and leaves the orders without it
+    /// alone. Several instances can share an account this way, as long as each has its own tag.
+    /// Only the positions opened by tagged fills are traded, the rest of the account belongs to
+    /// whoever opened it. The positions of earlier runs with the same tag are picked back up from
+    /// their orders.
+    pub(crate) async fn tag_orders(&self, tag: String) {
+        *self.inner.order_tag.lock().unwrap() = Some(tag);
+
+        let fills = self.own_fills().await;
+        let positions = &self.inner.account.positions;
+        let own = positions_from_fills(fills, positions);
+
+        tracing::info!(
+            "Picking up {} positions of earlier runs, leaving the other {} on the account alone",
+            own.len(),
+            positions.len() - own.len()
+        );
+        positions.clear();
+        for (symbol, pos) in own {
+            positions.insert(symbol, pos);
         }
-        .init(symbol.clone().ticker(), side, amount);
+    }
 
-        self.inner
-            .client
-            .issue::<order::Post>(&request)
-            .await
-            .unwrap();
+    // every fill of this instance's orders that alpaca still lists, oldest first
+    async fn own_fills(&self) -> Vec<OwnFill> {
+        let request = orders::OrdersReq {
+            status: orders::Status::All,
+            // as far back as a single request goes
+            limit: Some(500),
+            ..Default::default()
+        };
 
-        match side {
-            Side::Buy => tracing::info!("Bought {amount_str} of {symbol}"),
-            Side::Sell => tracing::info!("Sold {amount_str} of {symbol}"),
+        let orders = match self.inner.issue::<orders::Get>(&request).await {
+            Ok(orders) => orders,
+            Err(why) => {
+                tracing::warn!(
+                    "Couldn't get the earlier orders, starting without positions: {why}"
+                );
+                return Vec::new();
+            }
+        };
+
+        orders
+            .into_iter()
+            .filter(|order| self.inner.is_own_order(order) && !order.filled_quantity.is_zero())
+            .map(|order| OwnFill {
+                symbol: order.symbol.into(),
+                side: order.side,
+                quantity: order.filled_quantity,
+                price: order.average_fill_price.unwrap_or_default(),
+                time: order.filled_at.unwrap_or(order.created_at),
+            })
+            .sorted_by_key(|fill| fill.time)
+            .collect()
+    }
+
+    /// Streams the prices of the stocks among the symbols instead of asking for them on every scan,
+    /// replacing the symbols that were streamed before.
+    pub(crate) async fn stream_prices(&self, symbols: Vec<Symbol>) {
+        let mut price_stream = self.price_stream.lock().await;
+        if let Some(mut old) = price_stream.take() {
+            old.close(&self.inner);
         }
+
+        *price_stream = Some(LivePriceStream::new(self.inner.clone(), symbols));
     }
 
-    async fn cancel_all_open_orders(&self) {
-        let cancelled_orders = self
-            .inner
-            .client
-            .issue::<endpoints::CancelAllOrders>(&())
-            .await
-            .unwrap();
+    /// Caps how many buys can be submitted within any minute. Buys over the cap are dropped.
+    pub(crate) fn limit_orders_per_minute(&mut self, max_orders_per_minute: usize) {
+        self.max_orders_per_minute = max_orders_per_minute;
+    }
 
-        if !cancelled_orders.0.is_empty() {
-            tracing::debug!("Cancelled {} orders", cancelled_orders.0.len());
+    /// Sets which corporate actions the bars of stocks are adjusted for.
+    pub(crate) fn adjust_bars_for(&mut self, adjustment: BarAdjustment) {
+        self.adjustment = adjustment;
+    }
+
+    /// Keeps the bars of every scan around, so that the next scan only fetches the bars since.
+    pub(crate) fn cache_bars(&mut self) {
+        self.bar_cache = Some(DashMap::new());
+    }
+
+    /// Drops the cached bars of the symbols that aren't watched anymore.
+    pub(crate) fn forget_bars_except(&self, watch: &[Symbol]) {
+        if let Some(cache) = &self.bar_cache {
+            cache.retain(|(symbol, _, _), _| watch.contains(symbol));
         }
     }
 
-    async fn clock_now(&self) -> Clock {
-        self.inner.client.issue::<clock::Get>(&()).await.unwrap()
+    // whether another buy fits within the last minute, counting it if it does
+    fn take_order_slot(&self) -> bool {
+        let mut recent_orders = self.recent_orders.lock().unwrap();
+
+        while recent_orders.front().map_or(false, |submitted| {
+            submitted.elapsed() > Duration::from_secs(60)
+        }) {
+            recent_orders.pop_front();
+        }
+
+        if recent_orders.len() >= self.max_orders_per_minute {
+            return false;
+        }
+
+        recent_orders.push_back(Instant::now());
+        true
     }
 
-    async fn all_active_assets(&self) -> Vec<Symbol> {
-        self.inner
-            .client
-            .issue::<assets::Get>(
+    async fn active_assets(&self, class: AssetClass) -> Result<Vec<asset::Asset>, String> {
+        let requests = class.classes().iter().map(|class| {
+            self.inner.issue::<assets::Get>(
                 &assets::AssetsReqInit {
                     status: asset::Status::Active,
+                    class: *class,
                     ..Default::default()
                 }
                 .init(),
             )
-            .await
-            .unwrap()
+        });
+
+        let mut assets = Vec::new();
+        for result in futures::future::join_all(requests).await {
+            assets.extend(result.map_err(|why| why.to_string())?);
+        }
+
+        Ok(assets)
+    }
+
+    // all the bars of the symbols within the time range, following every page
+    async fn multi_bars(
+        &self,
+        symbols: &[Symbol],
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        timeframe: BarTimeFrame,
+        feed: Feed,
+        bar_limit: Option<usize>,
+    ) -> Result<HashMap<Symbol, Vec<bars::Bar>>, String> {
+        let mut results = HashMap::<Symbol, Vec<bars::Bar>>::new();
+
+        for symbols in symbols.chunks(MAX_SYMBOLS_PER_REQUEST) {
+            let mut page_token = None;
+
+            loop {
+                let request = endpoints::MultiBarsReqInit {
+                    // the limit is shared by all of the symbols
+                    limit: Some(bar_limit.map_or(MAX_BARS_PER_PAGE, |bar_limit| {
+                        (bar_limit * symbols.len()).min(MAX_BARS_PER_PAGE)
+                    })),
+                    adjustment: Some(self.adjustment.to_apca()),
+                    feed: Some(feed),
+                    page_token,
+                    ..Default::default()
+                }
+                .init(
+                    symbols.iter().map(|symbol| symbol.ticker().to_string()),
+                    from,
+                    to,
+                    timeframe,
+                );
+
+                let data = self
+                    .inner
+                    .issue::<endpoints::GetMultiBars>(&request)
+                    .await
+                    .map_err(|why| why.to_string())?;
+
+                for (symbol, bars) in data.bars.unwrap_or_default() {
+                    results.entry(symbol.into()).or_default().extend(bars);
+                }
+
+                page_token = data.next_page_token;
+                if page_token.is_none() {
+                    break;
+                }
+            }
+        }
+
+        Ok(results)
+    }
+
+    // the latest bars of the stocks from a single feed, looking further back for the ones that
+    // have less than `min_bars`
+    async fn latest_stock_bars(
+        &self,
+        symbols: Vec<Symbol>,
+        period: TimePeriod,
+        feed: Feed,
+        min_bars: usize,
+        bar_limit: Option<usize>,
+    ) -> Result<HashMap<Symbol, Vec<bars::Bar>>, String> {
+        let (mut from, mut to) = bars_window(period, feed, bar_limit);
+
+        let mut results = symbols
+            .iter()
+            .map(|symbol| (symbol.clone(), Vec::new()))
+            .collect::<HashMap<_, _>>();
+
+        let mut missing = symbols;
+
+        // weekends and holidays have no bars, so keep looking further back until there are enough
+        for _ in 0..=MAX_BAR_EXTENSIONS {
+            let older = self
+                .multi_bars(&missing, from, to, period.bar_timeframe(), feed, bar_limit)
+                .await?;
+            for (symbol, older) in older {
+                prepend_bars(results.entry(symbol).or_default(), older);
+            }
+
+            missing.retain(|symbol| results[symbol].len() < min_bars);
+            if missing.is_empty() {
+                break;
+            }
+
+            to = from;
+            from = from.checked_sub_signed(period.to_chrono()).unwrap();
+        }
+
+        if let Some(bar_limit) = bar_limit {
+            for bars in results.values_mut() {
+                keep_latest_bars(bars, bar_limit);
+            }
+        }
+
+        Ok(results)
+    }
+
+    // the latest bars of the symbol from a single feed, looking further back if there are less
+    // than `min_bars`
+    async fn latest_bars_from(
+        &self,
+        symbol: &Symbol,
+        period: TimePeriod,
+        feed: Feed,
+        min_bars: usize,
+        bar_limit: Option<usize>,
+    ) -> Result<Vec<bars::Bar>, String> {
+        let (mut from, mut to) = bars_window(period, feed, bar_limit);
+
+        let mut results = Vec::new();
+
+        // weekends and holidays have no bars, so keep looking further back until there are enough
+        for _ in 0..=MAX_BAR_EXTENSIONS {
+            let older = match symbol {
+                Symbol::Crypto { .. } => {
+                    self.crypto_bars(symbol, from, to, period.bar_timeframe())
+                        .await?
+                }
+                // apca's single symbol request only knows about bars of a single unit
+                Symbol::Stock { .. } => self
+                    .multi_bars(
+                        std::slice::from_ref(symbol),
+                        from,
+                        to,
+                        period.bar_timeframe(),
+                        feed,
+                        bar_limit,
+                    )
+                    .await?
+                    .remove(symbol)
+                    .unwrap_or_default(),
+            };
+
+            prepend_bars(&mut results, older);
+            if results.len() >= min_bars {
+                break;
+            }
+
+            to = from;
+            from = from.checked_sub_signed(period.to_chrono()).unwrap();
+        }
+
+        if let Some(bar_limit) = bar_limit {
+            keep_latest_bars(&mut results, bar_limit);
+        }
+
+        Ok(results)
+    }
+
+    // the bars of the symbols since `from`, stocks and cryptos alike
+    async fn bars_since(
+        &self,
+        symbols: &[Symbol],
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        timeframe: BarTimeFrame,
+        feed: Feed,
+    ) -> Result<HashMap<Symbol, Vec<bars::Bar>>, String> {
+        let (cryptos, stocks): (Vec<_>, Vec<_>) = symbols
+            .iter()
+            .cloned()
+            .partition(|symbol| matches!(symbol, Symbol::Crypto { .. }));
+
+        let mut results = if stocks.is_empty() {
+            HashMap::new()
+        } else {
+            self.multi_bars(&stocks, from, to, timeframe, feed, None)
+                .await?
+        };
+
+        for symbol in cryptos {
+            let bars = self.crypto_bars(&symbol, from, to, timeframe).await?;
+            results.insert(symbol, bars);
+        }
+
+        Ok(results)
+    }
+
+    // tops up the cached bars of the symbols with the ones since the last scan. the symbols that
+    // aren't cached, or whose bars couldn't be topped up, are returned to be fetched in full
+    async fn update_cached_bars(
+        &self,
+        cache: &DashMap<BarCacheKey, CachedBars>,
+        symbols: Vec<Symbol>,
+        period: TimePeriod,
+        feeds: &[Feed],
+        min_bars: usize,
+        bar_limit: Option<usize>,
+    ) -> (HashMap<Symbol, Vec<bars::Bar>>, Vec<Symbol>) {
+        let (cached, mut uncached): (Vec<_>, Vec<_>) = symbols.into_iter().partition(|symbol| {
+            cache
+                .get(&bar_cache_key(symbol, period))
+                .map_or(false, |cached| cached.bars.len() >= min_bars)
+        });
+
+        let mut results = HashMap::new();
+
+        for feed in feeds {
+            let symbols = cached
+                .iter()
+                .filter(|symbol| cache.get(&bar_cache_key(symbol, period)).unwrap().feed == *feed)
+                .cloned()
+                .collect::<Vec<_>>();
+            if symbols.is_empty() {
+                continue;
+            }
+
+            // the last cached bar was probably still being built, so it's fetched again
+            let since = symbols
+                .iter()
+                .map(|symbol| {
+                    let cached = cache.get(&bar_cache_key(symbol, period)).unwrap();
+                    cached.bars.last().unwrap().time
+                })
+                .min()
+                .unwrap();
+            let (from, to) = bars_window(period, *feed, bar_limit);
+
+            let newer = match self
+                .bars_since(&symbols, since, to, period.bar_timeframe(), *feed)
+                .await
+            {
+                Ok(newer) => newer,
+                Err(why) => {
+                    tracing::warn!("Couldn't update the cached bars from {feed:?}: {why}");
+                    uncached.extend(symbols);
+                    continue;
+                }
+            };
+
+            for symbol in symbols {
+                let mut cached = cache.get_mut(&bar_cache_key(&symbol, period)).unwrap();
+                append_bars(
+                    &mut cached.bars,
+                    newer.get(&symbol).cloned().unwrap_or_default(),
+                );
+                trim_bars(&mut cached.bars, from, min_bars, bar_limit);
+                results.insert(symbol, cached.bars.clone());
+            }
+        }
+
+        (results, uncached)
+    }
+
+    // the bars of the pair within the time range. if alpaca's bars can't be had, they're built
+    // out of every trade instead
+    async fn crypto_bars(
+        &self,
+        symbol: &Symbol,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        timeframe: BarTimeFrame,
+    ) -> Result<Vec<bars::Bar>, String> {
+        match self.crypto_bars_from_api(symbol, from, to, timeframe).await {
+            Ok(bars) => Ok(bars),
+            Err(why) => {
+                tracing::warn!(
+                    "Couldn't get the bars of {symbol}, building them out of its trades: {why}"
+                );
+                self.crypto_bars_from_trades(symbol, from, to, timeframe)
+                    .await
+            }
+        }
+    }
+
+    async fn crypto_bars_from_api(
+        &self,
+        symbol: &Symbol,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        timeframe: BarTimeFrame,
+    ) -> Result<Vec<bars::Bar>, String> {
+        let mut bars = Vec::new();
+        let mut request =
+            endpoints::CryptoBarsReq::new(symbol.request_symbol(), from, to, timeframe);
+
+        loop {
+            let data = self
+                .inner
+                .issue::<endpoints::GetCryptoBars>(&request)
+                .await
+                .map_err(|why| why.to_string())?;
+
+            bars.extend(data.bars);
+
+            request.page_token = data.next_page_token;
+            if request.page_token.is_none() {
+                break;
+            }
+        }
+
+        Ok(bars)
+    }
+
+    // bars built out of every trade of the symbol within the time range
+    async fn crypto_bars_from_trades(
+        &self,
+        symbol: &Symbol,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        timeframe: BarTimeFrame,
+    ) -> Result<Vec<bars::Bar>, String> {
+        let mut trades = Vec::new();
+        let mut request = endpoints::CryptoTradesReq::new(symbol.request_symbol(), from, to);
+
+        loop {
+            let data = self
+                .inner
+                .issue::<endpoints::GetCryptoTrades>(&request)
+                .await
+                .map_err(|why| why.to_string())?;
+
+            trades.extend(data.trades);
+
+            request.page_token = data.next_page_token;
+            if request.page_token.is_none() {
+                break;
+            }
+        }
+
+        Ok(trades_to_bars(&trades, timeframe))
+    }
+
+    /// Gets every bar of the symbols between `from` and `to`, however many pages that takes.
+    pub(crate) async fn historical_bars(
+        &self,
+        symbols: Vec<Symbol>,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        timeframe: BarTimeFrame,
+        feed: Feed,
+    ) -> Result<HashMap<Symbol, Vec<bars::Bar>>, String> {
+        // the multi bars endpoint only knows about stocks
+        let (cryptos, stocks): (Vec<_>, Vec<_>) = symbols
             .into_iter()
-            .filter(|asset| asset.tradable && asset.exchange != Exchange::Otc)
-            .map(|asset| asset.symbol.into())
-            .collect()
+            .partition(|symbol| matches!(symbol, Symbol::Crypto { .. }));
+
+        let mut results = self
+            .multi_bars(&stocks, from, to, timeframe, feed, None)
+            .await?;
+
+        for symbol in cryptos {
+            let bars = self.crypto_bars(&symbol, from, to, timeframe).await?;
+            results.insert(symbol, bars);
+        }
+
+        Ok(results)
     }
 
-    async fn all_latest_prices(&self, symbols: Vec<Symbol>) -> HashMap<Symbol, Num> {
+    // submits the order, unless the symbol can't be traded right now. it's a limit order if there's
+    // a limit price, and a market order otherwise
+    async fn post_order(
+        &self,
+        symbol: Symbol,
+        side: Side,
+        amount: Amount,
+        limit_price: Option<Num>,
+    ) -> Option<order::Order> {
+        if !self.is_tradable(&symbol).await {
+            tracing::warn!("Skipping order for {symbol}, it isn't tradable right now");
+            return None;
+        }
+
+        // something is probably looping if this many buys go out at once.
+        // sells are never held back, closing positions has to keep working
+        if side == Side::Buy && !self.take_order_slot() {
+            tracing::warn!(
+                "Dropping order for {symbol}, already submitted {} buys in the last minute",
+                self.max_orders_per_minute
+            );
+            return None;
+        }
+
+        // selling all of a crypto position by quantity can leave dust behind that's too small to
+        // ever be ordered, closing the position sells every last bit of it at market
+        if side == Side::Sell && self.sells_whole_crypto_position(&symbol, &amount) {
+            return match self
+                .inner
+                .issue::<endpoints::ClosePosition>(&symbol.request_symbol())
+                .await
+            {
+                Ok(order) => {
+                    tracing::info!("Closed the position in {symbol}");
+                    Some(order)
+                }
+                Err(why) => {
+                    tracing::error!("Couldn't close the position in {symbol}: {why}");
+                    None
+                }
+            };
+        }
+
+        let amount = match (&symbol, amount) {
+            (Symbol::Crypto { .. }, Amount::Quantity { quantity }) => {
+                match self.fit_to_lot(&symbol, quantity).await {
+                    Some(quantity) => Amount::quantity(quantity),
+                    None => {
+                        tracing::warn!(
+                            "Skipping order for {symbol}, it's below the minimum order size"
+                        );
+                        return None;
+                    }
+                }
+            }
+            (_, amount) => amount,
+        };
+
+        let amount_str = match &amount {
+            Amount::Quantity { quantity } => format_quantity(quantity),
+            Amount::Notional { notional } => format!("${:.2}", num_to_f64(notional)),
+        };
+
+        let request = self.inner.order_request(&symbol, side, amount, limit_price);
+
+        let order = match self.inner.issue::<order::Post>(&request).await {
+            Ok(order) => order,
+            Err(why) => {
+                tracing::error!("The order for {amount_str} of {symbol} was rejected: {why}");
+                return None;
+            }
+        };
+
+        match side {
+            Side::Buy => tracing::info!("Bought {amount_str} of {symbol}"),
+            Side::Sell => tracing::info!("Sold {amount_str} of {symbol}"),
+        }
+
+        Some(order)
+    }
+
+    // the close of a position can't be tagged, so it's only for when every order on the account is
+    // treated as ours
+    fn sells_whole_crypto_position(&self, symbol: &Symbol, amount: &Amount) -> bool {
+        let (Symbol::Crypto { .. }, Amount::Quantity { quantity }) = (symbol, amount) else {
+            return false;
+        };
+
+        self.inner.order_tag.lock().unwrap().is_none()
+            && self
+                .inner
+                .account
+                .positions
+                .get(symbol)
+                .map_or(false, |pos| *quantity >= pos.owned)
+    }
+
+    // rounds a crypto quantity to what the exchange accepts, or `None` if it's too small to order
+    async fn fit_to_lot(&self, symbol: &Symbol, quantity: Num) -> Option<Num> {
+        if !self.lots.contains_key(symbol) {
+            match self
+                .inner
+                .issue::<endpoints::GetAssetLots>(&symbol.request_symbol())
+                .await
+            {
+                Ok(lots) => {
+                    self.lots.insert(symbol.clone(), lots);
+                }
+                Err(why) => {
+                    // better to let the order through than to never sell
+                    tracing::warn!("couldn't get the order size limits of {symbol}: {why}");
+                    return Some(quantity);
+                }
+            }
+        }
+
+        let lots = self.lots.get(symbol)?;
+        sizing::fit_to_lot(
+            quantity,
+            lots.min_order_size.as_ref(),
+            lots.min_trade_increment.as_ref(),
+        )
+    }
+
+    async fn latest_stock_prices(
+        &self,
+        symbols: Vec<Symbol>,
+        source: PriceSource,
+    ) -> HashMap<Symbol, Num> {
+        if symbols.is_empty() {
+            return HashMap::new();
+        }
+
+        if source == PriceSource::QuoteMid {
+            return self
+                .all_latest_quotes(symbols)
+                .await
+                .into_iter()
+                .filter_map(|(symbol, quote)| Some((symbol, quote.mid_price()?)))
+                .collect();
+        }
+
         let request = endpoints::LastTradesReqInit {
             // feed: Some(Feed::IEX),
             ..Default::default()
@@ -145,7 +959,6 @@ impl Backend for LiveBackend {
 
         let data = self
             .inner
-            .client
             .issue::<endpoints::GetLastTrades>(&request)
             .await
             .unwrap();
@@ -155,54 +968,716 @@ impl Backend for LiveBackend {
             .collect()
     }
 
-    async fn latest_bars(&self, symbol: Symbol, period: TimePeriod, feed: Feed) -> Vec<bars::Bar> {
-        let to = Utc::now()
-            .checked_sub_signed(chrono::Duration::minutes(match feed {
-                Feed::IEX => 1,
-                Feed::SIP => 5,
-                _ => 0,
-            }))
+    async fn latest_crypto_prices(&self, symbols: Vec<Symbol>) -> HashMap<Symbol, Num> {
+        if symbols.is_empty() {
+            return HashMap::new();
+        }
+
+        let request = endpoints::LatestCryptoTradesReq::new(
+            symbols.iter().map(|symbol| symbol.request_symbol()),
+        );
+
+        let data = self
+            .inner
+            .issue::<endpoints::GetLatestCryptoTrades>(&request)
+            .await
             .unwrap();
-        let from = to.checked_sub_signed(period.to_chrono()).unwrap();
 
-        let request = bars::BarsReqInit {
-            feed: Some(feed),
+        data.into_iter()
+            .map(|(symbol, trade)| (symbol.into(), trade.price))
+            .collect()
+    }
+
+    async fn open_orders(&self) -> Vec<order::Order> {
+        let request = orders::OrdersReq {
+            status: orders::Status::Open,
             ..Default::default()
+        };
+
+        self.inner
+            .issue::<orders::Get>(&request)
+            .await
+            .unwrap()
+            .into_iter()
+            .filter(|order| self.inner.is_own_order(order))
+            .collect()
+    }
+
+    async fn is_tradable(&self, symbol: &Symbol) -> bool {
+        self.refresh_stale_assets().await;
+
+        // a missing symbol is no longer active
+        self.tradable
+            .read()
+            .unwrap()
+            .get(symbol)
+            .map_or(false, |tradable| *tradable)
+    }
+
+    // the assets from before are kept if they can't be refreshed, and the refresh is tried again
+    // the next time around
+    async fn refresh_stale_assets(&self) {
+        let mut refreshed = self.assets_refreshed.lock().await;
+        let stale = refreshed.map_or(true, |refreshed| {
+            refreshed.elapsed() > ASSET_REFRESH_INTERVAL
+        });
+        if !stale {
+            return;
+        }
+
+        let assets = match self.active_assets(AssetClass::Both).await {
+            Ok(assets) => assets,
+            Err(why) => {
+                tracing::warn!("Couldn't refresh the assets, keeping the ones from before: {why}");
+                return;
+            }
+        };
+
+        let tradable = assets
+            .iter()
+            .map(|asset| (asset.symbol.clone().into(), asset.tradable))
+            .collect();
+        let fractionable = assets
+            .iter()
+            .map(|asset| (asset.symbol.clone().into(), asset.fractionable))
+            .collect();
+        *self.tradable.write().unwrap() = tradable;
+        *self.fractionable.write().unwrap() = fractionable;
+        *refreshed = Some(Instant::now());
+    }
+}
+
+#[async_trait]
+impl Backend for LiveBackend {
+    async fn submit_order(
+        &self,
+        symbol: Symbol,
+        side: Side,
+        amount: Amount,
+        limit_price: Option<Num>,
+    ) {
+        self.post_order(symbol, side, amount, limit_price).await;
+    }
+
+    async fn submit_order_and_wait(
+        &self,
+        symbol: Symbol,
+        side: Side,
+        amount: Amount,
+        limit_price: Option<Num>,
+        timeout: Duration,
+    ) -> Option<order::Order> {
+        let order = self.post_order(symbol, side, amount, limit_price).await?;
+
+        Some(self.inner.wait_for(order, timeout).await)
+    }
+
+    async fn submit_limit_order_or_market(
+        &self,
+        symbol: Symbol,
+        side: Side,
+        quantity: Num,
+        limit_price: Num,
+        timeout: Duration,
+    ) {
+        let Some(order) = self
+            .post_order(
+                symbol.clone(),
+                side,
+                Amount::quantity(quantity.clone()),
+                Some(limit_price),
+            )
+            .await
+        else {
+            return;
+        };
+
+        // the scan shouldn't be held up by the wait, the other symbols' stops still need checking
+        let lots = self.lots.get(&symbol).map(|lots| lots.clone());
+        tokio::task::spawn(limit_or_market(
+            self.inner.clone(),
+            symbol,
+            side,
+            quantity,
+            order,
+            timeout,
+            lots,
+        ));
+    }
+
+    async fn cancel_all_open_orders(&self) {
+        // the orders of the other instances on the account have to be left alone
+        if self.inner.order_tag.lock().unwrap().is_some() {
+            let orders = self.open_orders().await;
+            for order in &orders {
+                self.cancel_order(order.id).await;
+            }
+
+            if !orders.is_empty() {
+                tracing::debug!("Cancelled {} orders", orders.len());
+            }
+            return;
+        }
+
+        let cancelled_orders = self
+            .inner
+            .issue::<endpoints::CancelAllOrders>(&())
+            .await
+            .unwrap();
+
+        if !cancelled_orders.0.is_empty() {
+            tracing::debug!("Cancelled {} orders", cancelled_orders.0.len());
         }
-        .init(symbol.ticker(), from, to, period.timeframe);
+    }
+
+    async fn cancel_order(&self, id: order::Id) {
+        self.inner.cancel_order(id).await;
+    }
+
+    async fn open_order_symbols(&self) -> Vec<Symbol> {
+        self.open_orders()
+            .await
+            .into_iter()
+            .map(|order| order.symbol.into())
+            .collect()
+    }
+
+    async fn open_orders_for(&self, symbol: &Symbol) -> Vec<order::Id> {
+        self.open_orders()
+            .await
+            .into_iter()
+            .filter(|order| Symbol::from(order.symbol.as_str()) == *symbol)
+            .map(|order| order.id)
+            .collect()
+    }
+
+    async fn clock_now(&self) -> Clock {
+        self.inner.issue::<clock::Get>(&()).await.unwrap()
+    }
+
+    async fn all_active_assets(&self, class: AssetClass) -> Vec<Symbol> {
+        let assets = match self.active_assets(class).await {
+            Ok(assets) => assets,
+            Err(why) => {
+                tracing::warn!("Couldn't get the active assets: {why}");
+                return Vec::new();
+            }
+        };
+
+        assets
+            .into_iter()
+            .filter(|asset| asset.tradable && asset.exchange != Exchange::Otc)
+            .map(|asset| asset.symbol.into())
+            .collect()
+    }
+
+    async fn is_fractionable(&self, symbol: &Symbol) -> bool {
+        self.refresh_stale_assets().await;
+
+        // whole shares are always safe to order
+        self.fractionable
+            .read()
+            .unwrap()
+            .get(symbol)
+            .map_or(false, |fractionable| *fractionable)
+    }
+
+    async fn all_latest_prices(
+        &self,
+        symbols: Vec<Symbol>,
+        source: PriceSource,
+    ) -> HashMap<Symbol, Num> {
+        let mut prices = HashMap::new();
+
+        // the streamed prices are of the last trades, they can't stand in for the quotes
+        let symbols = match source {
+            PriceSource::LastTrade => symbols
+                .into_iter()
+                .filter(|symbol| match self.inner.streamed_prices.get(symbol) {
+                    Some(price) => {
+                        prices.insert(symbol.clone(), price.clone());
+                        false
+                    }
+                    None => true,
+                })
+                .collect(),
+            PriceSource::QuoteMid => symbols,
+        };
+
+        // crypto has its own endpoint, and always goes by the last trade
+        let (cryptos, stocks): (Vec<_>, Vec<_>) = symbols
+            .into_iter()
+            .partition(|symbol| matches!(symbol, Symbol::Crypto { .. }));
+
+        let (stock_prices, crypto_prices) = futures::join!(
+            self.latest_stock_prices(stocks, source),
+            self.latest_crypto_prices(cryptos)
+        );
+        prices.extend(stock_prices);
+        prices.extend(crypto_prices);
+
+        prices
+    }
+
+    async fn all_latest_quotes(&self, symbols: Vec<Symbol>) -> HashMap<Symbol, LastQuote> {
+        let request = endpoints::LastQuotesReqInit::default().init(
+            symbols
+                .into_iter()
+                .map(|symbol| symbol.ticker().to_string()),
+        );
 
         let data = self
             .inner
-            .client
-            .issue::<bars::Get>(&request)
+            .issue::<endpoints::GetLastQuotes>(&request)
             .await
             .unwrap();
-        if data.next_page_token.is_some() {
-            tracing::error!("more pages than expected");
+
+        data.into_iter()
+            .map(|(symbol, quote)| (symbol.into(), quote))
+            .collect()
+    }
+
+    async fn snapshots(&self, symbols: Vec<Symbol>) -> HashMap<Symbol, Snapshot> {
+        let requests = symbols.chunks(MAX_SYMBOLS_PER_REQUEST).map(|symbols| {
+            let request = endpoints::SnapshotsReqInit::default()
+                .init(symbols.iter().map(|symbol| symbol.ticker().to_string()));
+
+            async move {
+                self.inner
+                    .issue::<endpoints::GetSnapshots>(&request)
+                    .await
+                    .unwrap()
+            }
+        });
+
+        futures::future::join_all(requests)
+            .await
+            .into_iter()
+            .flatten()
+            .map(|(symbol, snapshot)| (symbol.into(), snapshot))
+            .collect()
+    }
+
+    async fn all_latest_bars(
+        &self,
+        symbols: Vec<Symbol>,
+        period: TimePeriod,
+        feeds: &[Feed],
+        min_bars: usize,
+        bar_limit: Option<usize>,
+    ) -> HashMap<Symbol, Vec<bars::Bar>> {
+        let (cached, symbols) = match &self.bar_cache {
+            Some(cache) => {
+                self.update_cached_bars(cache, symbols, period, feeds, min_bars, bar_limit)
+                    .await
+            }
+            None => (HashMap::new(), symbols),
+        };
+
+        // the multi bars endpoint only knows about stocks
+        let (cryptos, symbols): (Vec<_>, Vec<_>) = symbols
+            .into_iter()
+            .partition(|symbol| matches!(symbol, Symbol::Crypto { .. }));
+
+        let cryptos = futures::future::join_all(cryptos.into_iter().map(|symbol| async move {
+            let bars = self
+                .latest_bars(symbol.clone(), period, feeds, min_bars, bar_limit)
+                .await;
+            (symbol, bars)
+        }))
+        .await;
+
+        let mut results = symbols
+            .iter()
+            .map(|symbol| (symbol.clone(), Vec::new()))
+            .collect::<HashMap<_, _>>();
+
+        let mut missing = symbols;
+
+        // a feed the account can't use, or that has nothing on some symbols, falls through to the
+        // next one
+        for feed in feeds {
+            if missing.is_empty() {
+                break;
+            }
+
+            match self
+                .latest_stock_bars(missing.clone(), period, *feed, min_bars, bar_limit)
+                .await
+            {
+                Ok(bars) => {
+                    let bars = bars
+                        .into_iter()
+                        .filter(|(_, bars)| !bars.is_empty())
+                        .collect::<Vec<_>>();
+                    if !bars.is_empty() {
+                        tracing::debug!("Got the bars of {} symbols from {feed:?}", bars.len());
+                    }
+                    if let Some(cache) = &self.bar_cache {
+                        for (symbol, bars) in &bars {
+                            cache.insert(
+                                bar_cache_key(symbol, period),
+                                CachedBars {
+                                    feed: *feed,
+                                    bars: bars.clone(),
+                                },
+                            );
+                        }
+                    }
+                    results.extend(bars);
+                }
+                Err(why) => tracing::warn!("Couldn't get bars from {feed:?}: {why}"),
+            }
+
+            missing.retain(|symbol| results[symbol].is_empty());
+        }
+
+        if let (Some(cache), Some(feed)) = (&self.bar_cache, feeds.first()) {
+            // crypto bars don't come from a feed, any of them will do
+            for (symbol, bars) in cryptos.iter().filter(|(_, bars)| !bars.is_empty()) {
+                cache.insert(
+                    bar_cache_key(symbol, period),
+                    CachedBars {
+                        feed: *feed,
+                        bars: bars.clone(),
+                    },
+                );
+            }
+        }
+
+        results.extend(cryptos);
+        results.extend(cached);
+
+        results
+    }
+
+    async fn latest_bars(
+        &self,
+        symbol: Symbol,
+        period: TimePeriod,
+        feeds: &[Feed],
+        min_bars: usize,
+        bar_limit: Option<usize>,
+    ) -> Vec<bars::Bar> {
+        // a feed the account can't use, or that has nothing on the symbol, falls through to the
+        // next one
+        for feed in feeds {
+            match self
+                .latest_bars_from(&symbol, period, *feed, min_bars, bar_limit)
+                .await
+            {
+                Ok(bars) if !bars.is_empty() => {
+                    tracing::debug!("Got the bars of {symbol} from {feed:?}");
+                    return bars;
+                }
+                Ok(_) => {}
+                Err(why) => tracing::warn!("Couldn't get bars of {symbol} from {feed:?}: {why}"),
+            }
+
+            // crypto bars don't come from a feed, so there's nothing else to try
+            if matches!(symbol, Symbol::Crypto { .. }) {
+                break;
+            }
         }
 
-        // calculate the average of all the trades
-        data.bars
+        Vec::new()
     }
 
     async fn final_stats(&self) -> Stats {
-        let account = self.inner.client.issue::<account::Get>(&()).await.unwrap();
+        let account = self.inner.issue::<account::Get>(&()).await.unwrap();
+        let journal = self.inner.account.take_journal();
 
         Stats {
             current_equity: account.equity,
             last_equity: account.last_equity,
+            trades: journal.trades,
+            wins: journal.wins,
+            losses: journal.losses,
+            average_hold: journal.average_hold(),
+            hold_histogram: journal.hold_histogram(),
+            realized: journal.realized_by_symbol(),
         }
     }
 
+    async fn equity(&self) -> Num {
+        let account = self.inner.issue::<account::Get>(&()).await.unwrap();
+
+        account.equity
+    }
+
+    async fn buying_power(&self) -> Num {
+        let account = self.inner.issue::<account::Get>(&()).await.unwrap();
+
+        account.buying_power
+    }
+
     async fn open(&self) {
         self.watcher.lock().await.open(self.inner.clone()).await;
+        if let Some(price_stream) = self.price_stream.lock().await.as_mut() {
+            price_stream.open(self.inner.clone());
+        }
     }
 
     async fn close(&self) {
         self.watcher.lock().await.close().await;
+        if let Some(price_stream) = self.price_stream.lock().await.as_mut() {
+            price_stream.close(&self.inner);
+        }
+
+        // the bars of a session have nothing to do with the next one, and would only pile up
+        if let Some(cache) = &self.bar_cache {
+            cache.clear();
+        }
     }
 
     fn account_data(&self) -> &AccountState {
         &self.inner.account
     }
 }
+
+// puts bars from further back in time in front of the ones we already have, skipping any overlap
+fn prepend_bars(bars: &mut Vec<bars::Bar>, mut older: Vec<bars::Bar>) {
+    if let Some(first) = bars.first() {
+        older.retain(|bar| bar.time < first.time);
+    }
+    older.append(bars);
+    *bars = older;
+}
+
+// puts newer bars after the ones we already have. the newer ones win where they overlap, the
+// last bar we had may have been built before its minute was over
+fn append_bars(bars: &mut Vec<bars::Bar>, newer: Vec<bars::Bar>) {
+    if let Some(first) = newer.first() {
+        bars.retain(|bar| bar.time < first.time);
+    }
+    bars.extend(newer);
+}
+
+// drops the bars that are older than `from`, but never so many that less than `min_bars` are left
+fn trim_bars(
+    bars: &mut Vec<bars::Bar>,
+    from: DateTime<Utc>,
+    min_bars: usize,
+    bar_limit: Option<usize>,
+) {
+    let recent = bars.iter().filter(|bar| bar.time >= from).count();
+    keep_latest_bars(bars, recent.max(min_bars));
+
+    if let Some(bar_limit) = bar_limit {
+        keep_latest_bars(bars, bar_limit);
+    }
+}
+
+// waits for the limit order to fill, and sends whatever didn't fill in time to market. the remainder
+// of a crypto order is fit to its lots, if they're known
+async fn limit_or_market(
+    inner: Arc<LiveInner>,
+    symbol: Symbol,
+    side: Side,
+    quantity: Num,
+    order: order::Order,
+    timeout: Duration,
+    lots: Option<endpoints::AssetLots>,
+) {
+    let mut order = inner.wait_for(order, timeout).await;
+    if order.status.is_terminal() {
+        return;
+    }
+
+    // it can still fill while being cancelled, so only what's left once it's done goes to market
+    inner.cancel_order(order.id).await;
+    while !order.status.is_terminal() {
+        tokio::time::sleep(ORDER_POLL_INTERVAL).await;
+
+        order = inner.reread_order(order).await;
+    }
+
+    let remaining = quantity - order.filled_quantity;
+    let remaining = match &lots {
+        Some(lots) => sizing::fit_to_lot(
+            remaining,
+            lots.min_order_size.as_ref(),
+            lots.min_trade_increment.as_ref(),
+        ),
+        None => Some(remaining).filter(|remaining| *remaining > Num::from(0)),
+    };
+    let Some(remaining) = remaining else {
+        return;
+    };
+
+    tracing::info!("{symbol}'s limit order didn't fill in time, going to market");
+    let request = inner.order_request(&symbol, side, Amount::quantity(remaining), None);
+    match inner.issue::<order::Post>(&request).await {
+        // keeps the scans off of it until the order watcher hears about it
+        Ok(_) => inner.account.mark_order_in_progress(symbol, Utc::now()),
+        Err(why) => tracing::error!("Couldn't send {symbol}'s limit order to market: {why}"),
+    }
+}
+
+// the time range to request bars from, accounting for how delayed the feed is. if only so many bars
+// are wanted, there's no need to ask for the ones before them
+fn bars_window(
+    period: TimePeriod,
+    feed: Feed,
+    bar_limit: Option<usize>,
+) -> (DateTime<Utc>, DateTime<Utc>) {
+    let to = Utc::now()
+        .checked_sub_signed(chrono::Duration::minutes(match feed {
+            Feed::IEX => 1,
+            Feed::SIP => 5,
+            _ => 0,
+        }))
+        .unwrap();
+    let mut from = to.checked_sub_signed(period.to_chrono()).unwrap();
+
+    if let Some(bar_limit) = bar_limit {
+        from = from.max(to - period.bar_length() * bar_limit as i32);
+    }
+
+    (from, to)
+}
+
+// drops all but the most recent bars
+fn keep_latest_bars(bars: &mut Vec<bars::Bar>, bar_limit: usize) {
+    if bars.len() > bar_limit {
+        bars.drain(..bars.len() - bar_limit);
+    }
+}
+
+// the start of the bar that the time falls into
+fn bar_start(time: DateTime<Utc>, timeframe: BarTimeFrame) -> DateTime<Utc> {
+    let length = timeframe.length().num_seconds();
+    let seconds = time.timestamp();
+
+    Utc.timestamp_opt(seconds - seconds.rem_euclid(length), 0)
+        .unwrap()
+}
+
+// groups the trades into bars of the time frame. the trades have to be sorted by time, which is
+// how alpaca returns them
+fn trades_to_bars(trades: &[endpoints::CryptoTrade], timeframe: BarTimeFrame) -> Vec<bars::Bar> {
+    trades
+        .iter()
+        .group_by(|trade| bar_start(trade.timestamp, timeframe))
+        .into_iter()
+        .map(|(time, trades)| {
+            let trades = trades.collect::<Vec<_>>();
+            let size = trades
+                .iter()
+                .fold(Num::from(0), |size, trade| size + trade.size.clone());
+            let value = trades.iter().fold(Num::from(0), |value, trade| {
+                value + trade.price.clone() * trade.size.clone()
+            });
+
+            bars::Bar {
+                vwap: (!size.is_zero()).then(|| value / size.clone()),
+                ..new_bar(
+                    time,
+                    &trades[0].price,
+                    trades.iter().map(|trade| &trade.price).max().unwrap(),
+                    trades.iter().map(|trade| &trade.price).min().unwrap(),
+                    &trades[trades.len() - 1].price,
+                    &size,
+                )
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use apca::{api::v2::order::Side, data::v2::bars::TimeFrame};
+    use chrono::{DateTime, Utc};
+    use dashmap::DashMap;
+    use num_decimal::Num;
+
+    use super::{positions_from_fills, trades_to_bars, OwnFill};
+    use crate::{
+        backend::endpoints::{BarTimeFrame, CryptoTrade},
+        Position, Symbol,
+    };
+
+    fn time(time: &str) -> DateTime<Utc> {
+        time.parse().unwrap()
+    }
+
+    fn fill(symbol: &str, side: Side, quantity: i64, price: i64, at: &str) -> OwnFill {
+        OwnFill {
+            symbol: Symbol::from(symbol),
+            side,
+            quantity: Num::from(quantity),
+            price: Num::from(price),
+            time: time(at),
+        }
+    }
+
+    fn held(positions: &[(&str, i64)]) -> DashMap<Symbol, Position> {
+        positions
+            .iter()
+            .map(|(symbol, owned)| {
+                let pos = Position {
+                    owned: Num::from(*owned),
+                    ..Position::new(Utc::now())
+                };
+                (Symbol::from(*symbol), pos)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn restarting_picks_up_the_open_tagged_positions() {
+        let fills = vec![
+            fill("AAPL", Side::Buy, 2, 100, "2023-06-05T14:00:00Z"),
+            fill("MSFT", Side::Buy, 1, 300, "2023-06-05T14:05:00Z"),
+            fill("AAPL", Side::Sell, 1, 110, "2023-06-05T15:00:00Z"),
+            fill("MSFT", Side::Sell, 1, 310, "2023-06-05T15:05:00Z"),
+        ];
+        // another instance holds more AAPL, and all of the TSLA
+        let on_account = held(&[("AAPL", 5), ("TSLA", 3)]);
+
+        let own = positions_from_fills(fills, &on_account);
+
+        assert_eq!(own.len(), 1);
+        let (symbol, pos) = &own[0];
+        assert_eq!(*symbol, Symbol::from("AAPL"));
+        assert_eq!(pos.owned, Num::from(1));
+        assert_eq!(pos.buy_in_price, Num::from(100));
+        assert_eq!(pos.timestamp, time("2023-06-05T14:00:00Z"));
+    }
+
+    #[test]
+    fn positions_sold_by_hand_are_only_kept_as_far_as_theyre_held() {
+        let fills = vec![fill("AAPL", Side::Buy, 4, 100, "2023-06-05T14:00:00Z")];
+
+        let own = positions_from_fills(fills, &held(&[("AAPL", 1)]));
+        assert_eq!(own[0].1.owned, Num::from(1));
+
+        let fills = vec![fill("AAPL", Side::Buy, 4, 100, "2023-06-05T14:00:00Z")];
+        assert!(positions_from_fills(fills, &held(&[])).is_empty());
+    }
+
+    #[test]
+    fn trades_of_fractions_of_a_coin_keep_their_volume() {
+        let trade = |at: &str, price: i64, size: &str| CryptoTrade {
+            timestamp: time(at),
+            price: Num::from(price),
+            size: size.parse().unwrap(),
+        };
+        let trades = [
+            trade("2023-06-05T14:00:10Z", 100, "0.25"),
+            trade("2023-06-05T14:00:50Z", 102, "0.5"),
+            trade("2023-06-05T14:01:30Z", 101, "0.125"),
+        ];
+        let minutes = BarTimeFrame {
+            unit: TimeFrame::OneMinute,
+            multiple: 1,
+        };
+
+        let bars = trades_to_bars(&trades, minutes);
+
+        assert_eq!(bars.len(), 2);
+        assert_eq!(bars[0].volume, "0.75".parse::<Num>().unwrap());
+        assert_eq!(bars[0].high, Num::from(102));
+        assert_eq!(bars[1].volume, "0.125".parse::<Num>().unwrap());
+    }
+}
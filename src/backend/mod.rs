@@ -1,58 +1,268 @@
+mod backtest;
 mod endpoints;
+mod event_log;
 mod live;
+mod prices;
+mod rate_limit;
+#[cfg(test)]
 mod test;
 mod watcher;
 
-use std::collections::HashMap;
+use std::{collections::HashMap, str::FromStr, time::Duration};
 
 use apca::{
     api::v2::{
+        asset,
         clock::Clock,
-        order::{Amount, Side},
+        order::{self, Amount, Side},
     },
-    data::v2::{bars, Feed},
+    data::v2::{Adjustment, Feed},
 };
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use num_decimal::Num;
 
-use crate::{AccountState, Symbol, TimePeriod};
+use crate::{bars, AccountState, Symbol, TimePeriod};
 
+pub(crate) use backtest::BacktestBackend;
+pub(crate) use endpoints::{replay, BarTimeFrame, LastQuote, Snapshot};
 pub(crate) use live::*;
+#[cfg(test)]
+pub(crate) use test::{CompressedMarket, TestBackend};
+
+/// Which kinds of assets to look at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum AssetClass {
+    Equity,
+    #[allow(unused)]
+    Crypto,
+    Both,
+}
+
+impl AssetClass {
+    pub(crate) fn classes(self) -> &'static [asset::Class] {
+        match self {
+            Self::Equity => &[asset::Class::UsEquity],
+            Self::Crypto => &[asset::Class::Crypto],
+            Self::Both => &[asset::Class::UsEquity, asset::Class::Crypto],
+        }
+    }
+}
+
+/// Which price a symbol is considered to be at.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum PriceSource {
+    /// the price of the last trade
+    #[default]
+    LastTrade,
+    /// halfway between the best bid and ask. thinly traded symbols can go a while without a
+    /// trade, but their quotes stay current
+    QuoteMid,
+}
+
+impl FromStr for PriceSource {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "last-trade" => Ok(Self::LastTrade),
+            "quote-mid" => Ok(Self::QuoteMid),
+            _ => Err("expected `last-trade` or `quote-mid`".to_string()),
+        }
+    }
+}
+
+/// Which corporate actions past bars are adjusted for. The indicators should always run on
+/// adjusted bars, a split within the lookback otherwise shows up as a huge drop or jump.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum BarAdjustment {
+    /// the prices as they were traded
+    Raw,
+    #[default]
+    Split,
+    Dividend,
+    /// splits and dividends
+    All,
+}
+
+impl BarAdjustment {
+    pub(crate) fn to_apca(self) -> Adjustment {
+        match self {
+            Self::Raw => Adjustment::Raw,
+            Self::Split => Adjustment::Split,
+            Self::Dividend => Adjustment::Dividend,
+            Self::All => Adjustment::All,
+        }
+    }
+}
+
+impl FromStr for BarAdjustment {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "raw" => Ok(Self::Raw),
+            "split" => Ok(Self::Split),
+            "dividend" => Ok(Self::Dividend),
+            "all" => Ok(Self::All),
+            _ => Err("expected `raw`, `split`, `dividend` or `all`".to_string()),
+        }
+    }
+}
+
+// the clock as alpaca would return it. the fields can't be set directly outside of apca
+fn clock(
+    open: bool,
+    now: DateTime<Utc>,
+    next_open: DateTime<Utc>,
+    next_close: DateTime<Utc>,
+) -> Clock {
+    serde_json::from_value(serde_json::json!({
+        "is_open": open,
+        "timestamp": now.to_rfc3339(),
+        "next_open": next_open.to_rfc3339(),
+        "next_close": next_close.to_rfc3339(),
+    }))
+    .unwrap()
+}
 
 pub(crate) struct Stats {
     pub(crate) current_equity: Num,
     pub(crate) last_equity: Num,
+    pub(crate) trades: usize,
+    pub(crate) wins: usize,
+    pub(crate) losses: usize,
+    pub(crate) average_hold: Option<Duration>,
+    /// the number of closed positions in each of `crate::HOLD_BUCKETS`, plus one for longer holds
+    pub(crate) hold_histogram: Vec<usize>,
+    /// the realized profit or loss of each symbol sold today, best first
+    pub(crate) realized: Vec<(Symbol, Num)>,
 }
 
 #[async_trait]
 pub(crate) trait Backend {
-    async fn submit_order(&self, symbol: Symbol, side: Side, amount: Amount);
+    /// The current time, as far as trading is concerned.
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+
+    /// Submits the order, as a limit at `limit_price` if given and at market otherwise.
+    async fn submit_order(
+        &self,
+        symbol: Symbol,
+        side: Side,
+        amount: Amount,
+        limit_price: Option<Num>,
+    );
+
+    /// Submits the order and waits until it's filled, cancelled or the timeout runs out, returning
+    /// the order as it was last seen. `None` means there's no order to show, because it was never
+    /// submitted or because the backend fills its orders as they're submitted, which is what is
+    /// assumed unless told otherwise.
+    async fn submit_order_and_wait(
+        &self,
+        symbol: Symbol,
+        side: Side,
+        amount: Amount,
+        limit_price: Option<Num>,
+        _timeout: Duration,
+    ) -> Option<order::Order> {
+        self.submit_order(symbol, side, amount, limit_price).await;
+        None
+    }
+
+    /// Submits a limit order, and replaces whatever of it hasn't filled within the timeout with a
+    /// market order. Returns once the limit order is in, the rest happens in the background.
+    /// Backends that fill their orders as they're submitted have nothing to replace.
+    async fn submit_limit_order_or_market(
+        &self,
+        symbol: Symbol,
+        side: Side,
+        quantity: Num,
+        limit_price: Num,
+        _timeout: Duration,
+    ) {
+        self.submit_order(symbol, side, Amount::quantity(quantity), Some(limit_price))
+            .await;
+    }
 
     async fn cancel_all_open_orders(&self);
 
+    /// Cancels a single order, leaving every other order alone.
+    async fn cancel_order(&self, _id: order::Id) {}
+
+    /// The symbols that have an order open right now. None do unless the backend says otherwise.
+    async fn open_order_symbols(&self) -> Vec<Symbol> {
+        Vec::new()
+    }
+
+    /// The orders of the symbol that are open right now.
+    async fn open_orders_for(&self, _symbol: &Symbol) -> Vec<order::Id> {
+        Vec::new()
+    }
+
     async fn clock_now(&self) -> Clock;
 
-    async fn all_active_assets(&self) -> Vec<Symbol>;
+    async fn all_active_assets(&self, class: AssetClass) -> Vec<Symbol>;
+
+    /// Whether the symbol can be bought in fractions of a share, and so by notional.
+    async fn is_fractionable(&self, _symbol: &Symbol) -> bool {
+        true
+    }
 
-    async fn all_latest_prices(&self, symbols: Vec<Symbol>) -> HashMap<Symbol, Num>;
+    async fn all_latest_prices(
+        &self,
+        symbols: Vec<Symbol>,
+        source: PriceSource,
+    ) -> HashMap<Symbol, Num>;
+
+    /// The latest quote of every symbol. Backends without quotes have none to give.
+    async fn all_latest_quotes(&self, _symbols: Vec<Symbol>) -> HashMap<Symbol, LastQuote> {
+        HashMap::new()
+    }
 
+    /// Gets the latest trade and bars of every symbol at once. Symbols without any recent data
+    /// are left out.
+    async fn snapshots(&self, _symbols: Vec<Symbol>) -> HashMap<Symbol, Snapshot> {
+        HashMap::new()
+    }
+
+    /// Gets the bars of every symbol within the period, looking further back for the symbols that
+    /// have less than `min_bars`. Only the latest `bar_limit` bars are kept. The feeds are tried in
+    /// order until one of them has bars for the symbol.
     async fn all_latest_bars(
         &self,
         symbols: Vec<Symbol>,
         period: TimePeriod,
-        feed: Feed,
+        feeds: &[Feed],
+        min_bars: usize,
+        bar_limit: Option<usize>,
     ) -> HashMap<Symbol, Vec<bars::Bar>> {
-        let bars = symbols.into_iter().map(|symbol| async {
-            let bars = self.latest_bars(symbol.clone(), period, feed).await;
+        let bars = symbols.into_iter().map(|symbol| async move {
+            let bars = self
+                .latest_bars(symbol.clone(), period, feeds, min_bars, bar_limit)
+                .await;
             (symbol, bars)
         });
         futures::future::join_all(bars).await.into_iter().collect()
     }
 
-    async fn latest_bars(&self, symbol: Symbol, period: TimePeriod, feed: Feed) -> Vec<bars::Bar>;
+    async fn latest_bars(
+        &self,
+        symbol: Symbol,
+        period: TimePeriod,
+        feeds: &[Feed],
+        min_bars: usize,
+        bar_limit: Option<usize>,
+    ) -> Vec<bars::Bar>;
 
     async fn final_stats(&self) -> Stats;
 
+    async fn equity(&self) -> Num;
+
+    /// How much can still be spent on buys, counting the orders that haven't filled yet.
+    async fn buying_power(&self) -> Num;
+
     async fn open(&self);
 
     async fn close(&self);
@@ -68,9 +278,10 @@ pub(crate) trait Backend {
             return;
         }
 
-        for (symbol, pos) in account.positions.clone() {
-            if filter(&symbol) {
-                self.submit_order(symbol, Side::Sell, Amount::quantity(pos.owned))
+        // sold off positions and buys that haven't filled yet have nothing to sell
+        for (symbol, pos) in account.sorted_positions() {
+            if !pos.owned.is_zero() && filter(&symbol) {
+                self.submit_order(symbol, Side::Sell, Amount::quantity(pos.owned), None)
                     .await;
             }
         }
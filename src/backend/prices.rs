@@ -0,0 +1,80 @@
+use std::sync::Arc;
+
+use apca::data::v2::stream::{drive, Data, MarketData, RealtimeData, IEX};
+use futures::{FutureExt, StreamExt};
+use tokio::task::JoinHandle;
+
+use crate::Symbol;
+
+use super::LiveInner;
+
+/// Keeps the price of the last trade of each stock it's subscribed to as they come in, so that
+/// they don't have to be asked for on every scan. Cryptos aren't streamed.
+///
+/// The stream comes from the IEX feed, which the free plan only allows so many symbols on. If the
+/// subscription is refused, or the stream goes down, the prices are polled like before.
+pub(super) struct LivePriceStream {
+    handle: JoinHandle<()>,
+    symbols: Vec<Symbol>,
+}
+
+impl LivePriceStream {
+    pub(super) fn new(inner: Arc<LiveInner>, symbols: Vec<Symbol>) -> Self {
+        let tickers = symbols
+            .iter()
+            .filter(|symbol| matches!(symbol, Symbol::Stock { .. }))
+            .map(|symbol| symbol.ticker().to_string())
+            .collect::<Vec<_>>();
+
+        Self {
+            handle: tokio::task::spawn(async move {
+                let (mut stream, mut subscription) =
+                    match inner.client.subscribe::<RealtimeData<IEX>>().await {
+                        Ok(stream) => stream,
+                        Err(why) => {
+                            tracing::warn!("couldn't stream prices, polling them instead: {why}");
+                            return;
+                        }
+                    };
+
+                let mut data = MarketData::default();
+                data.set_trades(tickers);
+
+                let subscribe = subscription.subscribe(&data).boxed();
+                if !matches!(drive(subscribe, &mut stream).await, Ok(Ok(Ok(())))) {
+                    tracing::warn!("the price stream refused the symbols, polling them instead");
+                    return;
+                }
+
+                while let Some(res) = stream.next().await {
+                    match res {
+                        Ok(Ok(Data::Trade(trade))) => {
+                            inner
+                                .streamed_prices
+                                .insert(Symbol::from(trade.symbol.as_str()), trade.trade_price);
+                        }
+                        Ok(Ok(_)) => {}
+                        Ok(Err(why)) => tracing::debug!("couldn't read a streamed price: {why}"),
+                        Err(why) => tracing::error!("price stream error: {why}"),
+                    }
+                }
+
+                // the prices would only get staler from here on
+                inner.streamed_prices.clear();
+                tracing::warn!("price stream closed, polling the prices instead");
+            }),
+            symbols,
+        }
+    }
+
+    pub(super) fn open(&mut self, inner: Arc<LiveInner>) {
+        if self.handle.is_finished() {
+            *self = Self::new(inner, std::mem::take(&mut self.symbols));
+        }
+    }
+
+    pub(super) fn close(&mut self, inner: &LiveInner) {
+        self.handle.abort();
+        inner.streamed_prices.clear();
+    }
+}
@@ -0,0 +1,87 @@
+use std::{sync::Mutex, time::Duration};
+
+use tokio::time::Instant;
+
+// alpaca allows this many requests per minute
+const REQUESTS_PER_MINUTE: f64 = 200.0;
+
+// how many requests can go out at once. the bucket refills that much slower than the limit, so
+// that a full bucket and a minute of refills together still stay under it
+const BURST: f64 = 20.0;
+
+/// A token bucket shared by every request to alpaca. Small bursts go straight through, after that
+/// requests are spaced out so that no minute has more than the limit.
+pub(super) struct RateLimiter {
+    bucket: Mutex<Bucket>,
+}
+
+struct Bucket {
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl RateLimiter {
+    pub(super) fn new() -> Self {
+        Self {
+            bucket: Mutex::new(Bucket {
+                tokens: BURST,
+                refilled: Instant::now(),
+            }),
+        }
+    }
+
+    /// Waits until there's room for another request.
+    pub(super) async fn acquire(&self) {
+        let per_second = (REQUESTS_PER_MINUTE - BURST) / 60.0;
+
+        loop {
+            let wait = {
+                let mut bucket = self.bucket.lock().unwrap();
+
+                let now = Instant::now();
+                let refill = now.duration_since(bucket.refilled).as_secs_f64() * per_second;
+                bucket.tokens = (bucket.tokens + refill).min(BURST);
+                bucket.refilled = now;
+
+                if bucket.tokens >= 1.0 {
+                    bucket.tokens -= 1.0;
+                    return;
+                }
+
+                Duration::from_secs_f64((1.0 - bucket.tokens) / per_second)
+            };
+
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::time::Instant;
+
+    use super::{RateLimiter, REQUESTS_PER_MINUTE};
+
+    #[tokio::test(start_paused = true)]
+    async fn no_minute_goes_over_the_limit() {
+        let limiter = RateLimiter::new();
+        let start = Instant::now();
+
+        // when each request went out, in seconds since the start
+        let mut sent = Vec::new();
+        while start.elapsed() < Duration::from_secs(180) {
+            limiter.acquire().await;
+            sent.push(start.elapsed().as_secs_f64());
+        }
+
+        for (i, first) in sent.iter().enumerate() {
+            let in_minute = sent[i..]
+                .iter()
+                .take_while(|time| **time < first + 60.0)
+                .count();
+            assert!(in_minute as f64 <= REQUESTS_PER_MINUTE);
+        }
+    }
+}
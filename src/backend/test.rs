@@ -1,40 +1,92 @@
-use std::collections::HashMap;
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+};
 
 use apca::{
     api::v2::{
         clock::Clock,
         order::{Amount, Side},
     },
-    data::v2::{bars, Feed},
+    data::v2::Feed,
 };
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use num_decimal::Num;
 
-use crate::{AccountState, Symbol, TimePeriod};
+use crate::{bars, wait::TimeSource, AccountState, Symbol, TimePeriod};
 
-use super::{Backend, Stats};
+use super::{clock, AssetClass, Backend, PriceSource, Stats};
 
 pub(crate) struct TestBackend {
-    client: apca::Client,
     account: AccountState,
+    /// what `clock_now` goes by
+    clock: TestClock,
+}
+
+enum TestClock {
+    /// the made up market hours, and the time they're checked against
+    Market(CompressedMarket, Box<dyn TimeSource>),
+    /// handed out in order, repeating the last one
+    Scripted(Mutex<VecDeque<Clock>>),
 }
 
 impl TestBackend {
-    async fn new() -> Self {
-        let api_info = apca::ApiInfo::from_env().unwrap();
+    /// A backend without an account behind it, whose market opens and closes on the schedule.
+    pub(crate) fn with_market(market: CompressedMarket, time: Box<dyn TimeSource>) -> Self {
+        Self {
+            account: AccountState::new(Default::default()),
+            clock: TestClock::Market(market, time),
+        }
+    }
 
+    /// A backend without an account behind it, whose clock is each of the clocks in turn, and the
+    /// last one from then on.
+    pub(crate) fn with_clocks(clocks: impl IntoIterator<Item = Clock>) -> Self {
         Self {
-            client: apca::Client::new(api_info),
-            account: AccountState {
-                positions: Default::default(),
-            },
+            account: AccountState::new(Default::default()),
+            clock: TestClock::Scripted(Mutex::new(clocks.into_iter().collect())),
         }
     }
 }
 
+/// A market that keeps opening and closing on a short schedule, so that the open and close logic
+/// can go through whole days in seconds.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct CompressedMarket {
+    /// when the first session opens. the market is closed until then
+    pub(crate) first_open: DateTime<Utc>,
+    pub(crate) open_for: chrono::Duration,
+    pub(crate) closed_for: chrono::Duration,
+}
+
+impl CompressedMarket {
+    /// The clock as alpaca would return it at the time.
+    pub(crate) fn clock_at(&self, now: DateTime<Utc>) -> Clock {
+        let session = self.open_for + self.closed_for;
+
+        let (open, next_open, next_close) = if now < self.first_open {
+            (false, self.first_open, self.first_open + self.open_for)
+        } else {
+            let sessions = (now - self.first_open).num_milliseconds() / session.num_milliseconds();
+            let opened = self.first_open
+                + chrono::Duration::milliseconds(sessions * session.num_milliseconds());
+            let closes = opened + self.open_for;
+
+            if now < closes {
+                (true, opened + session, closes)
+            } else {
+                (false, opened + session, opened + session + self.open_for)
+            }
+        };
+
+        clock(open, now, next_open, next_close)
+    }
+}
+
 #[async_trait]
 impl Backend for TestBackend {
-    async fn submit_order(&self, symbol: Symbol, side: Side, amount: Amount) {
+    async fn submit_order(&self, _: Symbol, _: Side, _: Amount, _: Option<Num>) {
         todo!()
     }
 
@@ -43,18 +95,35 @@ impl Backend for TestBackend {
     }
 
     async fn clock_now(&self) -> Clock {
-        todo!()
+        match &self.clock {
+            TestClock::Market(market, time) => market.clock_at(time.now()),
+            TestClock::Scripted(clocks) => {
+                let mut clocks = clocks.lock().unwrap();
+                if clocks.len() > 1 {
+                    clocks.pop_front().unwrap()
+                } else {
+                    clocks[0]
+                }
+            }
+        }
     }
 
-    async fn all_active_assets(&self) -> Vec<Symbol> {
+    async fn all_active_assets(&self, _: AssetClass) -> Vec<Symbol> {
         todo!()
     }
 
-    async fn all_latest_prices(&self, symbols: Vec<Symbol>) -> HashMap<Symbol, Num> {
+    async fn all_latest_prices(&self, _: Vec<Symbol>, _: PriceSource) -> HashMap<Symbol, Num> {
         todo!()
     }
 
-    async fn latest_bars(&self, symbol: Symbol, period: TimePeriod, feed: Feed) -> Vec<bars::Bar> {
+    async fn latest_bars(
+        &self,
+        _: Symbol,
+        _: TimePeriod,
+        _: &[Feed],
+        _: usize,
+        _: Option<usize>,
+    ) -> Vec<bars::Bar> {
         todo!()
     }
 
@@ -62,6 +131,14 @@ impl Backend for TestBackend {
         todo!()
     }
 
+    async fn equity(&self) -> Num {
+        todo!()
+    }
+
+    async fn buying_power(&self) -> Num {
+        todo!()
+    }
+
     async fn open(&self) {}
 
     async fn close(&self) {}
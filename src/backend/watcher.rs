@@ -1,11 +1,54 @@
-use std::{sync::Arc, time::Instant};
+use std::{collections::HashMap, sync::Arc};
 
-use apca::api::v2::updates::OrderUpdates;
+use apca::api::v2::{
+    order,
+    updates::{OrderStatus, OrderUpdates},
+};
+use chrono::Utc;
 use futures::StreamExt;
+use num_decimal::Num;
 use tokio::task::JoinHandle;
 
+use crate::Symbol;
+
 use super::LiveInner;
 
+/// How much of each order has filled so far, and at what average price. Order updates only carry
+/// the totals, so these are needed to tell what the latest fill added.
+#[derive(Default)]
+struct Fills {
+    orders: HashMap<order::Id, (Num, Num)>,
+}
+
+impl Fills {
+    /// Remembers the totals of the order, returning the quantity filled since it was last seen
+    /// along with the average price of that quantity.
+    fn record(&mut self, order: &order::Order) -> (Num, Num) {
+        let total = order.filled_quantity.clone();
+        let average_price = order.average_fill_price.clone().unwrap_or_default();
+
+        let (previous, previous_price) = if order.status.is_terminal() {
+            self.orders.remove(&order.id)
+        } else {
+            self.orders
+                .insert(order.id, (total.clone(), average_price.clone()))
+        }
+        .unwrap_or_default();
+
+        let quantity = total.clone() - previous.clone();
+        if quantity.is_zero() {
+            return (quantity, average_price);
+        }
+
+        let price = (total * average_price - previous * previous_price) / quantity.clone();
+        (quantity, price)
+    }
+
+    fn forget(&mut self, id: &order::Id) {
+        self.orders.remove(id);
+    }
+}
+
 pub(super) struct LiveOrderWatcher {
     handle: JoinHandle<()>,
 }
@@ -15,37 +58,44 @@ impl LiveOrderWatcher {
         Self {
             handle: tokio::task::spawn(async move {
                 let (mut stream, _) = inner.client.subscribe::<OrderUpdates>().await.unwrap();
+                let mut fills = Fills::default();
 
                 while let Some(res) = stream.next().await {
                     match res {
                         Ok(res) => match res {
+                            Ok(res) if !inner.is_own_order(&res.order) => {}
                             Ok(res) => {
-                                inner
-                                    .account
-                                    .positions
-                                    .entry(res.order.symbol.into())
-                                    .and_modify(|pos| {
-                                        pos.order_in_progress = res.order.status.is_terminal();
-
-                                        if res.order.status.is_terminal() {
-                                            pos.owned += res.order.filled_quantity.clone();
-                                            pos.buy_in_price = res
-                                                .order
-                                                .average_fill_price
-                                                .clone()
-                                                .unwrap_or_default();
-                                            pos.timestamp = Instant::now()
-                                        }
-                                    })
-                                    .or_insert_with(|| crate::Position {
-                                        owned: res.order.filled_quantity,
-                                        buy_in_price: res
-                                            .order
-                                            .average_fill_price
-                                            .unwrap_or_default(),
-                                        timestamp: Instant::now(),
-                                        order_in_progress: res.order.status.is_terminal(),
-                                    });
+                                if let Some(log) = inner.event_log.lock().unwrap().as_mut() {
+                                    log.record(&res.order);
+                                }
+
+                                let symbol = Symbol::from(res.order.symbol.as_str());
+
+                                match res.event {
+                                    OrderStatus::PartialFill | OrderStatus::Fill => {
+                                        let (quantity, price) = fills.record(&res.order);
+                                        inner.account.apply_fill(
+                                            symbol,
+                                            res.order.side,
+                                            &quantity,
+                                            Some(&price),
+                                            res.order.status,
+                                            Utc::now(),
+                                        );
+                                    }
+                                    // whatever filled before was already applied
+                                    OrderStatus::Canceled
+                                    | OrderStatus::Expired
+                                    | OrderStatus::Rejected => {
+                                        fills.forget(&res.order.id);
+                                        inner.account.finish_order(&symbol, Utc::now());
+                                    }
+                                    _ if res.order.status.is_terminal() => {
+                                        fills.forget(&res.order.id);
+                                        inner.account.finish_order(&symbol, Utc::now());
+                                    }
+                                    _ => inner.account.mark_order_in_progress(symbol, Utc::now()),
+                                }
                             }
                             Err(why) => tracing::error!("order updates error: {why}"),
                         },
@@ -0,0 +1,226 @@
+use std::{collections::HashMap, path::Path};
+
+use chrono::{DateTime, NaiveTime, Utc};
+use num_decimal::Num;
+use serde::Serialize;
+
+use crate::{
+    args::DayEndPolicy,
+    backend::{Backend, BacktestBackend, Stats},
+    bars, close_session, in_trading_window, open_session,
+    stats::num_to_f64,
+    strategy::Strategy,
+    wait::EXCHANGE_TZ,
+    watch_all, ScanOptions, Symbol,
+};
+
+// the made up account a backtest trades with starts out with this much cash
+const STARTING_CASH: i64 = 100_000;
+
+/// How a strategy did over a backtest, to be compared against the other runs of a parameter sweep.
+#[derive(Debug, Serialize)]
+pub(crate) struct BacktestReport {
+    pub(crate) starting_equity: Num,
+    pub(crate) final_equity: Num,
+    /// in percent of the starting equity
+    pub(crate) total_return_pct: f64,
+    /// the furthest the equity fell from an earlier high, in percent of that high
+    pub(crate) max_drawdown_pct: f64,
+    /// the mean return between scans over its standard deviation. it isn't annualized, since the
+    /// scans are however far apart the bars are. `None` if the equity never moved
+    pub(crate) sharpe: Option<f64>,
+    pub(crate) trades: usize,
+    pub(crate) wins: usize,
+    pub(crate) losses: usize,
+    /// the share of closed positions that were sold at a profit. `None` if none were closed
+    pub(crate) win_rate: Option<f64>,
+    /// the realized profit or loss of each symbol
+    pub(crate) realized: HashMap<String, Num>,
+    pub(crate) equity_curve: Vec<EquityPoint>,
+}
+
+/// The equity right after a scan.
+#[derive(Debug, Serialize)]
+pub(crate) struct EquityPoint {
+    pub(crate) time: DateTime<Utc>,
+    pub(crate) equity: Num,
+}
+
+impl BacktestReport {
+    pub(crate) fn save(&self, path: &Path) -> Result<(), String> {
+        let contents = serde_json::to_string_pretty(self)
+            .map_err(|why| format!("couldn't serialize the report: {why}"))?;
+
+        std::fs::write(path, contents).map_err(|why| format!("couldn't write {path:?}: {why}"))
+    }
+}
+
+// what the sessions of a backtest added up to, since each one's stats only cover that session
+#[derive(Default)]
+struct Totals {
+    trades: usize,
+    wins: usize,
+    losses: usize,
+    realized: HashMap<String, Num>,
+}
+
+impl Totals {
+    fn add(&mut self, stats: Stats) {
+        self.trades += stats.trades;
+        self.wins += stats.wins;
+        self.losses += stats.losses;
+        for (symbol, pnl) in stats.realized {
+            *self.realized.entry(symbol.to_string()).or_default() += pnl;
+        }
+    }
+}
+
+/// Runs the strategy over the bars, scanning every symbol at the end of each bar that ends while
+/// the market is open. Each session is opened and closed the way the live loop does it, so the day
+/// end policy is applied at every close.
+pub(crate) async fn run(
+    bars: HashMap<Symbol, Vec<bars::Bar>>,
+    strategy: &dyn Strategy,
+    options: &mut ScanOptions<'_>,
+    trading_windows: &[(NaiveTime, NaiveTime)],
+    day_end_policy: DayEndPolicy,
+    min_equity: Option<&Num>,
+) -> BacktestReport {
+    let mut symbols = bars.keys().cloned().collect::<Vec<_>>();
+    symbols.sort();
+
+    let start = bars
+        .values()
+        .filter_map(|bars| bars.first())
+        .map(|bar| bar.time)
+        .min()
+        .unwrap_or_else(Utc::now);
+    let backend = BacktestBackend::new(
+        bars,
+        options.period.bar_length(),
+        start,
+        Num::from(STARTING_CASH),
+    );
+
+    let starting_equity = backend.equity().await;
+    let mut equity_curve = Vec::new();
+
+    let mut totals = Totals::default();
+    // the close of the session that's open, if one is
+    let mut session_close = None;
+
+    for end in backend.bar_ends() {
+        if let Some(close) = session_close.filter(|close| end >= *close) {
+            session_close = None;
+            backend.advance_to(close);
+            totals.add(close_session(&backend, day_end_policy, options.blacklist).await);
+        }
+
+        backend.advance_to(end);
+        let clock = backend.clock_now().await;
+        if !clock.open {
+            continue;
+        }
+
+        let first_of_day = session_close.is_none();
+        session_close = Some(clock.next_close);
+        open_session(&backend, options, first_of_day, min_equity).await;
+
+        options.in_trading_window =
+            in_trading_window(trading_windows, end.with_timezone(&EXCHANGE_TZ).time());
+        watch_all(&backend, strategy, symbols.clone(), options).await;
+
+        equity_curve.push(EquityPoint {
+            time: end,
+            equity: backend.equity().await,
+        });
+    }
+
+    // the bars ran out before the last session's close
+    if let Some(close) = session_close {
+        backend.advance_to(close);
+        totals.add(close_session(&backend, day_end_policy, options.blacklist).await);
+    }
+
+    let final_equity = backend.equity().await;
+    let closed = totals.wins + totals.losses;
+
+    BacktestReport {
+        total_return_pct: (num_to_f64(&final_equity) / num_to_f64(&starting_equity) - 1.0) * 100.0,
+        max_drawdown_pct: max_drawdown_pct(&equity_curve),
+        sharpe: sharpe(&equity_curve),
+        trades: totals.trades,
+        wins: totals.wins,
+        losses: totals.losses,
+        win_rate: (closed > 0).then(|| totals.wins as f64 / closed as f64),
+        realized: totals.realized,
+        starting_equity,
+        final_equity,
+        equity_curve,
+    }
+}
+
+fn max_drawdown_pct(curve: &[EquityPoint]) -> f64 {
+    let mut high = f64::MIN;
+    let mut drawdown: f64 = 0.0;
+
+    for point in curve {
+        let equity = num_to_f64(&point.equity);
+        high = high.max(equity);
+        if high > 0.0 {
+            drawdown = drawdown.max((high - equity) / high * 100.0);
+        }
+    }
+
+    drawdown
+}
+
+fn sharpe(curve: &[EquityPoint]) -> Option<f64> {
+    let returns = curve
+        .windows(2)
+        .map(|pair| num_to_f64(&pair[1].equity) / num_to_f64(&pair[0].equity) - 1.0)
+        .collect::<Vec<_>>();
+    if returns.is_empty() {
+        return None;
+    }
+
+    let mean = returns.iter().sum::<f64>() / returns.len() as f64;
+    let variance = returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / returns.len() as f64;
+
+    (variance > 0.0).then(|| mean / variance.sqrt())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use num_decimal::Num;
+
+    use super::{max_drawdown_pct, sharpe, EquityPoint};
+
+    fn curve(equities: &[i64]) -> Vec<EquityPoint> {
+        let start = Utc.with_ymd_and_hms(2023, 6, 5, 14, 0, 0).unwrap();
+        equities
+            .iter()
+            .enumerate()
+            .map(|(i, equity)| EquityPoint {
+                time: start + chrono::Duration::minutes(i as i64),
+                equity: Num::from(*equity),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn drawdown_is_measured_from_the_highest_equity() {
+        assert_eq!(
+            max_drawdown_pct(&curve(&[100, 120, 90, 110, 60, 130])),
+            50.0
+        );
+        assert_eq!(max_drawdown_pct(&curve(&[100, 110, 120])), 0.0);
+    }
+
+    #[test]
+    fn flat_equity_has_no_sharpe() {
+        assert_eq!(sharpe(&curve(&[100, 100, 100])), None);
+        assert!(sharpe(&curve(&[100, 110, 105, 120])).unwrap() > 0.0);
+    }
+}
@@ -0,0 +1,26 @@
+use chrono::{DateTime, Utc};
+use num_decimal::Num;
+use serde::{Deserialize, Serialize};
+
+/// A bar of market data as the data endpoints return it. It's apca's bar along with the volume
+/// weighted average price, which apca leaves out.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub(crate) struct Bar {
+    /// The beginning time of the bar.
+    #[serde(rename = "t")]
+    pub time: DateTime<Utc>,
+    #[serde(rename = "o")]
+    pub open: Num,
+    #[serde(rename = "c")]
+    pub close: Num,
+    #[serde(rename = "h")]
+    pub high: Num,
+    #[serde(rename = "l")]
+    pub low: Num,
+    /// Fractional for crypto pairs.
+    #[serde(rename = "v")]
+    pub volume: Num,
+    /// The average price of the bar's trades, weighted by their size. Not every source has it.
+    #[serde(rename = "vw", default)]
+    pub vwap: Option<Num>,
+}
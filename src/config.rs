@@ -0,0 +1,205 @@
+use std::{fmt::Display, fs, path::Path, str::FromStr};
+
+use chrono_tz::Tz;
+use num_decimal::Num;
+use serde::{de, Deserialize, Deserializer};
+use serde_json::Number;
+
+use crate::{
+    args::ExitOrder,
+    backend::{BarAdjustment, PriceSource},
+    scrape::ScrapePolicy,
+    stats::PriceInput,
+    strategy::EntryRule,
+    TimePeriod,
+};
+
+/// The tunable knobs of a strategy, loaded from a JSON file so that each strategy can keep its own.
+/// Every field stands in for the flag of the same name, e.g. `"hold-days": 3` for `--hold-days 3`
+/// and `"adaptive-rsi": false` for `--no-adaptive-rsi`. The flags given on the command line win
+/// over the file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub(crate) struct StrategyConfig {
+    pub(crate) strategy: Option<String>,
+    pub(crate) strategy_tag: Option<String>,
+    pub(crate) fast: Option<usize>,
+    pub(crate) slow: Option<usize>,
+    pub(crate) rsi_warmup: Option<usize>,
+    pub(crate) bollinger_warmup: Option<usize>,
+    pub(crate) min_evaluation_bars: Option<usize>,
+    pub(crate) warmup_ticks: Option<usize>,
+    pub(crate) hold_overnight: Option<bool>,
+    pub(crate) hold_weeknights: Option<bool>,
+    pub(crate) hold_days: Option<usize>,
+    pub(crate) hold_trading_time: Option<bool>,
+    #[serde(deserialize_with = "decimal")]
+    pub(crate) scale_out: Option<Num>,
+    pub(crate) profit_ladder: Option<String>,
+    pub(crate) bar_limit: Option<usize>,
+    pub(crate) feeds: Option<String>,
+    #[serde(deserialize_with = "parsed")]
+    pub(crate) period: Option<TimePeriod>,
+    #[serde(deserialize_with = "parsed")]
+    pub(crate) confirm_period: Option<TimePeriod>,
+    pub(crate) confirm_rsi_min: Option<f64>,
+    pub(crate) adaptive_rsi: Option<bool>,
+    #[serde(deserialize_with = "parsed")]
+    pub(crate) indicator_input: Option<PriceInput>,
+    #[serde(deserialize_with = "parsed")]
+    pub(crate) entry_rule: Option<EntryRule>,
+    #[serde(deserialize_with = "decimal")]
+    pub(crate) take_profit_pct: Option<Num>,
+    #[serde(deserialize_with = "decimal")]
+    pub(crate) stop_loss_pct: Option<Num>,
+    #[serde(deserialize_with = "decimal")]
+    pub(crate) hard_stop_pct: Option<Num>,
+    pub(crate) incremental_bars: Option<bool>,
+    #[serde(deserialize_with = "parsed")]
+    pub(crate) adjustment: Option<BarAdjustment>,
+    pub(crate) snapshots: Option<bool>,
+    #[serde(deserialize_with = "parsed")]
+    pub(crate) price_source: Option<PriceSource>,
+    pub(crate) trading_window: Option<String>,
+    #[serde(deserialize_with = "parsed")]
+    pub(crate) tz: Option<Tz>,
+    pub(crate) max_clock_age: Option<f64>,
+    pub(crate) stream_prices: Option<bool>,
+    pub(crate) max_per_sector: Option<usize>,
+    #[serde(deserialize_with = "decimal")]
+    pub(crate) max_spread_pct: Option<Num>,
+    #[serde(deserialize_with = "decimal")]
+    pub(crate) max_concentration_pct: Option<Num>,
+    #[serde(deserialize_with = "decimal")]
+    pub(crate) risk_fraction: Option<Num>,
+    pub(crate) max_positions: Option<usize>,
+    #[serde(deserialize_with = "decimal")]
+    pub(crate) atr_risk: Option<Num>,
+    #[serde(deserialize_with = "decimal")]
+    pub(crate) atr_stop_multiple: Option<Num>,
+    #[serde(deserialize_with = "decimal")]
+    pub(crate) min_price: Option<Num>,
+    #[serde(deserialize_with = "decimal")]
+    pub(crate) max_price: Option<Num>,
+    #[serde(deserialize_with = "decimal")]
+    pub(crate) min_equity: Option<Num>,
+    pub(crate) wash_sale_days: Option<u64>,
+    pub(crate) skip_wash_sales: Option<bool>,
+    pub(crate) fractional_shares: Option<bool>,
+    pub(crate) max_open_orders: Option<usize>,
+    pub(crate) recheck_buying_power: Option<bool>,
+    pub(crate) max_orders_per_minute: Option<usize>,
+    #[serde(deserialize_with = "decimal")]
+    pub(crate) limit_offset_bps: Option<Num>,
+    #[serde(deserialize_with = "parsed")]
+    pub(crate) exit_order: Option<ExitOrder>,
+    pub(crate) exit_limit_timeout: Option<f64>,
+    pub(crate) confirm_timeout: Option<f64>,
+    pub(crate) min_scan_interval: Option<f64>,
+    #[serde(deserialize_with = "parsed")]
+    pub(crate) scrape_policy: Option<ScrapePolicy>,
+    pub(crate) refresh_universe: Option<bool>,
+    pub(crate) liquidate_dropped: Option<bool>,
+    pub(crate) watch_limit: Option<usize>,
+    pub(crate) scan_batch: Option<usize>,
+    pub(crate) blacklist: Option<String>,
+}
+
+impl StrategyConfig {
+    pub(crate) fn load(path: &Path) -> Result<Self, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|why| format!("couldn't read the config at {path:?}: {why}"))?;
+
+        serde_json::from_str(&contents).map_err(|why| format!("invalid config at {path:?}: {why}"))
+    }
+}
+
+// the knobs that are parsed from a string on the command line are written the same way in the file
+fn parsed<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: Display,
+{
+    let Some(value) = Option::<String>::deserialize(deserializer)? else {
+        return Ok(None);
+    };
+
+    value
+        .parse()
+        .map(Some)
+        .map_err(|why| de::Error::custom(format!("invalid value `{value}`: {why}")))
+}
+
+// decimals are written as plain JSON numbers, and kept exactly as written
+fn decimal<'de, D>(deserializer: D) -> Result<Option<Num>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let Some(number) = Option::<Number>::deserialize(deserializer)? else {
+        return Ok(None);
+    };
+
+    number
+        .to_string()
+        .parse()
+        .map(Some)
+        .map_err(|why| de::Error::custom(format!("invalid number `{number}`: {why}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use num_decimal::Num;
+
+    use super::StrategyConfig;
+    use crate::args::{Args, DayEndPolicy, ExitOrder};
+
+    #[test]
+    fn config_fills_in_the_args() {
+        let config: StrategyConfig = serde_json::from_str(
+            r#"{
+                "strategy": "mean-reversion",
+                "hold-overnight": true,
+                "hold-days": 3,
+                "scale-out": 0.5,
+                "exit-order": "limit"
+            }"#,
+        )
+        .unwrap();
+
+        let mut args = Args::default();
+        args.merge(config);
+
+        assert_eq!(args.strategy.as_deref(), Some("mean-reversion"));
+        assert_eq!(args.day_end_policy, DayEndPolicy::HoldAll);
+        assert_eq!(args.hold_days, Some(3));
+        assert_eq!(args.scale_out, Some(Num::new(1, 2)));
+        assert_eq!(args.exit_order, ExitOrder::Limit);
+    }
+
+    #[test]
+    fn false_turns_switches_off() {
+        let config: StrategyConfig =
+            serde_json::from_str(r#"{ "adaptive-rsi": false, "hold-overnight": false }"#).unwrap();
+
+        let mut args = Args {
+            adaptive_rsi: true,
+            day_end_policy: DayEndPolicy::HoldAll,
+            ..Default::default()
+        };
+        args.merge(config);
+
+        assert!(!args.adaptive_rsi);
+        assert_eq!(args.day_end_policy, DayEndPolicy::SellAll);
+    }
+
+    #[test]
+    fn unknown_knobs_are_refused() {
+        assert!(serde_json::from_str::<StrategyConfig>(r#"{ "hold-dayz": 3 }"#).is_err());
+    }
+
+    #[test]
+    fn invalid_values_are_refused() {
+        assert!(serde_json::from_str::<StrategyConfig>(r#"{ "exit-order": "stop" }"#).is_err());
+    }
+}
@@ -0,0 +1,118 @@
+use std::{path::Path, str::FromStr};
+
+/// A CSV file whose first line names the columns, which can be in any order. The fields are split
+/// on commas and trimmed, and can be quoted to have commas in them, e.g. `"$1,234.56"`.
+pub(crate) struct CsvFile<'a> {
+    path: &'a Path,
+    header: Vec<String>,
+    contents: String,
+}
+
+impl<'a> CsvFile<'a> {
+    pub(crate) fn read(path: &'a Path) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|why| format!("couldn't read {path:?}: {why}"))?;
+
+        let header = contents
+            .lines()
+            .next()
+            .ok_or_else(|| format!("{path:?} is empty"))?;
+        let header = split_fields(header);
+
+        Ok(Self {
+            path,
+            header,
+            contents,
+        })
+    }
+
+    /// The index of the column that goes by any of the names, ignoring case, if there is one.
+    pub(crate) fn column(&self, names: &[&str]) -> Option<usize> {
+        self.header
+            .iter()
+            .position(|column| names.iter().any(|name| column.eq_ignore_ascii_case(name)))
+    }
+
+    /// Like `column`, for a column that has to be there. It's referred to by its first name.
+    pub(crate) fn required_column(&self, names: &[&str]) -> Result<usize, String> {
+        self.column(names)
+            .ok_or_else(|| format!("{:?} has no `{}` column", self.path, names[0]))
+    }
+
+    /// Every line after the header, skipping the empty ones.
+    pub(crate) fn rows(&self) -> impl Iterator<Item = Row<'_>> {
+        self.contents
+            .lines()
+            .enumerate()
+            .skip(1)
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(idx, line)| Row {
+                path: self.path,
+                line: idx + 1,
+                fields: split_fields(line),
+            })
+    }
+}
+
+// the trimmed fields of the line. a quoted field goes on past commas until its closing quote, and
+// two quotes in a row within it are a quote of its own
+fn split_fields(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(ch) = chars.next() {
+        match ch {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field).trim().to_string()),
+            ch => field.push(ch),
+        }
+    }
+    fields.push(field.trim().to_string());
+
+    fields
+}
+
+pub(crate) struct Row<'a> {
+    path: &'a Path,
+    /// counted from 1, for the errors
+    line: usize,
+    fields: Vec<String>,
+}
+
+impl<'a> Row<'a> {
+    pub(crate) fn field(&self, column: usize) -> Result<&str, String> {
+        self.fields
+            .get(column)
+            .map(String::as_str)
+            .ok_or_else(|| format!("line {} of {:?} is missing columns", self.line, self.path))
+    }
+
+    /// The field parsed as a `T`, with `what` it is going in the error.
+    pub(crate) fn parse<T: FromStr>(&self, column: usize, what: &str) -> Result<T, String> {
+        self.field(column)?.parse().map_err(|_| self.invalid(what))
+    }
+
+    pub(crate) fn invalid(&self, what: &str) -> String {
+        format!("invalid {what} on line {} of {:?}", self.line, self.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::split_fields;
+
+    #[test]
+    fn quoted_fields_keep_their_commas() {
+        assert_eq!(
+            split_fields(r#"AAPL, 10,"$1,234.56" ,2023-06-01"#),
+            ["AAPL", "10", "$1,234.56", "2023-06-01"]
+        );
+        assert_eq!(split_fields(r#""say ""hi""",,"#), [r#"say "hi""#, "", ""]);
+    }
+}
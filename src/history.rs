@@ -0,0 +1,123 @@
+use std::{collections::HashMap, path::Path};
+
+use chrono::{DateTime, Utc};
+use itertools::Itertools;
+use num_decimal::Num;
+
+use crate::{bars, csv_file::CsvFile, Symbol};
+
+// the header names each column can go by. the first is ours, the rest are the ones alpaca uses
+const SYMBOL_COLUMN: &[&str] = &["symbol", "S"];
+const TIME_COLUMN: &[&str] = &["timestamp", "t"];
+const OPEN_COLUMN: &[&str] = &["open", "o"];
+const HIGH_COLUMN: &[&str] = &["high", "h"];
+const LOW_COLUMN: &[&str] = &["low", "l"];
+const CLOSE_COLUMN: &[&str] = &["close", "c"];
+const VOLUME_COLUMN: &[&str] = &["volume", "v"];
+const VWAP_COLUMN: &[&str] = &["vwap", "vw"];
+
+/// A bar without a vwap, for the bars that are made up or put together from something else.
+pub(crate) fn new_bar(
+    time: DateTime<Utc>,
+    open: &Num,
+    high: &Num,
+    low: &Num,
+    close: &Num,
+    volume: &Num,
+) -> bars::Bar {
+    bars::Bar {
+        time,
+        open: open.clone(),
+        close: close.clone(),
+        high: high.clone(),
+        low: low.clone(),
+        volume: volume.clone(),
+        vwap: None,
+    }
+}
+
+/// Reads the bars of every symbol in a CSV file, oldest first. The first line names the columns,
+/// which can be in any order and may use alpaca's single letter names. Other columns are ignored.
+pub(crate) fn load_bars_csv(path: &Path) -> Result<HashMap<Symbol, Vec<bars::Bar>>, String> {
+    let csv = CsvFile::read(path)?;
+
+    let symbol = csv.required_column(SYMBOL_COLUMN)?;
+    let time = csv.required_column(TIME_COLUMN)?;
+    let open = csv.required_column(OPEN_COLUMN)?;
+    let high = csv.required_column(HIGH_COLUMN)?;
+    let low = csv.required_column(LOW_COLUMN)?;
+    let close = csv.required_column(CLOSE_COLUMN)?;
+    let volume = csv.required_column(VOLUME_COLUMN)?;
+    // only saved since the bars have it, and it's empty when the bar didn't come with one
+    let vwap = csv.column(VWAP_COLUMN);
+
+    let mut results = HashMap::<Symbol, Vec<bars::Bar>>::new();
+
+    for row in csv.rows() {
+        let bar = bars::Bar {
+            vwap: match vwap {
+                Some(vwap) if !row.field(vwap)?.is_empty() => Some(row.parse(vwap, "vwap")?),
+                _ => None,
+            },
+            ..new_bar(
+                row.parse(time, "timestamp")?,
+                &row.parse(open, "open")?,
+                &row.parse(high, "high")?,
+                &row.parse(low, "low")?,
+                &row.parse(close, "close")?,
+                &row.parse(volume, "volume")?,
+            )
+        };
+
+        results
+            .entry(Symbol::from(row.field(symbol)?))
+            .or_default()
+            .push(bar);
+    }
+
+    for bars in results.values_mut() {
+        bars.sort_by_key(|bar| bar.time);
+    }
+
+    Ok(results)
+}
+
+/// Writes the bars of every symbol to a CSV file that `load_bars_csv` can read back, in symbol
+/// order.
+pub(crate) fn save_bars_csv(
+    path: &Path,
+    bars: &HashMap<Symbol, Vec<bars::Bar>>,
+) -> Result<(), String> {
+    let mut contents = format!(
+        "{},{},{},{},{},{},{},{}\n",
+        SYMBOL_COLUMN[0],
+        TIME_COLUMN[0],
+        OPEN_COLUMN[0],
+        HIGH_COLUMN[0],
+        LOW_COLUMN[0],
+        CLOSE_COLUMN[0],
+        VOLUME_COLUMN[0],
+        VWAP_COLUMN[0]
+    );
+
+    for (symbol, bars) in bars.iter().sorted_by(|(a, _), (b, _)| a.cmp(b)) {
+        for bar in bars {
+            contents.push_str(&format!(
+                "{},{},{},{},{},{},{},{}\n",
+                symbol.request_symbol(),
+                bar.time.to_rfc3339(),
+                bar.open,
+                bar.high,
+                bar.low,
+                bar.close,
+                bar.volume,
+                bar.vwap
+                    .as_ref()
+                    .map(ToString::to_string)
+                    .unwrap_or_default()
+            ));
+        }
+    }
+
+    std::fs::write(path, contents).map_err(|why| format!("couldn't write {path:?}: {why}"))
+}
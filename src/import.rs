@@ -0,0 +1,145 @@
+use std::path::Path;
+
+use chrono::{NaiveDate, NaiveTime, TimeZone, Utc};
+use num_decimal::Num;
+
+use crate::{csv_file::CsvFile, AccountState, Symbol};
+
+/// A position as a broker exported it.
+pub(crate) struct ImportedPosition {
+    symbol: Symbol,
+    quantity: Num,
+    /// what the whole position cost
+    cost_basis: Num,
+    acquired: Option<NaiveDate>,
+}
+
+/// Reads the positions from a CSV file. The first line names the columns, which have to include
+/// `symbol`, `quantity` and `cost_basis`, and can include `acquired` as a `YYYY-MM-DD` date.
+pub(crate) fn load_positions_csv(path: &Path) -> Result<Vec<ImportedPosition>, String> {
+    let csv = CsvFile::read(path)?;
+
+    let symbol = csv.required_column(&["symbol"])?;
+    let quantity = csv.required_column(&["quantity"])?;
+    let cost_basis = csv.required_column(&["cost_basis"])?;
+    let acquired = csv.column(&["acquired"]);
+
+    csv.rows()
+        .map(|row| -> Result<ImportedPosition, String> {
+            Ok(ImportedPosition {
+                symbol: Symbol::from(row.field(symbol)?),
+                quantity: row.parse(quantity, "quantity")?,
+                // brokers export it as money, e.g. `$1,234.56`
+                cost_basis: row
+                    .field(cost_basis)?
+                    .trim_start_matches('$')
+                    .replace(',', "")
+                    .parse()
+                    .map_err(|_| row.invalid("cost basis"))?,
+                acquired: match acquired {
+                    Some(acquired) => Some(row.parse(acquired, "date")?),
+                    None => None,
+                },
+            })
+        })
+        .collect()
+}
+
+/// Takes the buy in price and age of the account's positions from the imported ones. The account
+/// stays the authority on what's owned, so positions it doesn't have are left out, and ones whose
+/// quantity doesn't match are only warned about.
+pub(crate) fn apply_imported_positions(account: &AccountState, imported: Vec<ImportedPosition>) {
+    for position in imported {
+        let Some(mut pos) = account.positions.get_mut(&position.symbol) else {
+            tracing::warn!("{} isn't held, not importing it", position.symbol);
+            continue;
+        };
+
+        if pos.owned != position.quantity {
+            tracing::warn!(
+                "{} is imported with a quantity of {}, but {} are held",
+                position.symbol,
+                position.quantity,
+                pos.owned
+            );
+        }
+
+        if !position.quantity.is_zero() {
+            pos.buy_in_price = position.cost_basis / position.quantity;
+        }
+
+        if let Some(acquired) = position.acquired {
+            pos.timestamp = Utc.from_utc_datetime(&acquired.and_time(NaiveTime::MIN));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use dashmap::DashMap;
+    use num_decimal::Num;
+
+    use super::{apply_imported_positions, load_positions_csv};
+    use crate::{AccountState, Position, Symbol};
+
+    fn account(positions: &[(&str, i64)]) -> AccountState {
+        AccountState::new(
+            positions
+                .iter()
+                .map(|(symbol, owned)| {
+                    let pos = Position {
+                        owned: Num::from(*owned),
+                        ..Position::new(Utc::now())
+                    };
+                    (Symbol::from(*symbol), pos)
+                })
+                .collect::<DashMap<_, _>>(),
+        )
+    }
+
+    #[test]
+    fn imported_positions_take_their_cost_basis_and_age() {
+        let path = std::env::temp_dir().join("wall-street-wolf-imported-positions.csv");
+        std::fs::write(
+            &path,
+            "symbol,quantity,cost_basis,acquired\n\
+             AAPL,10,\"$1,234.50\",2023-06-01\n\
+             MSFT,2,600,2023-06-01\n\
+             TSLA,5,1000,2023-06-02\n",
+        )
+        .unwrap();
+        let imported = load_positions_csv(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(imported.len(), 3);
+
+        let account = account(&[("AAPL", 10), ("MSFT", 3)]);
+        apply_imported_positions(&account, imported);
+
+        let aapl = account.positions.get(&Symbol::from("AAPL")).unwrap();
+        assert_eq!(aapl.buy_in_price, Num::new(12345, 100));
+        assert_eq!(
+            aapl.timestamp,
+            Utc.with_ymd_and_hms(2023, 6, 1, 0, 0, 0).unwrap()
+        );
+
+        // the account stays the authority on what's owned
+        let msft = account.positions.get(&Symbol::from("MSFT")).unwrap();
+        assert_eq!(msft.owned, Num::from(3));
+        assert_eq!(msft.buy_in_price, Num::from(300));
+        assert!(account.positions.get(&Symbol::from("TSLA")).is_none());
+    }
+
+    #[test]
+    fn unreadable_cost_bases_are_rejected() {
+        let path = std::env::temp_dir().join("wall-street-wolf-bad-cost-basis.csv");
+        std::fs::write(&path, "symbol,quantity,cost_basis\nAAPL,10,lots\n").unwrap();
+        let loaded = load_positions_csv(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(loaded
+            .err()
+            .unwrap()
+            .contains("invalid cost basis on line 2"));
+    }
+}
@@ -1,46 +1,90 @@
+mod args;
 mod backend;
+mod backtest;
+mod bars;
+mod config;
+mod csv_file;
+mod history;
+mod import;
+mod metrics;
+mod pause;
 mod scrape;
+mod sectors;
+mod sizing;
 mod stats;
+mod strategy;
 mod wait;
 
 use std::{
-    fmt::{Debug, Display, Write},
-    sync::Arc,
-    time::{Duration, Instant},
+    collections::{HashMap, HashSet},
+    fmt::{Debug, Display},
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::Duration,
 };
 
 use apca::{
-    api::v2::order::{Amount, Side},
+    api::v2::order::{self, Amount, Side},
     data::v2::{bars::TimeFrame, Feed},
 };
+use chrono::{DateTime, NaiveTime, Utc};
 use dashmap::DashMap;
 use itertools::Itertools;
 use num_decimal::Num;
 use tracing_subscriber::{prelude::__tracing_subscriber_SubscriberExt, util::SubscriberInitExt};
 
 use crate::{
-    backend::{Backend, LiveBackend},
-    stats::Statistics,
-    wait::{MarketStatus, Ticker},
+    args::{Args, DayEndPolicy, ExitOrder, StartupLiquidation, Validated},
+    backend::{Backend, BarTimeFrame, LiveBackend, PriceSource, Stats},
+    metrics::{IndicatorSnapshot, IndicatorSnapshots, RationaleLog},
+    pause::Pause,
+    scrape::ScrapePolicy,
+    sectors::SectorCap,
+    sizing::{AtrRiskSizing, EquitySizing},
+    stats::{f64_to_num, format_price, format_quantity, num_to_f64, Statistics},
+    strategy::Strategy,
+    wait::{long_break_ahead, saturating_std, MarketStatus, Ticker},
 };
 
 const KNOWN_CRYPTOS: &[&str] = &[
     "BTC", "ETH", "PAXG", "BCH", "AAVE", "LTC", "LINK", "UNI", "SHIB", "USDT",
 ];
 
+// the currencies crypto can be quoted in, longest first so that `USDT` isn't mistaken for `USD`
+const KNOWN_QUOTES: &[&str] = &["USDT", "USDC", "USD", "BTC"];
+
+const DEFAULT_QUOTE: &str = "USD";
+
+// ticks don't land exactly on time, so a symbol that's due this close to its next scan is scanned
+const SCAN_INTERVAL_SLACK: Duration = Duration::from_secs(1);
+
 #[derive(Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
 enum Symbol {
     Stock { ticker: String },
-    Crypto { ticker: String },
+    Crypto { ticker: String, quote: String },
 }
 
 impl Symbol {
     fn ticker(&self) -> &str {
         match self {
             Self::Stock { ticker } => ticker,
-            Self::Crypto { ticker } => ticker,
+            Self::Crypto { ticker, .. } => ticker,
+        }
+    }
+
+    // alpaca trades crypto as pairs, e.g. `BTC/USD`
+    fn crypto_pair(&self) -> Option<String> {
+        match self {
+            Self::Stock { .. } => None,
+            Self::Crypto { ticker, quote } => Some(format!("{ticker}/{quote}")),
         }
     }
+
+    // the symbol as alpaca expects it in orders and data requests
+    fn request_symbol(&self) -> String {
+        self.crypto_pair()
+            .unwrap_or_else(|| self.ticker().to_string())
+    }
 }
 
 impl Display for Symbol {
@@ -53,7 +97,9 @@ impl Debug for Symbol {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::Stock { ticker } => f.write_fmt(format_args!("Stock {}", ticker)),
-            Self::Crypto { ticker } => f.write_fmt(format_args!("Crypto {}", ticker)),
+            Self::Crypto { ticker, quote } => {
+                f.write_fmt(format_args!("Crypto {}/{}", ticker, quote))
+            }
         }
     }
 }
@@ -63,13 +109,31 @@ where
     S: Into<String> + Ord,
 {
     fn from(value: S) -> Self {
-        let mut value: String = value.into();
-        value.retain(|ch| ch.is_alphabetic());
+        let value: String = value.into();
+
+        // pairs come either as `BTC/USD` or as `BTCUSD`
+        let (mut ticker, quote) = match value.split_once('/') {
+            Some((base, quote)) => (base.to_string(), Some(quote.to_string())),
+            None => KNOWN_QUOTES
+                .iter()
+                .find_map(|quote| {
+                    value
+                        .strip_suffix(quote)
+                        .filter(|base| KNOWN_CRYPTOS.contains(base))
+                        .map(|base| (base.to_string(), Some(quote.to_string())))
+                })
+                .unwrap_or((value, None)),
+        };
+        // class shares keep their dot, e.g. `BRK.B`
+        ticker.retain(|ch| ch.is_alphabetic() || ch == '.');
+
+        if KNOWN_CRYPTOS.iter().any(|known| ticker.contains(known)) {
+            let mut quote = quote.unwrap_or_else(|| DEFAULT_QUOTE.to_string());
+            quote.retain(|ch| ch.is_alphabetic());
 
-        if KNOWN_CRYPTOS.iter().any(|known| value.contains(known)) {
-            Self::Crypto { ticker: value }
+            Self::Crypto { ticker, quote }
         } else {
-            Self::Stock { ticker: value }
+            Self::Stock { ticker }
         }
     }
 }
@@ -83,9 +147,13 @@ where
 // 3rd minute ...
 // 4th minute ...
 // 5th minute done!
+//
+// each repetition is a bar, which can span several of the time frame, e.g. 5 minutes
 #[derive(Debug, Clone, Copy)]
 struct TimePeriod {
     timeframe: TimeFrame,
+    // how many of the time frame a single bar spans
+    multiple: u32,
     len: u64,
 }
 
@@ -94,6 +162,7 @@ impl TimePeriod {
     fn minutes(len: u64) -> Self {
         Self {
             timeframe: TimeFrame::OneMinute,
+            multiple: 1,
             len,
         }
     }
@@ -102,6 +171,7 @@ impl TimePeriod {
     fn hours(len: u64) -> Self {
         Self {
             timeframe: TimeFrame::OneHour,
+            multiple: 1,
             len,
         }
     }
@@ -110,15 +180,79 @@ impl TimePeriod {
     fn days(len: u64) -> Self {
         Self {
             timeframe: TimeFrame::OneDay,
+            multiple: 1,
             len,
         }
     }
 
+    // bars of `multiple` of the time frame each, as far as alpaca can aggregate them
+    fn in_bars_of(self, multiple: u32) -> Result<Self, String> {
+        let max = match self.timeframe {
+            TimeFrame::OneMinute => 59,
+            TimeFrame::OneHour => 23,
+            TimeFrame::OneDay => 1,
+        };
+        if !(1..=max).contains(&multiple) {
+            return Err(format!("bars can only span 1 to {max} of the unit"));
+        }
+
+        Ok(Self { multiple, ..self })
+    }
+
     fn to_chrono(self) -> chrono::Duration {
+        chrono::Duration::seconds(self.bar_length().num_seconds() * self.len as i64)
+    }
+
+    // the length of a single bar
+    fn bar_length(self) -> chrono::Duration {
+        self.bar_timeframe().length()
+    }
+
+    // the time frame to ask alpaca for the bars in
+    fn bar_timeframe(self) -> BarTimeFrame {
+        BarTimeFrame {
+            unit: self.timeframe,
+            multiple: self.multiple,
+        }
+    }
+
+    // how old the latest bar may be before the indicators are considered stale
+    fn max_bar_age(self) -> chrono::Duration {
         match self.timeframe {
-            TimeFrame::OneMinute => chrono::Duration::minutes(self.len as i64),
-            TimeFrame::OneHour => chrono::Duration::hours(self.len as i64),
-            TimeFrame::OneDay => chrono::Duration::days(self.len as i64),
+            // there are no daily bars over the weekend
+            TimeFrame::OneDay => chrono::Duration::days(4),
+            _ => self.bar_length() * 2,
+        }
+    }
+}
+
+// written as a length and a unit, e.g. `14d`, `48h` or `90m`. bars spanning several of the unit
+// come after the number of them, e.g. `78x5m` for 78 bars of five minutes
+impl FromStr for TimePeriod {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some((len, bar)) = s.split_once('x') {
+            let bar = bar.parse::<Self>()?;
+            let len = len
+                .parse()
+                .map_err(|why| format!("invalid length: {why}"))?;
+            return Self { len, ..bar }.in_bars_of(u32::try_from(bar.len).unwrap_or(u32::MAX));
+        }
+
+        let unit = s
+            .chars()
+            .last()
+            .ok_or_else(|| "expected a length and a unit".to_string())?;
+        let len = s[..s.len() - unit.len_utf8()]
+            .parse()
+            .map_err(|why| format!("invalid length: {why}"))?;
+
+        match unit {
+            'm' => Ok(Self::minutes(len)),
+            'h' => Ok(Self::hours(len)),
+            'd' => Ok(Self::days(len)),
+            _ => Err("expected the unit to be `m`, `h` or `d`".to_string()),
         }
     }
 }
@@ -127,34 +261,355 @@ impl TimePeriod {
 struct Position {
     owned: Num,
     buy_in_price: Num,
-    timestamp: Instant,
+    /// when the position was opened
+    timestamp: DateTime<Utc>,
     order_in_progress: bool,
+    // whether part of the position has already been sold off
+    scaled_out: bool,
+    // how many market closes the position has been held through
+    sessions_held: usize,
+    // how long the position has been held while the market was closed
+    closed_for: Duration,
+    // the rungs of the profit ladder that have already been sold at
+    hit_rungs: Vec<usize>,
+    // the rungs that the sell in progress is for, which only count as sold at once it fills
+    pending_rungs: Vec<usize>,
+    // how much the sell in progress has sold so far and what it got for it, so that the close is
+    // recorded at the average price of all of its fills
+    sold_quantity: Num,
+    sold_value: Num,
+}
+
+impl Position {
+    // a position that doesn't own anything yet, as of `now`
+    fn new(now: DateTime<Utc>) -> Self {
+        Self {
+            owned: Num::from(0),
+            buy_in_price: Num::from(0),
+            timestamp: now,
+            order_in_progress: false,
+            scaled_out: false,
+            sessions_held: 0,
+            closed_for: Duration::ZERO,
+            hit_rungs: Vec::new(),
+            pending_rungs: Vec::new(),
+            sold_quantity: Num::from(0),
+            sold_value: Num::from(0),
+        }
+    }
+
+    // records what the sell in progress sold, at the average price of its fills
+    fn record_close(&mut self, journal: &mut SessionJournal, now: DateTime<Utc>) {
+        if self.sold_quantity.is_zero() {
+            return;
+        }
+
+        let sell_price = self.sold_value.clone() / self.sold_quantity.clone();
+        journal.record_close(
+            &self.buy_in_price,
+            &sell_price,
+            saturating_std(now - self.timestamp),
+        );
+
+        self.sold_quantity = Num::from(0);
+        self.sold_value = Num::from(0);
+    }
+}
+
+// upper bounds of the buckets used to sort closed positions by how long they were held.
+// anything held longer than the last bound ends up in an extra overflow bucket
+const HOLD_BUCKETS: &[Duration] = &[
+    Duration::from_secs(60 * 5),
+    Duration::from_secs(60 * 15),
+    Duration::from_secs(60 * 30),
+    Duration::from_secs(60 * 60),
+];
+
+// everything that happened to the account since the last end-of-day summary
+#[derive(Debug, Default)]
+struct SessionJournal {
+    trades: usize,
+    wins: usize,
+    losses: usize,
+    held: Vec<Duration>,
+    /// the profit or loss of every sell, by symbol
+    realized: HashMap<Symbol, Num>,
+}
+
+impl SessionJournal {
+    fn record_fill(&mut self) {
+        self.trades += 1;
+    }
+
+    fn record_close(&mut self, buy_in_price: &Num, sell_price: &Num, held: Duration) {
+        if sell_price > buy_in_price {
+            self.wins += 1;
+        } else {
+            self.losses += 1;
+        }
+        self.held.push(held);
+    }
+
+    fn record_realized(&mut self, symbol: &Symbol, pnl: Num) {
+        *self.realized.entry(symbol.clone()).or_default() += pnl;
+    }
+
+    // the realized profit or loss of each symbol, best first
+    fn realized_by_symbol(&self) -> Vec<(Symbol, Num)> {
+        self.realized
+            .iter()
+            .map(|(symbol, pnl)| (symbol.clone(), pnl.clone()))
+            .sorted_by(|(a_symbol, a), (b_symbol, b)| b.cmp(a).then_with(|| a_symbol.cmp(b_symbol)))
+            .collect()
+    }
+
+    fn average_hold(&self) -> Option<Duration> {
+        if self.held.is_empty() {
+            return None;
+        }
+
+        Some(self.held.iter().sum::<Duration>() / self.held.len() as u32)
+    }
+
+    fn hold_histogram(&self) -> Vec<usize> {
+        let mut histogram = vec![0; HOLD_BUCKETS.len() + 1];
+
+        for held in &self.held {
+            let bucket = HOLD_BUCKETS
+                .iter()
+                .position(|bound| held < bound)
+                .unwrap_or(HOLD_BUCKETS.len());
+            histogram[bucket] += 1;
+        }
+
+        histogram
+    }
 }
 
 #[derive(Debug)]
 struct AccountState {
     positions: DashMap<Symbol, Position>,
+    journal: Mutex<SessionJournal>,
+    // when the market closed with positions still held, until it opens again
+    closed_at: Mutex<Option<DateTime<Utc>>>,
+    // when each symbol was last sold at a loss
+    loss_exits: DashMap<Symbol, DateTime<Utc>>,
 }
 
-impl Display for AccountState {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_char('{')?;
-        for (idx, entry) in self.positions.iter().enumerate() {
-            let (symbol, position) = entry.pair();
-            f.write_str("\n  ")?;
-            Display::fmt(&symbol, f)?;
-            f.write_str(" (")?;
-            Display::fmt(&position.owned.to_f64().unwrap(), f)?;
-            write!(f, " @ ${:.2})", &position.buy_in_price.to_f64().unwrap())?;
-
-            if idx < self.positions.len() - 1 {
-                f.write_char(',')?;
-            } else {
-                f.write_char('\n')?;
+impl AccountState {
+    fn new(positions: DashMap<Symbol, Position>) -> Self {
+        Self {
+            positions,
+            journal: Default::default(),
+            closed_at: Default::default(),
+            loss_exits: DashMap::new(),
+        }
+    }
+
+    // keeps `watch_all` away from the symbol until the order watcher hears about its order
+    fn mark_order_in_progress(&self, symbol: Symbol, now: DateTime<Utc>) {
+        self.positions
+            .entry(symbol)
+            .or_insert_with(|| Position::new(now))
+            .order_in_progress = true;
+    }
+
+    // adds a fill of one of the symbol's orders to its position. `filled_quantity` is how much was
+    // filled since the last time the order was seen, at `fill_price` on average, as of `now`
+    fn apply_fill(
+        &self,
+        symbol: Symbol,
+        side: Side,
+        filled_quantity: &Num,
+        fill_price: Option<&Num>,
+        status: order::Status,
+        now: DateTime<Utc>,
+    ) {
+        let mut pos = self
+            .positions
+            .entry(symbol.clone())
+            .or_insert_with(|| Position::new(now));
+
+        pos.order_in_progress = !status.is_terminal();
+        if filled_quantity.is_zero() {
+            // a sell that ended without filling didn't sell at its rungs, one that ended partway
+            // still closed what it sold
+            if status.is_terminal() {
+                pos.pending_rungs.clear();
+                pos.record_close(&mut self.journal.lock().unwrap(), now);
+            }
+            return;
+        }
+
+        let fill_price = fill_price.cloned().unwrap_or_default();
+        let mut journal = self.journal.lock().unwrap();
+        // an order is only counted once, when its last fill comes in
+        if status.is_terminal() {
+            journal.record_fill();
+        }
+
+        match side {
+            Side::Buy if pos.owned.is_zero() => {
+                pos.owned = filled_quantity.clone();
+                pos.buy_in_price = fill_price;
+                pos.timestamp = now;
+                pos.scaled_out = false;
+                pos.sessions_held = 0;
+                pos.closed_for = Duration::ZERO;
+                pos.hit_rungs.clear();
+            }
+            Side::Buy => {
+                // adding to a position averages the buy in price over all of the shares
+                let owned = pos.owned.clone() + filled_quantity.clone();
+                pos.buy_in_price = (pos.owned.clone() * pos.buy_in_price.clone()
+                    + filled_quantity.clone() * fill_price)
+                    / owned.clone();
+                pos.owned = owned;
+            }
+            _ => {
+                let rungs = std::mem::take(&mut pos.pending_rungs);
+                pos.hit_rungs.extend(rungs);
+                pos.sold_quantity += filled_quantity.clone();
+                pos.sold_value += filled_quantity.clone() * fill_price.clone();
+                if status.is_terminal() {
+                    pos.record_close(&mut journal, now);
+                }
+                journal.record_realized(
+                    &symbol,
+                    (fill_price.clone() - pos.buy_in_price.clone()) * filled_quantity.clone(),
+                );
+                if fill_price < pos.buy_in_price {
+                    self.loss_exits.insert(symbol.clone(), now);
+                }
+
+                // a sell might only close part of the position
+                pos.owned -= filled_quantity.clone();
+                if pos.owned > Num::from(0) {
+                    pos.scaled_out = true;
+                } else {
+                    pos.owned = Num::from(0);
+                }
+            }
+        }
+    }
+
+    // how long ago the symbol was sold at a loss, if that was within the window. only the sells
+    // seen since starting up are known
+    fn sold_at_loss_within(
+        &self,
+        symbol: &Symbol,
+        window: Duration,
+        now: DateTime<Utc>,
+    ) -> Option<Duration> {
+        self.loss_exits
+            .get(symbol)
+            .map(|sold_at| saturating_std(now - *sold_at))
+            .filter(|since| *since < window)
+    }
+
+    // lets `watch_all` trade the symbol again once its order is done without filling any further.
+    // a sell that was cancelled partway still closed what it sold
+    fn finish_order(&self, symbol: &Symbol, now: DateTime<Utc>) {
+        if let Some(mut pos) = self.positions.get_mut(symbol) {
+            pos.order_in_progress = false;
+            pos.record_close(&mut self.journal.lock().unwrap(), now);
+        }
+    }
+
+    // counts another market close for every position that's still open
+    fn end_session(&self, now: DateTime<Utc>) {
+        for mut pos in self.positions.iter_mut() {
+            if !pos.owned.is_zero() {
+                pos.sessions_held += 1;
+            }
+        }
+
+        *self.closed_at.lock().unwrap() = Some(now);
+    }
+
+    // takes the time the market was closed for off of how long the positions have been held, once
+    // it opens again
+    fn start_session(&self, now: DateTime<Utc>) {
+        let Some(closed_at) = self.closed_at.lock().unwrap().take() else {
+            return;
+        };
+
+        let closed_for = saturating_std(now - closed_at);
+        for mut pos in self.positions.iter_mut() {
+            if !pos.owned.is_zero() {
+                pos.closed_for += closed_for;
             }
         }
-        f.write_char('}')?;
-        Ok(())
+    }
+
+    // a copy of every position, ordered by symbol so that anything going through them is
+    // reproducible
+    fn sorted_positions(&self) -> Vec<(Symbol, Position)> {
+        let mut positions = self
+            .positions
+            .iter()
+            .map(|entry| (entry.key().clone(), entry.value().clone()))
+            .collect::<Vec<_>>();
+        positions.sort_by(|(a, _), (b, _)| a.cmp(b));
+        positions
+    }
+
+    // returns the journal for the day so far, starting a fresh one
+    fn take_journal(&self) -> SessionJournal {
+        std::mem::take(&mut *self.journal.lock().unwrap())
+    }
+
+    // what the position in the symbol is worth, if we know its price
+    fn position_value(&self, symbol: &Symbol, prices: &HashMap<Symbol, Num>) -> Option<Num> {
+        let price = prices.get(symbol)?;
+
+        Some(
+            self.positions
+                .get(symbol)
+                .map_or_else(Num::default, |pos| pos.owned.clone() * price.clone()),
+        )
+    }
+
+    // what all positions with a known price are worth together
+    fn total_owned_value(&self, prices: &HashMap<Symbol, Num>) -> Num {
+        self.positions
+            .iter()
+            .filter_map(|entry| {
+                let price = prices.get(entry.key())?;
+                Some(entry.owned.clone() * price.clone())
+            })
+            .fold(Num::default(), |total, value| total + value)
+    }
+
+    // the share of the total owned value that is held in the symbol
+    #[allow(unused)]
+    fn position_weight(&self, symbol: &Symbol, prices: &HashMap<Symbol, Num>) -> Option<f64> {
+        let value = self.position_value(symbol, prices)?;
+        let total = self.total_owned_value(prices);
+
+        if total.is_zero() {
+            return None;
+        }
+
+        (value / total).to_f64()
+    }
+}
+
+impl Display for AccountState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let positions = self.sorted_positions();
+
+        write!(
+            f,
+            "{{{}\n}}",
+            positions
+                .iter()
+                .format_with(",", |(symbol, position), f| f(&format_args!(
+                    "\n  {symbol} ({} @ ${})",
+                    format_quantity(&position.owned),
+                    format_price(symbol, &position.buy_in_price)
+                )))
+        )
     }
 }
 
@@ -169,145 +624,1911 @@ async fn main() {
         .with(tracing_subscriber::fmt::layer())
         .init();
 
+    let args = match Args::from_env() {
+        Ok(args) => args,
+        Err(why) => {
+            tracing::error!("{why}");
+            std::process::exit(2);
+        }
+    };
+
+    if let Some(path) = &args.config {
+        tracing::info!("filling in the strategy from {path:?}");
+    }
+
+    // nothing is started with a config that would fail halfway through, or trade nonsense
+    let validated = match args.validate() {
+        Ok(validated) => validated,
+        Err(problems) => {
+            for problem in &problems {
+                tracing::error!("{problem}");
+            }
+            std::process::exit(2);
+        }
+    };
+
+    if args.validate_config {
+        tracing::info!("The config is valid");
+        return;
+    }
+
+    if let Some(path) = &args.replay {
+        if let Err(why) = backend::replay(path, args.endpoint.as_deref()) {
+            tracing::error!("{why}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let Validated {
+        strategy,
+        sector_cap,
+        equity_sizing,
+        max_concentration_pct,
+        atr_risk_sizing,
+        wash_sale_window,
+        max_clock_age,
+        limit_offset_bps,
+        strategy_tag,
+        trading_windows,
+        feeds,
+        api_info,
+        download_range,
+        imported_positions,
+    } = validated;
+
+    let blacklist = args.blacklist();
+    let pause = Pause::new();
+    let indicators = Arc::new(IndicatorSnapshots::new());
+    let rationale_log = args.rationale_log.as_ref().map(|path| {
+        RationaleLog::open(path).unwrap_or_else(|why| {
+            tracing::error!("Couldn't open the rationale log at {path:?}: {why}");
+            std::process::exit(1);
+        })
+    });
+
+    let last_scanned = DashMap::new();
+    let atr_stops = DashMap::new();
+
+    let tick_period = Duration::from_secs_f32(60.0 * 1.5);
+    let mut scan_options = ScanOptions {
+        period: args.period.unwrap_or(TimePeriod::days(14)),
+        confirm_period: args.confirm_period,
+        bar_limit: args.bar_limit,
+        feeds,
+        use_snapshots: args.snapshots,
+        price_source: args.price_source,
+        max_concentration_pct,
+        sector_cap: sector_cap.as_ref(),
+        blacklist: &blacklist,
+        sell_only: HashSet::new(),
+        pause: &pause,
+        max_spread_pct: args.max_spread_pct.clone(),
+        equity_sizing,
+        atr_risk_sizing,
+        atr_stops: &atr_stops,
+        min_price: args.min_price.clone(),
+        max_price: args.max_price.clone(),
+        confirm_timeout: args.confirm_timeout.map(Duration::from_secs_f64),
+        max_open_orders: args.max_open_orders,
+        recheck_buying_power: args.recheck_buying_power,
+        fractional_shares: args.fractional_shares,
+        exit_order: args.exit_order,
+        exit_limit_timeout: Duration::from_secs_f64(args.exit_limit_timeout.unwrap_or(30.0)),
+        limit_offset_bps,
+        hard_stop_pct: args.hard_stop_pct.clone(),
+        min_scan_interval: args
+            .min_scan_interval
+            .map_or(tick_period, Duration::from_secs_f64),
+        scan_batch: args.scan_batch,
+        dry_run: false,
+        in_trading_window: true,
+        above_min_equity: true,
+        wash_sale_window,
+        skip_wash_sales: args.skip_wash_sales,
+        last_scanned: &last_scanned,
+        indicators: &indicators,
+        rationale_log: rationale_log.as_ref(),
+        buy_notional: None,
+    };
+
+    if let Some(path) = &args.backtest {
+        let bars = match history::load_bars_csv(path) {
+            Ok(bars) => bars,
+            Err(why) => {
+                tracing::error!("{why}");
+                std::process::exit(1);
+            }
+        };
+
+        // there's no rate limit to stay under, every bar gets a scan unless asked otherwise
+        if args.min_scan_interval.is_none() {
+            scan_options.min_scan_interval = Duration::ZERO;
+        }
+
+        let report = backtest::run(
+            bars,
+            strategy.as_ref(),
+            &mut scan_options,
+            &trading_windows,
+            args.day_end_policy,
+            args.min_equity.as_ref(),
+        )
+        .await;
+        tracing::info!(
+            "Backtest ended with ${:.2} equity, {:.2}% return, {:.2}% max drawdown, {} trades",
+            num_to_f64(&report.final_equity),
+            report.total_return_pct,
+            report.max_drawdown_pct,
+            report.trades
+        );
+
+        if let Some(path) = &args.backtest_out {
+            if let Err(why) = report.save(path) {
+                tracing::error!("{why}");
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
     let _ = dotenv::dotenv();
 
-    let backend = Arc::new(LiveBackend::new().await);
+    let mut backend = match api_info {
+        Some(api_info) => LiveBackend::from_api_info(api_info).await,
+        None => LiveBackend::new().await,
+    };
 
-    let watch =
-        //scrape::all_stocks_within_price_range(&client, Num::new(3, 1)..Num::new(6, 1)).await;
-        scrape::all_top_stocks().await;
+    backend.adjust_bars_for(args.adjustment);
+    if args.incremental_bars {
+        backend.cache_bars();
+    }
 
-    let watch = watch[..watch.len().min(50)].iter().cloned().collect_vec();
+    // before the imported positions, which are this instance's own
+    if let Some(strategy_tag) = strategy_tag {
+        backend.tag_orders(strategy_tag).await;
+    }
 
-    backend.cancel_all_open_orders().await;
+    if let Some(imported) = imported_positions {
+        import::apply_imported_positions(backend.account_data(), imported);
+    }
+
+    if let Some(max_orders_per_minute) = args.max_orders_per_minute {
+        backend.limit_orders_per_minute(max_orders_per_minute);
+    }
+
+    if let Some(path) = &args.event_log {
+        if let Err(why) = backend.log_events_to(path) {
+            tracing::error!("Couldn't open the event log at {path:?}: {why}");
+            std::process::exit(1);
+        }
+    }
+
+    if let (Some(path), Some((symbols, from, to))) = (&args.download, download_range) {
+        let timeframe = args.period.unwrap_or(TimePeriod::days(14)).bar_timeframe();
+
+        let bars = match backend
+            .historical_bars(symbols, from, to, timeframe, scan_options.feeds[0])
+            .await
+        {
+            Ok(bars) => bars,
+            Err(why) => {
+                tracing::error!("Couldn't download the bars: {why}");
+                std::process::exit(1);
+            }
+        };
+        tracing::info!(
+            "Downloaded {} bars of {} symbols",
+            bars.values().map(Vec::len).sum::<usize>(),
+            bars.len()
+        );
+
+        if let Err(why) = history::save_bars_csv(path, &bars) {
+            tracing::error!("{why}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if args.list_positions {
+        list_positions(&backend, args.price_source).await;
+        return;
+    }
+
+    let backend = Arc::new(backend);
+
+    let watch_limit = args.watch_limit.unwrap_or(50);
+    let mut watch = match watch_list(args.scrape_policy, watch_limit).await {
+        Ok(watch) => watch,
+        Err(why) => {
+            tracing::error!("{why}");
+            std::process::exit(1);
+        }
+    };
+
+    // nothing would get traded, and liquidating unwatched positions would flatten the account
+    if watch.is_empty() {
+        tracing::error!("No symbols to watch, refusing to trade");
+        std::process::exit(1);
+    }
+
+    if args.resume {
+        // leave the orders alone, but don't trade over them either
+        let symbols = backend.open_order_symbols().await;
+        tracing::info!("Resuming with {} open orders", symbols.len());
+        for symbol in symbols {
+            backend
+                .account_data()
+                .mark_order_in_progress(symbol, backend.now());
+        }
+    } else {
+        backend.cancel_all_open_orders().await;
+    }
+
+    match args.startup_liquidation {
+        StartupLiquidation::None => {}
+        StartupLiquidation::Unwatched => {
+            tracing::info!("Selling positions that aren't being watched");
+            backend
+                .sell_all_positions(|s| !watch.contains(s) && !blacklist.contains(s))
+                .await
+        }
+        StartupLiquidation::All => {
+            tracing::info!("Selling all positions");
+            backend.sell_all_positions(|_| true).await
+        }
+    }
+
+    if args.stream_prices {
+        backend.stream_prices(watch.clone()).await;
+    }
+
+    let mut ticker = Ticker::new(backend.as_ref(), tick_period).await.unwrap();
+    if let Some(tz) = args.tz {
+        ticker.display_in(tz);
+    }
+    if let Some(max_clock_age) = max_clock_age {
+        ticker.refetch_clock_after(max_clock_age);
+    }
 
-    backend.sell_all_positions(|s| !watch.contains(s)).await;
+    pause.listen_for_signal();
 
-    let mut ticker = Ticker::new(backend.as_ref(), Duration::from_secs_f32(60.0 * 1.5))
-        .await
-        .unwrap();
+    if let Some(addr) = args.metrics_addr {
+        let indicators = indicators.clone();
+        tokio::spawn(async move {
+            if let Err(why) = metrics::serve(addr, indicators).await {
+                tracing::error!("metrics server stopped: {why}");
+            }
+        });
+    }
 
-    let period = TimePeriod::days(14);
+    let mut refresh_due = false;
+    let mut equity_check_due = true;
+    let mut warmup_ticks = args.warmup_ticks.unwrap_or(0);
 
     loop {
         match ticker.wait_for_open_or_tick(backend.as_ref()).await {
             MarketStatus::Open => {
-                backend.open().await;
+                open_session(
+                    backend.as_ref(),
+                    &mut scan_options,
+                    equity_check_due,
+                    args.min_equity.as_ref(),
+                )
+                .await;
+                equity_check_due = false;
+
+                if refresh_due {
+                    refresh_due = false;
+                    refresh_watch_list(
+                        backend.as_ref(),
+                        &mut watch,
+                        &mut scan_options.sell_only,
+                        &args,
+                        watch_limit,
+                        &blacklist,
+                    )
+                    .await;
+                    backend.forget_bars_except(&watch);
+                    if args.stream_prices {
+                        backend.stream_prices(watch.clone()).await;
+                    }
+                }
+
+                // the dropped symbols are let go of once they're sold off
+                let account = backend.account_data();
+                let sold_off = scan_options
+                    .sell_only
+                    .iter()
+                    .filter(|symbol| {
+                        account
+                            .positions
+                            .get(*symbol)
+                            .map_or(true, |pos| pos.owned.is_zero())
+                    })
+                    .cloned()
+                    .collect::<Vec<_>>();
+                for symbol in &sold_off {
+                    scan_options.sell_only.remove(symbol);
+                }
+                watch.retain(|symbol| !sold_off.contains(symbol));
+
+                // the first scans after a restart only show what would be traded
+                scan_options.dry_run = warmup_ticks > 0;
+                warmup_ticks = warmup_ticks.saturating_sub(1);
+                scan_options.in_trading_window =
+                    in_trading_window(&trading_windows, ticker.exchange_time());
 
                 tracing::debug!("measuring trends...");
                 watch_all(
                     backend.as_ref(),
+                    strategy.as_ref(),
                     watch.clone(),
-                    period,
-                    30.0..70.0,
-                    Duration::from_secs(60 * 30),
-                    Num::new(9, 10)..Num::new(15, 10),
+                    &scan_options,
                 )
                 .await;
             }
             MarketStatus::AboutToClose => {
-                backend.cancel_all_open_orders().await;
+                // the universe is scraped again before the next day's first scan
+                refresh_due = args.refresh_universe;
+                equity_check_due = true;
 
-                backend.sell_all_positions(|_| true).await;
-
-                let stats = backend.final_stats().await;
+                let stats = close_session(backend.as_ref(), args.day_end_policy, &blacklist).await;
 
                 tracing::info!(
                     "Day ended with ${:.2} equity, an increase of ${:.2} over yesterday",
-                    stats.current_equity.to_f64().unwrap(),
-                    (stats.current_equity - stats.last_equity).to_f64().unwrap()
+                    num_to_f64(&stats.current_equity),
+                    num_to_f64(&(stats.current_equity - stats.last_equity))
+                );
+
+                tracing::info!(
+                    "{} trades today, {} wins / {} losses, positions held for {} on average",
+                    stats.trades,
+                    stats.wins,
+                    stats.losses,
+                    stats.average_hold.map_or("-".to_string(), |held| format!(
+                        "{:.1} min",
+                        held.as_secs_f64() / 60.0
+                    ))
+                );
+
+                if !stats.realized.is_empty() {
+                    tracing::info!(
+                        "P&L by symbol: {}",
+                        stats
+                            .realized
+                            .iter()
+                            .map(|(symbol, pnl)| format!("{symbol} ${:.2}", num_to_f64(pnl)))
+                            .join(", ")
+                    );
+                }
+
+                tracing::info!(
+                    "hold times: {}",
+                    HOLD_BUCKETS
+                        .iter()
+                        .map(|bound| format!("<{}m", bound.as_secs() / 60))
+                        .chain(std::iter::once("longer".to_string()))
+                        .zip(stats.hold_histogram.iter())
+                        .map(|(bucket, count)| format!("{bucket}: {count}"))
+                        .join(", ")
                 );
             }
         }
     }
 }
 
-async fn watch_all<I, S>(
-    backend: &(dyn Backend + Sync),
-    symbols: I,
-    period: TimePeriod,
-    rsi_range: std::ops::Range<f64>,
-    hold_limit: Duration,
-    profit_limit: std::ops::Range<Num>,
+// the start of every tick while the market is open. the buys are sized and the equity is checked
+// against the minimum on the first tick of the day
+async fn open_session<B>(
+    backend: &B,
+    scan_options: &mut ScanOptions<'_>,
+    first_of_day: bool,
+    min_equity: Option<&Num>,
 ) where
-    I: IntoIterator<Item = S>,
-    S: Into<Symbol>,
+    B: Backend + Sync,
 {
-    let account = backend.account_data();
+    backend.open().await;
+    backend.account_data().start_session(backend.now());
 
-    // alpaca sorts the latest price data by symbols, alphabetically.
-    // it's easier if our list of symbols is already sorted alphabetically,
-    // because then we don't have to deal with hashmaps
-    let mut symbols = symbols
-        .into_iter()
-        .map(|s| s.into())
-        .filter(|s| {
-            // filter out symbols with outstanding orders
-            account
-                .positions
-                .get(s)
-                .map_or(true, |pos| !pos.order_in_progress)
-        })
-        .collect::<Vec<Symbol>>();
-    symbols.sort();
+    if !first_of_day {
+        return;
+    }
 
-    let (all_bars, current_prices) = futures::join!(
-        backend.all_latest_bars(symbols.clone(), period, Feed::IEX),
-        backend.all_latest_prices(symbols)
-    );
+    // not `final_stats`, that would start a new journal for the day
+    let equity = backend.equity().await;
 
-    let now = Instant::now();
+    // every buy of the day gets the same amount, instead of shrinking as the positions are opened
+    scan_options.buy_notional = scan_options
+        .equity_sizing
+        .as_ref()
+        .map(|sizing| sizing.notional(&equity));
 
-    for (symbol, bars) in all_bars {
-        if bars.is_empty() {
-            continue;
+    if let Some(min_equity) = min_equity {
+        scan_options.above_min_equity = equity >= *min_equity;
+
+        if !scan_options.above_min_equity {
+            tracing::warn!(
+                "Equity of ${:.2} is below the minimum of ${:.2}, only managing the open positions today",
+                num_to_f64(&equity),
+                num_to_f64(min_equity)
+            );
         }
+    }
+}
 
-        let current_price = current_prices[&symbol].clone();
-        let current_price_float = current_price.to_f64().unwrap();
-        let bb = bars.bollinger().unwrap();
-        let rsi = bars.rsi().unwrap();
+// cancels whatever is still open and sells what the day end policy says to, returning how the day
+// went
+async fn close_session<B>(
+    backend: &B,
+    day_end_policy: DayEndPolicy,
+    blacklist: &HashSet<Symbol>,
+) -> Stats
+where
+    B: Backend + Sync,
+{
+    backend.cancel_all_open_orders().await;
 
-        tracing::debug!(
-            "{:<5} | (${:.2}) | bb {:.2} < {:.2} < {:.2} | rsi {:.2}",
-            symbol,
-            current_price_float,
-            bb.lower,
-            bb.average,
-            bb.upper,
-            rsi
-        );
+    match day_end_policy {
+        DayEndPolicy::SellAll => backend.sell_all_positions(|s| !blacklist.contains(s)).await,
+        DayEndPolicy::HoldAll => {}
+        DayEndPolicy::HoldWeeknights => {
+            if long_break_ahead(backend).await {
+                tracing::info!("The market stays closed for more than a night, selling everything");
+                backend.sell_all_positions(|s| !blacklist.contains(s)).await
+            }
+        }
+    }
+    // the blacklisted positions are held through the close no matter the policy, the time the
+    // market is closed for has to be taken off of theirs too
+    backend.account_data().end_session(backend.now());
 
-        let position = account.positions.get(&symbol.clone());
+    backend.final_stats().await
+}
 
-        let all_owned = position
-            .as_ref()
-            .map(|pos| pos.owned.clone())
-            .unwrap_or_default();
-        let held_too_long = position
-            .as_ref()
-            .map_or(false, |pos| now.duration_since(pos.timestamp) > hold_limit);
-        let profit_limit_reached =
-            position
-                .filter(|pos| !pos.buy_in_price.is_zero())
-                .map_or(false, |pos| {
-                    let profit = current_price / pos.buy_in_price.clone();
+// the symbols to watch, scraped from the top stocks
+async fn watch_list(policy: ScrapePolicy, watch_limit: usize) -> Result<Vec<Symbol>, String> {
+    let watch =
+        //scrape::all_stocks_within_price_range(&client, Num::new(3, 1)..Num::new(6, 1)).await;
+        scrape::all_top_stocks(policy).await?;
 
-                    !profit_limit.contains(&profit)
-                });
+    Ok(watch[..watch.len().min(watch_limit)].to_vec())
+}
 
-        if all_owned.is_zero() && rsi < rsi_range.start && current_price_float < bb.lower {
-            backend
-                .submit_order(symbol, Side::Buy, Amount::quantity(1))
-                .await
-        } else if !all_owned.is_zero()
-            && (held_too_long
-                || profit_limit_reached
-                || (rsi > rsi_range.end && current_price_float > bb.upper))
-        {
-            backend
-                .submit_order(symbol, Side::Sell, Amount::quantity(all_owned))
-                .await
+// scrapes the watch list again, selling the positions of the symbols that dropped off if asked to.
+// the ones that are kept stay watched until they're sold, or nothing would ever sell them
+async fn refresh_watch_list(
+    backend: &LiveBackend,
+    watch: &mut Vec<Symbol>,
+    sell_only: &mut HashSet<Symbol>,
+    args: &Args,
+    watch_limit: usize,
+    blacklist: &HashSet<Symbol>,
+) {
+    let refreshed = match watch_list(args.scrape_policy, watch_limit).await {
+        Ok(refreshed) if !refreshed.is_empty() => refreshed,
+        Ok(_) => {
+            tracing::warn!("The refreshed watch list is empty, keeping the old one");
+            return;
         }
+        Err(why) => {
+            tracing::warn!("Couldn't refresh the watch list, keeping the old one: {why}");
+            return;
+        }
+    };
+
+    let dropped = watch
+        .iter()
+        .filter(|symbol| !refreshed.contains(symbol))
+        .cloned()
+        .collect::<HashSet<_>>();
+    let added = refreshed
+        .iter()
+        .filter(|symbol| !watch.contains(symbol))
+        .count();
+    tracing::info!(
+        "Refreshed the watch list, {added} symbols were added and {} dropped",
+        dropped.len()
+    );
+
+    if args.liquidate_dropped {
+        backend
+            .sell_all_positions(|s| dropped.contains(s) && !blacklist.contains(s))
+            .await;
+    }
+
+    // the positions that are still open stay watched so that they're still managed, and with
+    // `--liquidate-dropped` nothing more is bought of them until their sells fill
+    let account = backend.account_data();
+    let still_held = dropped
+        .into_iter()
+        .filter(|symbol| {
+            account
+                .positions
+                .get(symbol)
+                .map_or(false, |pos| !pos.owned.is_zero())
+        })
+        .collect::<Vec<_>>();
+    sell_only.retain(|symbol| !refreshed.contains(symbol));
+    if args.liquidate_dropped {
+        sell_only.extend(still_held.iter().cloned());
+    }
+    *watch = refreshed.into_iter().chain(still_held).collect();
+}
+
+// prints every position along with what it's worth now and how much it's up or down
+async fn list_positions(backend: &(dyn Backend + Sync), price_source: PriceSource) {
+    let account = backend.account_data();
+    println!("{account}");
+
+    let positions = account.sorted_positions();
+    let prices = backend
+        .all_latest_prices(
+            positions.iter().map(|(symbol, _)| symbol.clone()).collect(),
+            price_source,
+        )
+        .await;
+
+    for (symbol, position) in positions {
+        let Some(price) = prices.get(&symbol) else {
+            println!("{symbol:<9} no price");
+            continue;
+        };
+
+        let value = position.owned.clone() * price.clone();
+        let cost = position.owned.clone() * position.buy_in_price.clone();
+        println!(
+            "{symbol:<9} ${:>12.2} | P&L ${:>10.2}",
+            num_to_f64(&value),
+            num_to_f64(&(value - cost))
+        );
+    }
+}
+
+/// How `watch_all` looks at the market, and which buys it holds back on.
+struct ScanOptions<'a> {
+    period: TimePeriod,
+    /// a longer period whose bars have to agree with every buy
+    confirm_period: Option<TimePeriod>,
+    /// the most bars to fetch per symbol
+    bar_limit: Option<usize>,
+    /// the feeds to get bars from, in order of preference
+    feeds: Vec<Feed>,
+    /// get prices and the latest bar from snapshots
+    use_snapshots: bool,
+    price_source: PriceSource,
+    /// no single position is allowed to be worth more than this percent of the equity
+    max_concentration_pct: Num,
+    sector_cap: Option<&'a SectorCap>,
+    /// symbols that are never traded
+    blacklist: &'a HashSet<Symbol>,
+    /// symbols that dropped off the watch list, which are only sold until they're flat
+    sell_only: HashSet<Symbol>,
+    pause: &'a Pause,
+    /// don't buy when the bid and ask are further apart than this percent of the mid price
+    max_spread_pct: Option<Num>,
+    /// size buys from the equity instead of taking the strategy's amount, and cap how many
+    /// positions are open at once
+    equity_sizing: Option<EquitySizing>,
+    /// the amount of every buy of the session, sized from the equity at its open
+    buy_notional: Option<Num>,
+    /// size buys by how much would be lost at a stop below the entry, instead of either
+    atr_risk_sizing: Option<AtrRiskSizing>,
+    /// the stops of the positions that were sized by their atr
+    atr_stops: &'a DashMap<Symbol, Num>,
+    /// only buy symbols whose price is within these bounds
+    min_price: Option<Num>,
+    max_price: Option<Num>,
+    /// how long to wait for each order to fill before moving on. `None` doesn't wait at all
+    confirm_timeout: Option<Duration>,
+    /// the most orders to have open at once. only buys are held back, never sells
+    max_open_orders: Option<usize>,
+    /// ask for the buying power before every buy, instead of trusting the equity from the start
+    /// of the pass
+    recheck_buying_power: bool,
+    /// size buys of the stocks that are fractionable in fractions of a share. cryptos always are,
+    /// everything else is bought in whole shares
+    fractional_shares: bool,
+    /// the kind of order that positions are closed with, other than by the hard stop
+    exit_order: ExitOrder,
+    /// how long a limit exit may take to fill before the rest goes to market
+    exit_limit_timeout: Duration,
+    /// put the strategy's buys and sells in as limits this many basis points through the price
+    /// instead of at market. hard stops and flattening always go to market
+    limit_offset_bps: Option<Num>,
+    /// close a position as soon as it's this many percent below its buy in price
+    hard_stop_pct: Option<Num>,
+    /// symbols that aren't held are only looked at again once this much time has passed
+    min_scan_interval: Duration,
+    /// the most symbols that aren't held to look at per pass. the rest wait for a later pass
+    scan_batch: Option<usize>,
+    /// log what would be traded without submitting any orders
+    dry_run: bool,
+    /// whether it's a time of day that buys are allowed at
+    in_trading_window: bool,
+    /// whether the equity was at least the minimum when the day started. no positions are opened
+    /// otherwise
+    above_min_equity: bool,
+    /// warn about buying back symbols that were sold at a loss within this long, since it would be
+    /// a wash sale in a taxable account
+    wash_sale_window: Option<Duration>,
+    /// skip those buys instead of only warning about them
+    skip_wash_sales: bool,
+    /// when each symbol was last looked at
+    last_scanned: &'a DashMap<Symbol, DateTime<Utc>>,
+    /// the indicators of each symbol as of the last time it was evaluated
+    indicators: &'a IndicatorSnapshots,
+    /// where to write down every decision and why it was made
+    rationale_log: Option<&'a RationaleLog>,
+}
+
+async fn watch_all<I, S>(
+    backend: &(dyn Backend + Sync),
+    strategy: &dyn Strategy,
+    symbols: I,
+    options: &ScanOptions<'_>,
+) where
+    I: IntoIterator<Item = S>,
+    S: Into<Symbol>,
+{
+    let account = backend.account_data();
+    let now = backend.now();
+
+    let symbols = symbols
+        .into_iter()
+        .map(|s| s.into())
+        .filter(|s| !options.blacklist.contains(s))
+        .filter(|s| {
+            // filter out symbols with outstanding orders
+            account
+                .positions
+                .get(s)
+                .map_or(true, |pos| !pos.order_in_progress)
+        });
+
+    // open positions are managed on every pass, only the market scan is slowed down
+    let (mut symbols, mut unheld): (Vec<Symbol>, Vec<Symbol>) = symbols.partition(|s| {
+        account
+            .positions
+            .get(s)
+            .map_or(false, |pos| !pos.owned.is_zero())
+    });
+
+    unheld.retain(|s| {
+        options.last_scanned.get(s).map_or(true, |scanned| {
+            let since = now.signed_duration_since(*scanned).to_std();
+            since.unwrap_or_default() + SCAN_INTERVAL_SLACK >= options.min_scan_interval
+        })
+    });
+
+    if let Some(scan_batch) = options.scan_batch {
+        // the symbols that were looked at the longest ago go first, so every symbol gets its turn
+        unheld.sort_by_key(|s| options.last_scanned.get(s).map(|scanned| *scanned));
+        unheld.truncate(scan_batch);
+    }
+
+    // alpaca sorts the latest price data by symbols, alphabetically.
+    // it's easier if our list of symbols is already sorted alphabetically,
+    // because then we don't have to deal with hashmaps
+    symbols.extend(unheld);
+    symbols.sort();
+
+    for symbol in &symbols {
+        options.last_scanned.insert(symbol.clone(), now);
+    }
+
+    let period = options.period;
+
+    let (mut all_bars, (current_prices, recent_bars), quotes, confirm_bars, equity) = futures::join!(
+        backend.all_latest_bars(
+            symbols.clone(),
+            period,
+            &options.feeds,
+            strategy.min_bars(),
+            options.bar_limit
+        ),
+        latest_prices(backend, symbols.clone(), options),
+        async {
+            // the quotes are only needed for the spread
+            match options.max_spread_pct {
+                Some(_) => backend.all_latest_quotes(symbols.clone()).await,
+                None => HashMap::new(),
+            }
+        },
+        async {
+            match options.confirm_period {
+                Some(confirm_period) => {
+                    backend
+                        .all_latest_bars(
+                            symbols.clone(),
+                            confirm_period,
+                            &options.feeds,
+                            strategy.min_bars(),
+                            options.bar_limit,
+                        )
+                        .await
+                }
+                None => HashMap::new(),
+            }
+        },
+        backend.equity()
+    );
+
+    for (symbol, bar) in recent_bars {
+        if let Some(bars) = all_bars.get_mut(&symbol) {
+            merge_recent_bar(bars, bar);
+        }
+    }
+
+    let paused = options.pause.is_paused();
+    if paused {
+        tracing::info!("Paused, only managing the open positions");
+    }
+    if !paused && !options.in_trading_window {
+        tracing::debug!("Outside of the trading windows, only managing the open positions");
+    }
+
+    // the orders submitted this pass count too, the order watcher may not have heard of them yet
+    let mut open_orders = account
+        .positions
+        .iter()
+        .filter(|pos| pos.order_in_progress)
+        .count();
+
+    // the buys of this pass count too, the order watcher may not have heard of them yet
+    let mut open_positions = account
+        .positions
+        .iter()
+        .filter(|pos| !pos.owned.is_zero() || pos.order_in_progress)
+        .count();
+
+    // no single position is allowed to be worth more than this
+    let max_position_value =
+        equity.clone() * options.max_concentration_pct.clone() / Num::from(100);
+
+    for (symbol, bars) in all_bars {
+        let current_price = match current_prices.get(&symbol) {
+            Some(price) => price.clone(),
+            None => {
+                tracing::warn!("{symbol} has no latest price, skipping it");
+                continue;
+            }
+        };
+
+        // whether the buys and partial sells can be in fractions of a share
+        let fractional = match &symbol {
+            Symbol::Crypto { .. } => true,
+            Symbol::Stock { .. } => {
+                options.fractional_shares && backend.is_fractionable(&symbol).await
+            }
+        };
+
+        let position = account.positions.get(&symbol);
+        // the hard stop doesn't need any indicators, so it holds even when the bars are unusable
+        let stopped = options
+            .hard_stop_pct
+            .as_ref()
+            .and_then(|hard_stop_pct| hard_stop(position.as_deref(), &current_price, hard_stop_pct))
+            .or_else(|| {
+                let stop_price = options.atr_stops.get(&symbol)?;
+                stop_hit(position.as_deref(), &current_price, &stop_price)
+            });
+        let hard_stopped = stopped.is_some();
+        let values = strategy.indicator_values(&symbol, &bars, &current_price);
+        let (decision, conditions) = match stopped {
+            Some(order) => {
+                tracing::info!("{symbol} fell past its hard stop, closing it");
+                (Some(order), vec![("hard_stop", true)])
+            }
+            None if !usable_bars(&symbol, &bars, strategy, period, now) => continue,
+            None => strategy.decide(
+                &symbol,
+                position.as_deref(),
+                &bars,
+                &current_price,
+                now,
+                fractional,
+            ),
+        };
+        let mut snapshot = IndicatorSnapshot {
+            time: now,
+            price: num_to_f64(&current_price),
+            values,
+            decision: decision.as_ref().map(|(side, _)| *side),
+            conditions,
+            action: None,
+        };
+        let rungs = match (&decision, position.as_deref()) {
+            (Some((Side::Sell, _)), Some(pos)) => strategy.ladder_rungs(pos, &current_price),
+            _ => Vec::new(),
+        };
+        // the order watcher needs to be able to update the position while we're submitting
+        drop(position);
+
+        // every gate the decision goes through is added to its conditions as whether it passed, up
+        // to the first one that stops it
+        let gates = &mut snapshot.conditions;
+        // where the stop of a buy sized by its atr goes
+        let mut atr_stop = None;
+        let order = 'gates: {
+            let (side, amount) = match decision {
+                Some((Side::Buy, amount)) => {
+                    let sell_only = options.sell_only.contains(&symbol);
+                    gates.push(("not_paused", !paused));
+                    gates.push(("not_sell_only", !sell_only));
+                    gates.push(("in_trading_window", options.in_trading_window));
+                    gates.push(("above_min_equity", options.above_min_equity));
+                    if paused
+                        || sell_only
+                        || !options.in_trading_window
+                        || !options.above_min_equity
+                    {
+                        break 'gates None;
+                    }
+
+                    // positions that drifted out of the band can still be sold
+                    let too_cheap = options
+                        .min_price
+                        .as_ref()
+                        .map_or(false, |min_price| current_price < *min_price);
+                    let too_expensive = options
+                        .max_price
+                        .as_ref()
+                        .map_or(false, |max_price| current_price > *max_price);
+                    gates.push(("in_price_band", !too_cheap && !too_expensive));
+                    if too_cheap || too_expensive {
+                        tracing::debug!("{symbol} is outside of the price band");
+                        break 'gates None;
+                    }
+
+                    let sold_at_loss = options
+                        .wash_sale_window
+                        .and_then(|window| account.sold_at_loss_within(&symbol, window, now));
+                    gates.push(("no_cooldown", sold_at_loss.is_none()));
+                    if let Some(since) = sold_at_loss {
+                        tracing::warn!(
+                            "{symbol} was sold at a loss {:.1} days ago, buying it back could be a wash sale",
+                            since.as_secs_f64() / (24.0 * 60.0 * 60.0)
+                        );
+                        if options.skip_wash_sales {
+                            break 'gates None;
+                        }
+                    }
+
+                    if let Some(max_spread_pct) = &options.max_spread_pct {
+                        let spread_pct = quotes.get(&symbol).and_then(|quote| quote.spread_pct());
+                        let spread_ok =
+                            spread_pct.map_or(false, |spread_pct| spread_pct <= *max_spread_pct);
+                        gates.push(("spread_ok", spread_ok));
+                        if !spread_ok {
+                            tracing::debug!("{symbol}'s spread is too wide to buy into");
+                            break 'gates None;
+                        }
+                    }
+
+                    if options.confirm_period.is_some() {
+                        let confirm_bars = confirm_bars.get(&symbol).map_or(&[][..], Vec::as_slice);
+                        let confirmed = strategy.confirms_entry(&symbol, confirm_bars);
+                        gates.push(("confirmed", confirmed));
+                        if !confirmed {
+                            tracing::debug!("{symbol}'s longer period doesn't agree with buying");
+                            break 'gates None;
+                        }
+                    }
+
+                    if let Some(sector_cap) = options.sector_cap {
+                        let sector_has_room = !sector_cap.is_full(&symbol, account);
+                        gates.push(("sector_has_room", sector_has_room));
+                        if !sector_has_room {
+                            tracing::debug!("{symbol}'s sector already has too many positions");
+                            break 'gates None;
+                        }
+                    }
+
+                    if let Some(equity_sizing) = &options.equity_sizing {
+                        // adding to a position doesn't open another one
+                        let held = account
+                            .positions
+                            .get(&symbol)
+                            .map_or(false, |pos| !pos.owned.is_zero());
+                        let below_max_positions =
+                            held || open_positions < equity_sizing.max_positions;
+                        gates.push(("below_max_positions", below_max_positions));
+                        if !below_max_positions {
+                            tracing::debug!(
+                                "{symbol} has to wait, {open_positions} positions are already open"
+                            );
+                            break 'gates None;
+                        }
+                    }
+
+                    let amount = match (&options.buy_notional, &options.atr_risk_sizing) {
+                        (Some(notional), _) => sizing::shares_for_notional(
+                            notional.clone(),
+                            current_price.clone(),
+                            fractional,
+                        ),
+                        (None, Some(atr_sizing)) => {
+                            let Some(atr) = bars.atr(atr_sizing.atr_period).and_then(f64_to_num)
+                            else {
+                                tracing::debug!("{symbol} doesn't have the bars for an atr yet");
+                                gates.push(("atr_sizable", false));
+                                break 'gates None;
+                            };
+
+                            let quantity = atr_sizing.quantity(&equity, &atr, fractional);
+                            gates.push(("atr_sizable", !quantity.is_zero()));
+                            if quantity.is_zero() {
+                                tracing::debug!(
+                                    "{symbol}'s stop is too far away to risk buying it"
+                                );
+                                break 'gates None;
+                            }
+
+                            atr_stop = Some(atr_sizing.stop_price(&current_price, &atr));
+                            Amount::quantity(quantity)
+                        }
+                        (None, None) => amount,
+                    };
+
+                    let owned_value = account
+                        .position_value(&symbol, &current_prices)
+                        .unwrap_or_default();
+                    let order_value = sizing::amount_value(&amount, &current_price);
+
+                    let amount = if owned_value.clone() + order_value <= max_position_value {
+                        amount
+                    } else {
+                        // buy just enough to land on the cap
+                        sizing::shares_for_notional(
+                            max_position_value.clone() - owned_value,
+                            current_price.clone(),
+                            fractional,
+                        )
+                    };
+
+                    gates.push(("below_max_concentration", !sizing::is_empty(&amount)));
+                    if sizing::is_empty(&amount) {
+                        tracing::debug!("{symbol} is already at the maximum concentration");
+                        break 'gates None;
+                    }
+
+                    if let Some(max_open_orders) = options.max_open_orders {
+                        let below_max_open_orders = open_orders < max_open_orders;
+                        gates.push(("below_max_open_orders", below_max_open_orders));
+                        if !below_max_open_orders {
+                            tracing::debug!(
+                                "{symbol} has to wait, {open_orders} orders are still open"
+                            );
+                            break 'gates None;
+                        }
+                    }
+
+                    // the orders of this pass that haven't filled yet are already spending it
+                    if options.recheck_buying_power {
+                        let buying_power = backend.buying_power().await;
+                        let enough_buying_power =
+                            sizing::amount_value(&amount, &current_price) <= buying_power;
+                        gates.push(("enough_buying_power", enough_buying_power));
+                        if !enough_buying_power {
+                            tracing::debug!("not enough buying power left to buy {symbol}");
+                            break 'gates None;
+                        }
+                    }
+
+                    (Side::Buy, amount)
+                }
+                Some(order) => order,
+                None => break 'gates None,
+            };
+
+            gates.push(("not_dry_run", !options.dry_run));
+            if options.dry_run {
+                tracing::info!(
+                    "Warming up, would have submitted a {side:?} of {amount:?} for {symbol}"
+                );
+                break 'gates None;
+            }
+
+            Some((side, amount))
+        };
+
+        snapshot.action = order.as_ref().map(|(side, _)| *side);
+        tracing::debug!("{symbol:<5} | {}", snapshot.describe_conditions());
+        if let Some(rationale_log) = options.rationale_log {
+            rationale_log.record(&symbol, &snapshot);
+        }
+        options.indicators.insert(symbol.clone(), snapshot);
+
+        let Some((side, amount)) = order else {
+            continue;
+        };
+
+        if side == Side::Buy {
+            open_orders += 1;
+            open_positions += 1;
+        }
+        // each rung is only sold at once, even if the price comes back down and up again. they're
+        // counted as sold at when the sell fills
+        if !rungs.is_empty() {
+            if let Some(mut pos) = account.positions.get_mut(&symbol) {
+                pos.pending_rungs = rungs;
+            }
+        }
+        if let Some(atr_stop) = atr_stop {
+            options.atr_stops.insert(symbol.clone(), atr_stop);
+        }
+
+        if let (Side::Sell, ExitOrder::Limit, false, Amount::Quantity { quantity }) =
+            (side, options.exit_order, hard_stopped, &amount)
+        {
+            backend
+                .submit_limit_order_or_market(
+                    symbol,
+                    side,
+                    quantity.clone(),
+                    current_price,
+                    options.exit_limit_timeout,
+                )
+                .await;
+            continue;
+        }
+
+        // orders for a dollar amount can only go to market
+        let limit_price = match (&options.limit_offset_bps, hard_stopped, &amount) {
+            (Some(offset_bps), false, Amount::Quantity { .. }) => Some(sizing::marketable_limit(
+                &current_price,
+                side,
+                offset_bps,
+                matches!(symbol, Symbol::Crypto { .. }),
+            )),
+            _ => None,
+        };
+
+        match options.confirm_timeout {
+            Some(timeout) => {
+                let order = backend
+                    .submit_order_and_wait(symbol.clone(), side, amount, limit_price, timeout)
+                    .await;
+                if let Some(order) = order {
+                    tracing::debug!(
+                        "{symbol} order ended up {:?} with {} filled",
+                        order.status,
+                        order.filled_quantity
+                    );
+                }
+            }
+            None => {
+                backend
+                    .submit_order(symbol, side, amount, limit_price)
+                    .await
+            }
+        }
+    }
+}
+
+// whether buys are allowed at this time of day. no windows means they always are
+fn in_trading_window(windows: &[(NaiveTime, NaiveTime)], time: NaiveTime) -> bool {
+    windows.is_empty()
+        || windows
+            .iter()
+            .any(|(start, end)| (*start..*end).contains(&time))
+}
+
+// whether there are enough bars, and recent enough ones, for the strategy to go off of
+fn usable_bars(
+    symbol: &Symbol,
+    bars: &[bars::Bar],
+    strategy: &dyn Strategy,
+    period: TimePeriod,
+    now: DateTime<Utc>,
+) -> bool {
+    if bars.len() < strategy.min_bars() {
+        tracing::debug!(
+            "{symbol} only has {} bars, at least {} are needed",
+            bars.len(),
+            strategy.min_bars()
+        );
+        return false;
+    }
+
+    if bars.closes().is_none() {
+        tracing::warn!("{symbol} has a bar with an unusable close, skipping it");
+        return false;
+    }
+
+    let bar_age = now.signed_duration_since(bars.last().unwrap().time);
+    if bar_age > period.max_bar_age() {
+        tracing::warn!(
+            "{symbol} hasn't had a new bar in {} minutes, skipping it",
+            bar_age.num_minutes()
+        );
+        return false;
+    }
+
+    true
+}
+
+// sells the whole position once the price is more than `hard_stop_pct` percent below the buy in
+// price, whatever the strategy thinks
+fn hard_stop(
+    position: Option<&Position>,
+    price: &Num,
+    hard_stop_pct: &Num,
+) -> Option<(Side, Amount)> {
+    let buy_in_price = &position?.buy_in_price;
+    if buy_in_price.is_zero() {
+        return None;
+    }
+    let stop_price =
+        buy_in_price.clone() * (Num::from(100) - hard_stop_pct.clone()) / Num::from(100);
+
+    stop_hit(position, price, &stop_price)
+}
+
+// sells the whole position once the price is below the stop
+fn stop_hit(position: Option<&Position>, price: &Num, stop_price: &Num) -> Option<(Side, Amount)> {
+    let position = position.filter(|pos| !pos.owned.is_zero())?;
+
+    (*price < *stop_price).then(|| (Side::Sell, Amount::quantity(position.owned.clone())))
+}
+
+// the latest price of every symbol. snapshots also come with the bar that's currently forming,
+// which saves having to wait for the bars endpoint to catch up
+async fn latest_prices(
+    backend: &(dyn Backend + Sync),
+    symbols: Vec<Symbol>,
+    options: &ScanOptions<'_>,
+) -> (HashMap<Symbol, Num>, HashMap<Symbol, bars::Bar>) {
+    if !options.use_snapshots {
+        let prices = backend
+            .all_latest_prices(symbols, options.price_source)
+            .await;
+        return (prices, HashMap::new());
+    }
+
+    let snapshots = backend.snapshots(symbols).await;
+
+    let prices = snapshots
+        .iter()
+        .filter_map(|(symbol, snapshot)| {
+            let price = match options.price_source {
+                PriceSource::LastTrade => snapshot.latest_trade.as_ref()?.price.clone(),
+                PriceSource::QuoteMid => snapshot.latest_quote.as_ref()?.mid_price()?,
+            };
+            Some((symbol.clone(), price))
+        })
+        .collect();
+
+    let recent_bars = snapshots
+        .into_iter()
+        .filter_map(|(symbol, snapshot)| {
+            // there's no snapshot of an hourly bar, or of one spanning several minutes or days
+            let bar = match (options.period.timeframe, options.period.multiple) {
+                (TimeFrame::OneMinute, 1) => snapshot.minute_bar,
+                (TimeFrame::OneDay, 1) => snapshot.daily_bar,
+                _ => None,
+            };
+            Some((symbol, bar?))
+        })
+        .collect();
+
+    (prices, recent_bars)
+}
+
+// adds the bar to the end of the bars, or replaces the last bar if it's a newer look at the same one
+fn merge_recent_bar(bars: &mut Vec<bars::Bar>, bar: bars::Bar) {
+    match bars.last_mut() {
+        Some(last) if last.time == bar.time => *last = bar,
+        Some(last) if last.time > bar.time => {}
+        _ => bars.push(bar),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::{HashMap, HashSet},
+        sync::Arc,
+        time::Duration,
+    };
+
+    use apca::{
+        api::v2::order::{Side, Status},
+        data::v2::Feed,
+    };
+    use chrono::{DateTime, NaiveTime, TimeZone, Utc};
+    use dashmap::DashMap;
+    use num_decimal::Num;
+
+    use super::{
+        close_session, hard_stop, in_trading_window, open_session, watch_all, AccountState,
+        DayEndPolicy, ExitOrder, IndicatorSnapshots, Pause, Position, PriceSource, ScanOptions,
+        Symbol, TimePeriod,
+    };
+    use crate::{
+        backend::{Backend, BacktestBackend},
+        backtest, bars,
+        history::new_bar,
+        stats::PriceInput,
+        strategy::MovingAverageCrossover,
+        wait::{MarketStatus, PausedTime, Ticker, TimeSource},
+    };
+
+    fn account(positions: &[(&str, Num, Num)]) -> AccountState {
+        AccountState::new(
+            positions
+                .iter()
+                .map(|(symbol, owned, buy_in_price)| {
+                    (
+                        Symbol::from(*symbol),
+                        Position {
+                            owned: owned.clone(),
+                            buy_in_price: buy_in_price.clone(),
+                            ..Position::new(Utc::now())
+                        },
+                    )
+                })
+                .collect::<DashMap<_, _>>(),
+        )
+    }
+
+    #[test]
+    fn display_no_positions() {
+        assert_eq!(account(&[]).to_string(), "{\n}");
+    }
+
+    #[test]
+    fn display_one_position() {
+        let account = account(&[("AAPL", Num::from(2), Num::new(15025, 100))]);
+        assert_eq!(account.to_string(), "{\n  AAPL (2 @ $150.25)\n}");
+    }
+
+    #[test]
+    fn display_two_positions_in_symbol_order() {
+        let account = account(&[
+            ("MSFT", Num::new(3, 2), Num::from(300)),
+            ("AAPL", Num::from(2), Num::new(15025, 100)),
+        ]);
+        assert_eq!(
+            account.to_string(),
+            "{\n  AAPL (2 @ $150.25),\n  MSFT (1.5 @ $300.00)\n}"
+        );
+    }
+
+    #[test]
+    fn hard_stop_only_below_the_stop_price() {
+        let account = account(&[("AAPL", Num::from(2), Num::from(100))]);
+        let position = account.positions.get(&Symbol::from("AAPL"));
+        let hard_stop_pct = Num::from(5);
+
+        assert!(hard_stop(position.as_deref(), &Num::new(951, 10), &hard_stop_pct).is_none());
+        assert!(hard_stop(position.as_deref(), &Num::new(949, 10), &hard_stop_pct).is_some());
+        assert!(hard_stop(None, &Num::from(1), &hard_stop_pct).is_none());
+    }
+
+    fn owned_and_basis(account: &AccountState, symbol: &str) -> (Num, Num) {
+        let pos = account.positions.get(&Symbol::from(symbol)).unwrap();
+        (pos.owned.clone(), pos.buy_in_price.clone())
+    }
+
+    #[test]
+    fn apply_fill_buy() {
+        let account = account(&[]);
+        let symbol = Symbol::from("AAPL");
+
+        account.apply_fill(
+            symbol.clone(),
+            Side::Buy,
+            &Num::from(0),
+            None,
+            Status::New,
+            Utc::now(),
+        );
+        assert!(account.positions.get(&symbol).unwrap().order_in_progress);
+
+        let price = Num::from(10);
+        account.apply_fill(
+            symbol.clone(),
+            Side::Buy,
+            &Num::from(2),
+            Some(&price),
+            Status::Filled,
+            Utc::now(),
+        );
+        assert!(!account.positions.get(&symbol).unwrap().order_in_progress);
+        assert_eq!(
+            owned_and_basis(&account, "AAPL"),
+            (Num::from(2), Num::from(10))
+        );
+    }
+
+    #[test]
+    fn apply_fill_add_averages_the_basis() {
+        let account = account(&[("AAPL", Num::from(2), Num::from(10))]);
+
+        let price = Num::from(16);
+        account.apply_fill(
+            "AAPL".into(),
+            Side::Buy,
+            &Num::from(1),
+            Some(&price),
+            Status::Filled,
+            Utc::now(),
+        );
+        assert_eq!(
+            owned_and_basis(&account, "AAPL"),
+            (Num::from(3), Num::from(12))
+        );
+    }
+
+    #[test]
+    fn apply_fill_partial_close() {
+        let account = account(&[("AAPL", Num::from(3), Num::from(10))]);
+
+        let price = Num::from(11);
+        account.apply_fill(
+            "AAPL".into(),
+            Side::Sell,
+            &Num::from(1),
+            Some(&price),
+            Status::Filled,
+            Utc::now(),
+        );
+        assert_eq!(
+            owned_and_basis(&account, "AAPL"),
+            (Num::from(2), Num::from(10))
+        );
+        assert!(
+            account
+                .positions
+                .get(&Symbol::from("AAPL"))
+                .unwrap()
+                .scaled_out
+        );
+        assert_eq!(account.take_journal().wins, 1);
+    }
+
+    #[test]
+    fn apply_fill_full_close() {
+        let account = account(&[("AAPL", Num::from(3), Num::from(10))]);
+
+        let price = Num::from(9);
+        account.apply_fill(
+            "AAPL".into(),
+            Side::Sell,
+            &Num::from(3),
+            Some(&price),
+            Status::Filled,
+            Utc::now(),
+        );
+        assert!(account
+            .positions
+            .get(&Symbol::from("AAPL"))
+            .unwrap()
+            .owned
+            .is_zero());
+        assert!(
+            !account
+                .positions
+                .get(&Symbol::from("AAPL"))
+                .unwrap()
+                .scaled_out
+        );
+        assert_eq!(account.take_journal().losses, 1);
+    }
+
+    #[test]
+    fn close_is_recorded_at_the_average_of_its_fills() {
+        let account = account(&[("AAPL", Num::from(2), Num::from(10))]);
+
+        let price = Num::from(8);
+        account.apply_fill(
+            "AAPL".into(),
+            Side::Sell,
+            &Num::from(1),
+            Some(&price),
+            Status::PartiallyFilled,
+            Utc::now(),
+        );
+        // the last fill alone would make it a win
+        let price = Num::from(11);
+        account.apply_fill(
+            "AAPL".into(),
+            Side::Sell,
+            &Num::from(1),
+            Some(&price),
+            Status::Filled,
+            Utc::now(),
+        );
+
+        let journal = account.take_journal();
+        assert_eq!((journal.wins, journal.losses), (0, 1));
+    }
+
+    #[test]
+    fn cancelled_sell_still_records_its_close() {
+        let account = account(&[("AAPL", Num::from(2), Num::from(10))]);
+
+        let price = Num::from(12);
+        account.apply_fill(
+            "AAPL".into(),
+            Side::Sell,
+            &Num::from(1),
+            Some(&price),
+            Status::PartiallyFilled,
+            Utc::now(),
+        );
+        assert_eq!(account.take_journal().wins, 0);
+
+        account.finish_order(&Symbol::from("AAPL"), Utc::now());
+        assert_eq!(account.take_journal().wins, 1);
+    }
+
+    #[test]
+    fn closed_market_time_is_only_counted_for_held_positions() {
+        let account = account(&[
+            ("AAPL", Num::from(2), Num::from(10)),
+            ("MSFT", Num::from(0), Num::from(0)),
+        ]);
+
+        let closed = Utc::now();
+        let opened = closed + chrono::Duration::hours(16);
+        account.end_session(closed);
+        account.start_session(opened);
+        // a second open without a close in between doesn't count anything
+        account.start_session(opened + chrono::Duration::hours(1));
+
+        let closed_for = |symbol| {
+            account
+                .positions
+                .get(&Symbol::from(symbol))
+                .unwrap()
+                .closed_for
+        };
+        assert_eq!(closed_for("AAPL"), Duration::from_secs(16 * 60 * 60));
+        assert_eq!(closed_for("MSFT"), Duration::ZERO);
+    }
+
+    #[test]
+    fn apply_fill_partial_fills_add_up() {
+        let account = account(&[]);
+        let symbol = Symbol::from("AAPL");
+
+        let price = Num::from(10);
+        account.apply_fill(
+            symbol.clone(),
+            Side::Buy,
+            &Num::from(1),
+            Some(&price),
+            Status::PartiallyFilled,
+            Utc::now(),
+        );
+        assert!(account.positions.get(&symbol).unwrap().order_in_progress);
+
+        let price = Num::from(13);
+        account.apply_fill(
+            symbol.clone(),
+            Side::Buy,
+            &Num::from(2),
+            Some(&price),
+            Status::Filled,
+            Utc::now(),
+        );
+        assert_eq!(
+            owned_and_basis(&account, "AAPL"),
+            (Num::from(3), Num::from(12))
+        );
+        assert_eq!(account.take_journal().trades, 1);
+    }
+
+    #[test]
+    fn trading_windows_include_their_start_only() {
+        let time = |hour, minute| NaiveTime::from_hms_opt(hour, minute, 0).unwrap();
+        let windows = [(time(9, 30), time(10, 30)), (time(15, 0), time(16, 0))];
+
+        assert!(in_trading_window(&windows, time(9, 30)));
+        assert!(in_trading_window(&windows, time(15, 59)));
+        assert!(!in_trading_window(&windows, time(10, 30)));
+        assert!(!in_trading_window(&windows, time(12, 0)));
+        assert!(in_trading_window(&[], time(12, 0)));
+    }
+
+    #[test]
+    fn realized_pnl_is_kept_per_symbol() {
+        let account = account(&[
+            ("AAPL", Num::from(4), Num::from(10)),
+            ("MSFT", Num::from(1), Num::from(20)),
+        ]);
+
+        let price = Num::from(12);
+        account.apply_fill(
+            Symbol::from("AAPL"),
+            Side::Sell,
+            &Num::from(2),
+            Some(&price),
+            Status::Filled,
+            Utc::now(),
+        );
+        let price = Num::from(15);
+        account.apply_fill(
+            Symbol::from("MSFT"),
+            Side::Sell,
+            &Num::from(1),
+            Some(&price),
+            Status::Filled,
+            Utc::now(),
+        );
+        let price = Num::from(9);
+        account.apply_fill(
+            Symbol::from("AAPL"),
+            Side::Sell,
+            &Num::from(2),
+            Some(&price),
+            Status::Filled,
+            Utc::now(),
+        );
+
+        assert_eq!(
+            account.take_journal().realized_by_symbol(),
+            vec![
+                (Symbol::from("AAPL"), Num::from(2)),
+                (Symbol::from("MSFT"), Num::from(-5)),
+            ]
+        );
+    }
+
+    #[test]
+    fn only_losing_sells_are_remembered_for_wash_sales() {
+        let account = account(&[
+            ("AAPL", Num::from(1), Num::from(10)),
+            ("MSFT", Num::from(1), Num::from(20)),
+        ]);
+
+        let price = Num::from(12);
+        account.apply_fill(
+            Symbol::from("AAPL"),
+            Side::Sell,
+            &Num::from(1),
+            Some(&price),
+            Status::Filled,
+            Utc::now(),
+        );
+        let price = Num::from(15);
+        account.apply_fill(
+            Symbol::from("MSFT"),
+            Side::Sell,
+            &Num::from(1),
+            Some(&price),
+            Status::Filled,
+            Utc::now(),
+        );
+
+        let window = Duration::from_secs(30 * 24 * 60 * 60);
+        assert!(account
+            .sold_at_loss_within(&Symbol::from("AAPL"), window, Utc::now())
+            .is_none());
+        assert!(account
+            .sold_at_loss_within(&Symbol::from("MSFT"), window, Utc::now())
+            .is_some());
+        assert!(account
+            .sold_at_loss_within(&Symbol::from("MSFT"), Duration::ZERO, Utc::now())
+            .is_none());
+    }
+
+    #[test]
+    fn class_shares_keep_their_dot() {
+        for ticker in ["BRK.B", "BF.B"] {
+            let symbol = Symbol::from(ticker);
+            assert_eq!(
+                symbol,
+                Symbol::Stock {
+                    ticker: ticker.to_string()
+                }
+            );
+            assert_eq!(symbol.request_symbol(), ticker);
+        }
+    }
+
+    #[test]
+    fn crypto_pairs_round_trip() {
+        let pair = Symbol::Crypto {
+            ticker: "BTC".to_string(),
+            quote: "USD".to_string(),
+        };
+
+        assert_eq!(Symbol::from("BTC/USD"), pair);
+        assert_eq!(Symbol::from("BTCUSD"), pair);
+        assert_eq!(pair.request_symbol(), "BTC/USD");
+        assert_eq!(Symbol::from(pair.request_symbol()), pair);
+    }
+
+    #[test]
+    fn junk_is_stripped_from_tickers() {
+        assert_eq!(
+            Symbol::from(" AAPL\n"),
+            Symbol::Stock {
+                ticker: "AAPL".to_string()
+            }
+        );
+    }
+
+    // what the scan options borrow, kept together so that a test only has to spell out the options
+    // it cares about
+    struct Scanning {
+        blacklist: HashSet<Symbol>,
+        pause: Arc<Pause>,
+        atr_stops: DashMap<Symbol, Num>,
+        last_scanned: DashMap<Symbol, DateTime<Utc>>,
+        indicators: IndicatorSnapshots,
+    }
+
+    impl Scanning {
+        fn new() -> Self {
+            Self {
+                blacklist: HashSet::new(),
+                pause: Pause::new(),
+                atr_stops: DashMap::new(),
+                last_scanned: DashMap::new(),
+                indicators: IndicatorSnapshots::new(),
+            }
+        }
+
+        // scans over ten minutes of bars that buy and sell at market, with none of the optional
+        // checks turned on
+        fn options(&self) -> ScanOptions<'_> {
+            ScanOptions {
+                period: TimePeriod::minutes(10),
+                confirm_period: None,
+                bar_limit: None,
+                feeds: vec![Feed::IEX],
+                use_snapshots: false,
+                price_source: PriceSource::LastTrade,
+                max_concentration_pct: Num::from(10),
+                sector_cap: None,
+                blacklist: &self.blacklist,
+                sell_only: HashSet::new(),
+                pause: &self.pause,
+                max_spread_pct: None,
+                equity_sizing: None,
+                atr_risk_sizing: None,
+                atr_stops: &self.atr_stops,
+                min_price: None,
+                max_price: None,
+                confirm_timeout: None,
+                max_open_orders: None,
+                recheck_buying_power: false,
+                fractional_shares: false,
+                exit_order: ExitOrder::Market,
+                exit_limit_timeout: Duration::from_secs(30),
+                limit_offset_bps: None,
+                hard_stop_pct: None,
+                min_scan_interval: Duration::ZERO,
+                scan_batch: None,
+                dry_run: false,
+                in_trading_window: true,
+                above_min_equity: true,
+                wash_sale_window: None,
+                skip_wash_sales: false,
+                last_scanned: &self.last_scanned,
+                indicators: &self.indicators,
+                rationale_log: None,
+                buy_notional: None,
+            }
+        }
+    }
+
+    // a minute by minute day that goes flat, up and back down, for the averages to cross both ways
+    fn crossing_day() -> BacktestBackend {
+        let start = Utc.with_ymd_and_hms(2023, 6, 5, 14, 0, 0).unwrap();
+        let bars = [10, 10, 10, 10, 10, 11, 12, 13, 14, 13, 12]
+            .into_iter()
+            .enumerate()
+            .map(|(i, close)| {
+                let close = Num::from(close);
+                let time = start + chrono::Duration::minutes(i as i64);
+                new_bar(time, &close, &close, &close, &close, &Num::from(100))
+            })
+            .collect();
+
+        BacktestBackend::new(
+            HashMap::from([(Symbol::from("AAPL"), bars)]),
+            chrono::Duration::minutes(1),
+            start,
+            Num::from(1000),
+        )
+    }
+
+    #[tokio::test]
+    async fn a_day_of_scans_buys_and_sells_at_a_profit() {
+        let backend = crossing_day();
+        let strategy = MovingAverageCrossover {
+            fast: 2,
+            slow: 4,
+            price_input: PriceInput::Close,
+        };
+
+        let scanning = Scanning::new();
+        let options = scanning.options();
+
+        // a scan at the end of every bar
+        let start = backend.now();
+        for minute in 1..=11 {
+            backend.advance_to(start + chrono::Duration::minutes(minute));
+            watch_all(&backend, &strategy, ["AAPL"], &options).await;
+        }
+
+        // bought at 11 on the way up, sold at 12 on the way down
+        let position = backend
+            .account_data()
+            .positions
+            .get(&Symbol::from("AAPL"))
+            .unwrap()
+            .clone();
+        assert!(position.owned.is_zero());
+        assert_eq!(backend.cash(), Num::from(1001));
+
+        let stats = backend.final_stats().await;
+        assert_eq!(stats.current_equity, Num::from(1001));
+        assert_eq!(stats.last_equity, Num::from(1000));
+        assert_eq!((stats.trades, stats.wins, stats.losses), (2, 1, 0));
+        assert_eq!(stats.realized, vec![(Symbol::from("AAPL"), Num::from(1))]);
+    }
+
+    // a thursday that keeps rising after the averages cross, so that the share bought is still
+    // held at the close
+    fn rising_thursday_bars() -> HashMap<Symbol, Vec<bars::Bar>> {
+        let start = Utc.with_ymd_and_hms(2023, 6, 1, 14, 0, 0).unwrap();
+        let bars = [10, 10, 10, 10, 10, 11, 12, 13, 14]
+            .into_iter()
+            .enumerate()
+            .map(|(i, close)| {
+                let close = Num::from(close);
+                let time = start + chrono::Duration::minutes(i as i64);
+                new_bar(time, &close, &close, &close, &close, &Num::from(100))
+            })
+            .collect();
+
+        HashMap::from([(Symbol::from("AAPL"), bars)])
+    }
+
+    fn rising_thursday(time: PausedTime) -> BacktestBackend {
+        BacktestBackend::new(
+            rising_thursday_bars(),
+            chrono::Duration::minutes(1),
+            time.now(),
+            Num::from(1000),
+        )
+        .following(Box::new(time))
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn weeknights_are_held_through_and_weekends_sold_before() {
+        let time = PausedTime::starting_at("2023-06-01T13:00:00Z");
+        let backend = rising_thursday(time);
+        let strategy = MovingAverageCrossover {
+            fast: 2,
+            slow: 4,
+            price_input: PriceInput::Close,
+        };
+        let aapl = Symbol::from("AAPL");
+
+        let scanning = Scanning::new();
+        let mut options = scanning.options();
+
+        let mut ticker =
+            Ticker::with_time_source(&backend, Duration::from_secs(60), Box::new(time))
+                .await
+                .unwrap();
+
+        // how each day ended, and the position at its close
+        let mut days = Vec::new();
+        let mut first_of_day = true;
+        // how long the position has been held while the market was closed, as of the latest tick
+        let mut closed_for = Duration::ZERO;
+
+        while days.len() < 2 {
+            match ticker.wait_for_open_or_tick(&backend).await {
+                MarketStatus::Open => {
+                    open_session(&backend, &mut options, first_of_day, None).await;
+                    first_of_day = false;
+
+                    if let Some(pos) = backend.account_data().positions.get(&aapl) {
+                        closed_for = pos.closed_for;
+                    }
+
+                    watch_all(&backend, &strategy, ["AAPL"], &options).await;
+                }
+                MarketStatus::AboutToClose => {
+                    let stats =
+                        close_session(&backend, DayEndPolicy::HoldWeeknights, &scanning.blacklist)
+                            .await;
+                    first_of_day = true;
+
+                    let position = backend
+                        .account_data()
+                        .positions
+                        .get(&aapl)
+                        .map(|pos| pos.clone());
+                    days.push((stats, position));
+                }
+            }
+        }
+
+        // bought at 11 on thursday and held through the night
+        let (thursday, position) = &days[0];
+        let position = position.as_ref().unwrap();
+        assert_eq!(position.owned, Num::from(1));
+        assert_eq!(position.sessions_held, 1);
+        assert_eq!((thursday.trades, thursday.wins, thursday.losses), (1, 0, 0));
+        assert_eq!(thursday.last_equity, Num::from(1000));
+        assert_eq!(thursday.current_equity, Num::from(1003));
+
+        // the night doesn't count towards how long it was held
+        assert!(closed_for > Duration::from_secs(17 * 60 * 60));
+        assert!(closed_for < Duration::from_secs(18 * 60 * 60));
+
+        // sold at 14 at friday's close, with the weekend ahead
+        let (friday, position) = &days[1];
+        assert!(position.as_ref().unwrap().owned.is_zero());
+        assert_eq!((friday.trades, friday.wins, friday.losses), (1, 1, 0));
+        assert_eq!(friday.realized, vec![(aapl, Num::from(3))]);
+        assert_eq!(friday.last_equity, Num::from(1003));
+        assert_eq!(friday.current_equity, Num::from(1003));
+        assert_eq!(backend.cash(), Num::from(1003));
+    }
+
+    #[tokio::test]
+    async fn backtests_apply_the_day_end_policy_at_each_close() {
+        let strategy = MovingAverageCrossover {
+            fast: 2,
+            slow: 4,
+            price_input: PriceInput::Close,
+        };
+        let scanning = Scanning::new();
+
+        let mut options = scanning.options();
+        let held = backtest::run(
+            rising_thursday_bars(),
+            &strategy,
+            &mut options,
+            &[],
+            DayEndPolicy::HoldAll,
+            None,
+        )
+        .await;
+        assert_eq!((held.trades, held.wins, held.losses), (1, 0, 0));
+        assert!(held.realized.is_empty());
+
+        let mut options = scanning.options();
+        let sold = backtest::run(
+            rising_thursday_bars(),
+            &strategy,
+            &mut options,
+            &[],
+            DayEndPolicy::SellAll,
+            None,
+        )
+        .await;
+        assert_eq!((sold.trades, sold.wins, sold.losses), (2, 1, 0));
+        assert_eq!(
+            sold.realized,
+            HashMap::from([("AAPL".to_string(), Num::from(3))])
+        );
+        assert_eq!(sold.final_equity, held.final_equity);
+    }
+
+    #[test]
+    fn periods_can_have_bars_of_several_units() {
+        let period = "78x5m".parse::<TimePeriod>().unwrap();
+        assert_eq!(period.bar_length(), chrono::Duration::minutes(5));
+        assert_eq!(period.to_chrono(), chrono::Duration::minutes(390));
+
+        let period = "14d".parse::<TimePeriod>().unwrap();
+        assert_eq!(period.bar_length(), chrono::Duration::days(1));
+        assert_eq!(period.to_chrono(), chrono::Duration::days(14));
+
+        assert!("10x2d".parse::<TimePeriod>().is_err());
+        assert!("10x0m".parse::<TimePeriod>().is_err());
     }
 }
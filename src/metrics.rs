@@ -0,0 +1,155 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    net::SocketAddr,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use apca::api::v2::order::Side;
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use serde_json::{json, Map, Value};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+};
+
+use crate::Symbol;
+
+/// The indicators a symbol was last evaluated with.
+#[derive(Debug, Clone)]
+pub(crate) struct IndicatorSnapshot {
+    pub(crate) time: DateTime<Utc>,
+    pub(crate) price: f64,
+    /// whatever the strategy looks at, by name
+    pub(crate) values: Vec<(&'static str, f64)>,
+    /// what the strategy decided, and the conditions it came down to followed by the gates the
+    /// decision went through before being acted on
+    pub(crate) decision: Option<Side>,
+    pub(crate) conditions: Vec<(&'static str, bool)>,
+    /// the order that was actually submitted, if the decision made it through the gates
+    pub(crate) action: Option<Side>,
+}
+
+impl IndicatorSnapshot {
+    /// The conditions that hold, and the ones that don't prefixed with `!`.
+    pub(crate) fn describe_conditions(&self) -> String {
+        self.conditions
+            .iter()
+            .map(|(name, holds)| {
+                if *holds {
+                    name.to_string()
+                } else {
+                    format!("!{name}")
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    fn to_json(&self) -> Value {
+        let mut fields = Map::new();
+        fields.insert("time".to_string(), json!(self.time.to_rfc3339()));
+        fields.insert("price".to_string(), json!(self.price));
+        for (name, value) in &self.values {
+            fields.insert(name.to_string(), json!(value));
+        }
+        fields.insert("decision".to_string(), json!(self.decision));
+        fields.insert("action".to_string(), json!(self.action));
+        fields.insert(
+            "conditions".to_string(),
+            Value::Object(
+                self.conditions
+                    .iter()
+                    .map(|(name, holds)| (name.to_string(), json!(holds)))
+                    .collect(),
+            ),
+        );
+
+        Value::Object(fields)
+    }
+}
+
+/// Every decision the strategy makes, one JSON object per line, for going through afterwards.
+pub(crate) struct RationaleLog {
+    file: Mutex<File>,
+}
+
+impl RationaleLog {
+    pub(crate) fn open(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+
+        Ok(Self {
+            file: Mutex::new(file),
+        })
+    }
+
+    pub(crate) fn record(&self, symbol: &Symbol, snapshot: &IndicatorSnapshot) {
+        let mut line = snapshot.to_json();
+        line["symbol"] = json!(symbol.request_symbol());
+
+        let mut file = self.file.lock().unwrap();
+        if let Err(why) = writeln!(file, "{line}") {
+            tracing::warn!("couldn't write to the rationale log: {why}");
+        }
+    }
+}
+
+pub(crate) type IndicatorSnapshots = DashMap<Symbol, IndicatorSnapshot>;
+
+/// Answers `GET /indicators` with the latest snapshot of every symbol, so that something else can
+/// keep an eye on the program without reading its logs.
+pub(crate) async fn serve(addr: SocketAddr, snapshots: Arc<IndicatorSnapshots>) -> io::Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    tracing::info!("Serving metrics on http://{addr}");
+
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let snapshots = snapshots.clone();
+        tokio::spawn(async move {
+            if let Err(why) = respond(stream, &snapshots).await {
+                tracing::debug!("couldn't answer a metrics request: {why}");
+            }
+        });
+    }
+}
+
+async fn respond(stream: TcpStream, snapshots: &IndicatorSnapshots) -> io::Result<()> {
+    let mut stream = BufReader::new(stream);
+
+    let mut request_line = String::new();
+    stream.read_line(&mut request_line).await?;
+
+    // none of the headers matter, but they have to be read before answering
+    let mut header = String::new();
+    loop {
+        header.clear();
+        if stream.read_line(&mut header).await? == 0 || header.trim().is_empty() {
+            break;
+        }
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let (status, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/indicators")) => ("200 OK", indicators_json(snapshots)),
+        _ => ("404 Not Found", json!({ "error": "not found" })),
+    };
+
+    let body = body.to_string();
+    let response = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+
+    stream.get_mut().write_all(response.as_bytes()).await
+}
+
+fn indicators_json(snapshots: &IndicatorSnapshots) -> Value {
+    let symbols = snapshots
+        .iter()
+        .map(|entry| (entry.key().request_symbol(), entry.value().to_json()))
+        .collect::<Map<_, _>>();
+
+    Value::Object(symbols)
+}
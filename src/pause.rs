@@ -0,0 +1,59 @@
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+// trading is paused for as long as this file exists in the working directory
+const PAUSE_FILE: &str = ".wsw-pause";
+
+/// Stops new positions from being opened without stopping the program, so exits keep being
+/// managed. Paused while the pause file exists, or after a SIGUSR1 until the next one.
+pub(crate) struct Pause {
+    toggled: AtomicBool,
+    file: PathBuf,
+}
+
+impl Pause {
+    pub(crate) fn new() -> Arc<Self> {
+        Arc::new(Self {
+            toggled: AtomicBool::new(false),
+            file: PAUSE_FILE.into(),
+        })
+    }
+
+    pub(crate) fn is_paused(&self) -> bool {
+        self.toggled.load(Ordering::Relaxed) || self.file.exists()
+    }
+
+    /// Toggles the pause whenever SIGUSR1 comes in.
+    #[cfg(unix)]
+    pub(crate) fn listen_for_signal(self: &Arc<Self>) {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut signals = match signal(SignalKind::user_defined1()) {
+            Ok(signals) => signals,
+            Err(why) => {
+                tracing::error!("couldn't listen for SIGUSR1: {why}");
+                return;
+            }
+        };
+
+        let pause = self.clone();
+        tokio::task::spawn(async move {
+            while signals.recv().await.is_some() {
+                let paused = !pause.toggled.fetch_xor(true, Ordering::Relaxed);
+                if paused {
+                    tracing::info!("Pausing, no new positions will be opened");
+                } else {
+                    tracing::info!("Resuming");
+                }
+            }
+        });
+    }
+
+    #[cfg(not(unix))]
+    pub(crate) fn listen_for_signal(self: &Arc<Self>) {}
+}
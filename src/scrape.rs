@@ -1,75 +1,188 @@
-use std::{fs, time::Duration};
+use std::{fs, path::Path, str::FromStr, time::Duration};
 
-use futures::future::join_all;
+use futures::StreamExt;
 use itertools::Itertools;
 use lazy_static::lazy_static;
 use num_decimal::Num;
 use scraper::{Html, Selector};
-use tokio::time::Instant;
+use tokio::time::MissedTickBehavior;
 
-use crate::{backend::Backend, Symbol};
+use crate::{
+    backend::{AssetClass, Backend, PriceSource},
+    Symbol,
+};
 
 const YAHOO_FINANCE: &str = "https://finance.yahoo.com/";
 const MARKET_WATCH: &str = "https://www.marketwatch.com/investing";
 const SLICK_CHARTS: &str = "https://www.slickcharts.com/sp500";
 const INVESTOPEDIA_TOP_STOCKS: &str = "https://www.investopedia.com/top-stocks-june-2023-7505936";
 
-lazy_static! {
-    static ref CLIENT: reqwest::Client = reqwest::Client::builder().build().unwrap();
+// some of the sites refuse requests that don't look like they're coming from a browser
+const USER_AGENT: &str = "Mozilla/5.0";
+
+// a hung connection would otherwise stall startup forever
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+// how far apart the articles are fetched, however many are in flight, so that marketwatch doesn't
+// start blocking us
+const ARTICLE_DELAY: Duration = Duration::from_millis(250);
+
+// where the last good result of each scraper is kept, for `ScrapePolicy::Fallback`
+const SCRAPE_CACHE_DIR: &str = "scrape-cache";
+
+// fewer symbols than this means the markup of the page probably changed
+const MIN_SP_500: usize = 400;
+const MIN_TOP_STOCKS: usize = 5;
+
+/// What to do when a scraper comes back with fewer symbols than it should.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ScrapePolicy {
+    /// refuse to start
+    Abort,
+    /// log it and go on with whatever was scraped
+    #[default]
+    Warn,
+    /// use what the scraper returned the last time it worked
+    Fallback,
 }
 
-pub(crate) async fn all_stocks_within_price_range(
-    backend: &dyn Backend,
-    price_range: std::ops::Range<Num>,
-) -> Vec<(Symbol, Num)> {
-    let all_assets = backend.all_active_assets().await;
+impl FromStr for ScrapePolicy {
+    type Err = String;
 
-    let mut results = Vec::with_capacity(all_assets.len());
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "abort" => Ok(Self::Abort),
+            "warn" => Ok(Self::Warn),
+            "fallback" => Ok(Self::Fallback),
+            _ => Err("expected `abort`, `warn` or `fallback`".to_string()),
+        }
+    }
+}
 
-    let mut last_sleep = Instant::now();
+lazy_static! {
+    static ref CLIENT: reqwest::Client = reqwest::Client::builder()
+        .user_agent(USER_AGENT)
+        .connect_timeout(CONNECT_TIMEOUT)
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .unwrap();
+}
 
-    // we can't just call `get_latest_prices` with ALL the assets because the url will get too long
-    for (idx, assets) in all_assets.into_iter().chunks(1000).into_iter().enumerate() {
-        let latest_prices = backend
-            .all_latest_prices(assets.collect())
+// the body of the page, or `None` if it couldn't be fetched in time
+async fn fetch(url: &str) -> Option<String> {
+    let result = async {
+        CLIENT
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
             .await
-            .into_iter()
-            .filter(|(_, price)| price_range.contains(price));
-
-        results.extend(latest_prices);
+    }
+    .await;
 
-        if idx % 150 == 149 && last_sleep.elapsed().as_secs() < 60 {
-            tracing::debug!("sleeping for rate limit");
-            tokio::time::sleep(Duration::from_secs(60)).await;
-            last_sleep = Instant::now();
+    match result {
+        Ok(body) => Some(body),
+        Err(why) => {
+            tracing::error!("couldn't fetch {url}: {why}");
+            None
         }
     }
+}
+
+/// Every stock whose last trade is within the price range. The prices are asked for in chunks,
+/// `concurrency` of them at a time.
+pub(crate) async fn all_stocks_within_price_range(
+    backend: &dyn Backend,
+    price_range: std::ops::Range<Num>,
+    concurrency: usize,
+) -> Vec<(Symbol, Num)> {
+    let all_assets = backend.all_active_assets(AssetClass::Equity).await;
+
+    // we can't just call `get_latest_prices` with ALL the assets because the url will get too long.
+    // the backend keeps us under the rate limit, however many chunks are in flight
+    let chunks = all_assets
+        .chunks(1000)
+        .map(|assets| backend.all_latest_prices(assets.to_vec(), PriceSource::LastTrade))
+        .collect::<Vec<_>>();
+
+    let mut results = futures::stream::iter(chunks)
+        .buffer_unordered(concurrency.max(1))
+        .flat_map(futures::stream::iter)
+        .filter(|(_, price)| futures::future::ready(price_range.contains(price)))
+        .collect::<Vec<_>>()
+        .await;
 
     results.shrink_to_fit();
 
     results
 }
 
-pub(crate) async fn all_top_stocks() -> Vec<Symbol> {
+pub(crate) async fn all_top_stocks(policy: ScrapePolicy) -> Result<Vec<Symbol>, String> {
     let (sp_500, top_stocks) = futures::join!(sp_500(), investopedia_top_stocks());
-    sp_500
-        .iter()
-        .chain(top_stocks.iter())
+    let sp_500 = validate("sp-500", sp_500, MIN_SP_500, policy)?;
+    let top_stocks = validate("investopedia", top_stocks, MIN_TOP_STOCKS, policy)?;
+
+    Ok(merge_tickers(sp_500.iter().chain(top_stocks.iter())))
+}
+
+// the sources don't agree on how to write a ticker, e.g. `AAPL ` and `aapl`. they're cleaned up
+// first, and then the symbols they make are deduped, since that's what they're told apart by
+fn merge_tickers<'a>(tickers: impl IntoIterator<Item = &'a String>) -> Vec<Symbol> {
+    tickers
+        .into_iter()
+        .map(|ticker| Symbol::from(ticker.trim().to_uppercase()))
         .unique()
-        .map(Symbol::from)
         .collect()
 }
 
+/// Checks that a scraper found at least `min` symbols, applying `policy` if it didn't.
+/// Good results are cached so that a later `ScrapePolicy::Fallback` has something to use.
+fn validate(
+    name: &str,
+    scraped: Vec<String>,
+    min: usize,
+    policy: ScrapePolicy,
+) -> Result<Vec<String>, String> {
+    let cache = Path::new(SCRAPE_CACHE_DIR).join(format!("{name}.txt"));
+
+    if scraped.len() >= min {
+        let _ = fs::create_dir_all(SCRAPE_CACHE_DIR);
+        if let Err(why) = fs::write(&cache, scraped.join("\n")) {
+            tracing::warn!("couldn't cache the {name} symbols: {why}");
+        }
+        return Ok(scraped);
+    }
+
+    let problem = format!(
+        "scraping {name} found {} symbols, expected at least {min}",
+        scraped.len()
+    );
+
+    match policy {
+        ScrapePolicy::Abort => Err(problem),
+        ScrapePolicy::Warn => {
+            tracing::warn!("{problem}");
+            Ok(scraped)
+        }
+        ScrapePolicy::Fallback => {
+            let cached = fs::read_to_string(&cache)
+                .map_err(|why| format!("{problem}, and there's no cache to fall back on: {why}"))?;
+            let cached = cached.lines().map(str::to_string).collect_vec();
+            tracing::warn!(
+                "{problem}, falling back on the {} cached ones",
+                cached.len()
+            );
+            Ok(cached)
+        }
+    }
+}
+
 pub(crate) async fn investopedia_top_stocks() -> Vec<String> {
-    let body = &CLIENT
-        .get(INVESTOPEDIA_TOP_STOCKS)
-        .header("User-Agent", "Mozilla/5.0")
-        .send()
-        .await
-        .unwrap()
-        .text()
-        .await
-        .unwrap();
+    let Some(body) = &fetch(INVESTOPEDIA_TOP_STOCKS).await else {
+        return Vec::new();
+    };
 
     let doc = Html::parse_document(body);
 
@@ -94,23 +207,20 @@ pub(crate) async fn investopedia_top_stocks() -> Vec<String> {
 }
 
 pub(crate) async fn sp_500() -> Vec<String> {
-    let body = &CLIENT
-        .get(SLICK_CHARTS)
-        .header("User-Agent", "Mozilla/5.0")
-        .send()
-        .await
-        .unwrap()
-        .text()
-        .await
-        .unwrap();
+    let Some(body) = &fetch(SLICK_CHARTS).await else {
+        return Vec::new();
+    };
 
     let doc = Html::parse_document(body);
 
     let sel = Selector::parse("tbody").unwrap();
 
-    doc.select(&sel)
-        .next()
-        .unwrap()
+    // a missing table is the same as an empty one, `validate` decides what to do about it
+    let Some(tbody) = doc.select(&sel).next() else {
+        return Vec::new();
+    };
+
+    tbody
         .children()
         .filter_map(|tr| {
             tr.children()
@@ -126,20 +236,21 @@ pub(crate) async fn sp_500() -> Vec<String> {
         .collect()
 }
 
-pub(crate) async fn scrape_news() -> Vec<String> {
-    let body = &CLIENT
-        .get(MARKET_WATCH)
-        .send()
-        .await
-        .unwrap()
-        .text()
-        .await
-        .unwrap();
+/// Scrapes the articles linked from the front page, `concurrency` of them at a time.
+pub(crate) async fn scrape_news(concurrency: usize) -> Vec<String> {
+    let Some(body) = &fetch(MARKET_WATCH).await else {
+        return Vec::new();
+    };
 
     let doc = Html::parse_document(body);
 
     let sel = Selector::parse("a").unwrap();
 
+    // shared by every fetch, so that running them at once doesn't send them all at once
+    let mut interval = tokio::time::interval(ARTICLE_DELAY);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let spacing = &tokio::sync::Mutex::new(interval);
+
     let stocks = doc
         .select(&sel)
         .filter_map(|el| {
@@ -148,23 +259,22 @@ pub(crate) async fn scrape_news() -> Vec<String> {
             })
         })
         .unique()
-        .map(scrape_article)
+        .map(|link| async move {
+            spacing.lock().await.tick().await;
+            scrape_article(link).await
+        })
         .collect::<Vec<_>>();
 
-    join_all(stocks).await;
+    futures::stream::iter(stocks)
+        .buffer_unordered(concurrency.max(1))
+        .collect::<Vec<_>>()
+        .await;
 
     Vec::new()
 }
 
 async fn scrape_article(link: &str) -> Option<(String, f32)> {
-    let body = &CLIENT
-        .get(MARKET_WATCH)
-        .send()
-        .await
-        .unwrap()
-        .text()
-        .await
-        .unwrap();
+    let body = &fetch(MARKET_WATCH).await?;
 
     use std::io::Write;
 
@@ -200,7 +310,22 @@ async fn scrape_article(link: &str) -> Option<(String, f32)> {
     Some((String::new(), 0.0))
 }
 
+#[cfg(test)]
 mod tests {
+    use super::merge_tickers;
+    use crate::Symbol;
+
+    #[test]
+    fn messy_tickers_are_merged() {
+        let sp_500 = ["AAPL ", "MSFT", "BRK.B"].map(String::from);
+        let top_stocks = ["aapl", " msft\n", "brk.b", "NVDA"].map(String::from);
+
+        assert_eq!(
+            merge_tickers(sp_500.iter().chain(top_stocks.iter())),
+            ["AAPL", "MSFT", "BRK.B", "NVDA"].map(Symbol::from)
+        );
+    }
+
     // #[tokio::test]
     // async fn sp_500_is_500() {
     //     let top = sp_500().await;
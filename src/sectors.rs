@@ -0,0 +1,56 @@
+use std::{collections::HashMap, path::Path};
+
+use crate::{AccountState, Symbol};
+
+/// Limits how many positions can be open in a single sector at once.
+///
+/// None of the scraped pages list sectors, so they're loaded from a file instead. Each line is a
+/// symbol and its sector separated by a comma, e.g. `AAPL,Technology`.
+pub(crate) struct SectorCap {
+    sectors: HashMap<Symbol, String>,
+    max_positions: usize,
+}
+
+impl SectorCap {
+    pub(crate) fn load(path: &Path, max_positions: usize) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|why| format!("couldn't read {path:?}: {why}"))?;
+
+        let mut sectors = HashMap::new();
+
+        for (idx, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (symbol, sector) = line
+                .split_once(',')
+                .ok_or_else(|| format!("line {} of {path:?} isn't `symbol,sector`", idx + 1))?;
+
+            sectors.insert(Symbol::from(symbol.trim()), sector.trim().to_string());
+        }
+
+        Ok(Self {
+            sectors,
+            max_positions,
+        })
+    }
+
+    /// Whether buying the symbol would open one position too many in its sector. Symbols without a
+    /// known sector are never capped.
+    pub(crate) fn is_full(&self, symbol: &Symbol, account: &AccountState) -> bool {
+        let Some(sector) = self.sectors.get(symbol) else {
+            return false;
+        };
+
+        let open = account
+            .positions
+            .iter()
+            .filter(|pos| !pos.owned.is_zero() && pos.key() != symbol)
+            .filter(|pos| self.sectors.get(pos.key()) == Some(sector))
+            .count();
+
+        open >= self.max_positions
+    }
+}
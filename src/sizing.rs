@@ -0,0 +1,251 @@
+use apca::api::v2::order::{Amount, Side};
+use num_decimal::Num;
+
+/// Turns a dollar amount into an order amount. Symbols that can be traded fractionally are bought
+/// by notional, rounded down to the cent. Everything else is bought in whole shares, rounded down
+/// so that the order never costs more than `notional`.
+pub(crate) fn shares_for_notional(notional: Num, price: Num, fractional: bool) -> Amount {
+    let zero = Num::from(0);
+
+    if notional <= zero {
+        return Amount::quantity(0);
+    }
+
+    if fractional {
+        let cents = (notional * Num::from(100)).trunc();
+        Amount::notional(cents / Num::from(100))
+    } else if price <= zero {
+        Amount::quantity(0)
+    } else {
+        Amount::quantity((notional / price).trunc())
+    }
+}
+
+/// Sizes every buy of a scan as the same slice of the equity, so that positions grow and shrink
+/// along with the account.
+#[derive(Debug, Clone)]
+pub(crate) struct EquitySizing {
+    /// how much of the equity may be at risk at once, e.g. `0.5` for half
+    pub(crate) risk_fraction: Num,
+    /// how many positions the risked equity is split between, and the most to have open at once
+    pub(crate) max_positions: usize,
+}
+
+impl EquitySizing {
+    pub(crate) fn notional(&self, equity: &Num) -> Num {
+        equity.clone() * self.risk_fraction.clone() / Num::from(self.max_positions.max(1))
+    }
+
+    /// How much of the equity every buy is sized at, in percent.
+    pub(crate) fn slice_pct(&self) -> Num {
+        self.notional(&Num::from(100))
+    }
+}
+
+/// Sizes each buy so that falling to its stop, `atr_multiple` ATRs below the entry, loses
+/// `risk_per_trade` of the equity. Volatile symbols get smaller positions than calm ones.
+#[derive(Debug, Clone)]
+pub(crate) struct AtrRiskSizing {
+    /// the fraction of the equity to lose if the stop is hit, e.g. `0.01` for 1%
+    pub(crate) risk_per_trade: Num,
+    pub(crate) atr_multiple: Num,
+    /// how many bars the atr is measured over
+    pub(crate) atr_period: usize,
+}
+
+impl AtrRiskSizing {
+    pub(crate) fn stop_price(&self, entry: &Num, atr: &Num) -> Num {
+        entry.clone() - atr.clone() * self.atr_multiple.clone()
+    }
+
+    /// The quantity to buy, in whole shares unless the symbol is fractional. Zero if not even a
+    /// single share can be risked.
+    pub(crate) fn quantity(&self, equity: &Num, atr: &Num, fractional: bool) -> Num {
+        let stop_distance = atr.clone() * self.atr_multiple.clone();
+        if stop_distance <= Num::from(0) {
+            return Num::from(0);
+        }
+
+        let quantity = equity.clone() * self.risk_per_trade.clone() / stop_distance;
+        if fractional {
+            quantity
+        } else {
+            quantity.trunc()
+        }
+    }
+}
+
+/// The part of an owned quantity to sell, in whole shares unless the symbol is fractional. Selling
+/// everything is always possible.
+pub(crate) fn fraction_of(owned: Num, fraction: Num, fractional: bool) -> Num {
+    if fraction >= Num::from(1) {
+        return owned;
+    }
+
+    let quantity = owned * fraction;
+    if fractional {
+        quantity
+    } else {
+        quantity.trunc()
+    }
+}
+
+/// What an order for the amount would cost at the given price.
+pub(crate) fn amount_value(amount: &Amount, price: &Num) -> Num {
+    match amount {
+        Amount::Quantity { quantity } => quantity.clone() * price.clone(),
+        Amount::Notional { notional } => notional.clone(),
+    }
+}
+
+/// Whether submitting the amount would be pointless.
+pub(crate) fn is_empty(amount: &Amount) -> bool {
+    match amount {
+        Amount::Quantity { quantity } => quantity.is_zero(),
+        Amount::Notional { notional } => notional.is_zero(),
+    }
+}
+
+/// A limit `offset_bps` basis points through the price, above it for buys and below it for sells,
+/// so that the order still fills right away but never much worse than the price. Stocks are
+/// limited to cents, or hundredths of a cent below a dollar, which is what they can be priced in.
+pub(crate) fn marketable_limit(price: &Num, side: Side, offset_bps: &Num, crypto: bool) -> Num {
+    let offset = price.clone() * offset_bps.clone() / Num::from(10_000);
+    let limit = match side {
+        Side::Buy => price.clone() + offset,
+        Side::Sell => price.clone() - offset,
+    };
+
+    let scale = Num::from(if crypto {
+        1_000_000_000
+    } else if *price < Num::from(1) {
+        10_000
+    } else {
+        100
+    });
+    (limit * scale.clone()).trunc() / scale
+}
+
+/// Rounds the quantity down to a multiple of `increment`, or `None` if what's left is below
+/// `min_order_size` and the order would be rejected.
+pub(crate) fn fit_to_lot(
+    quantity: Num,
+    min_order_size: Option<&Num>,
+    increment: Option<&Num>,
+) -> Option<Num> {
+    let quantity = match increment.filter(|increment| !increment.is_zero()) {
+        Some(increment) => (quantity / increment.clone()).trunc() * increment.clone(),
+        None => quantity,
+    };
+
+    if quantity.is_zero() || min_order_size.map_or(false, |min| quantity < *min) {
+        return None;
+    }
+
+    Some(quantity)
+}
+
+#[cfg(test)]
+mod tests {
+    use apca::api::v2::order::{Amount, Side};
+    use num_decimal::Num;
+
+    use super::{fit_to_lot, is_empty, marketable_limit, shares_for_notional, AtrRiskSizing};
+
+    #[test]
+    fn fractional_rounds_down_to_the_cent() {
+        let amount = shares_for_notional(Num::new(10239, 1000), Num::from(30), true);
+        assert_eq!(amount, Amount::notional(Num::new(1023, 100)));
+    }
+
+    #[test]
+    fn fractional_ignores_price() {
+        let amount = shares_for_notional(Num::from(5), Num::from(0), true);
+        assert_eq!(amount, Amount::notional(5));
+    }
+
+    #[test]
+    fn whole_shares_round_down() {
+        let amount = shares_for_notional(Num::from(100), Num::from(30), false);
+        assert_eq!(amount, Amount::quantity(3));
+    }
+
+    #[test]
+    fn whole_shares_exact_multiple() {
+        let amount = shares_for_notional(Num::from(90), Num::from(30), false);
+        assert_eq!(amount, Amount::quantity(3));
+    }
+
+    #[test]
+    fn less_than_one_share_is_empty() {
+        let amount = shares_for_notional(Num::from(20), Num::from(30), false);
+        assert_eq!(amount, Amount::quantity(0));
+        assert!(is_empty(&amount));
+    }
+
+    #[test]
+    fn zero_price_is_empty() {
+        let amount = shares_for_notional(Num::from(20), Num::from(0), false);
+        assert!(is_empty(&amount));
+    }
+
+    #[test]
+    fn negative_notional_is_empty() {
+        let amount = shares_for_notional(Num::from(-20), Num::from(30), true);
+        assert!(is_empty(&amount));
+    }
+
+    #[test]
+    fn lot_rounds_down_to_the_increment() {
+        let quantity = fit_to_lot(Num::new(123456, 100000), None, Some(&Num::new(1, 1000)));
+        assert_eq!(quantity, Some(Num::new(1234, 1000)));
+    }
+
+    #[test]
+    fn lot_below_minimum_is_skipped() {
+        let min = Num::new(1, 10);
+        let quantity = fit_to_lot(Num::new(5, 100), Some(&min), Some(&Num::new(1, 1000)));
+        assert_eq!(quantity, None);
+    }
+
+    #[test]
+    fn marketable_limits_go_through_the_price() {
+        let offset_bps = Num::from(20);
+
+        let buy = marketable_limit(&Num::from(10), Side::Buy, &offset_bps, false);
+        assert_eq!(buy, Num::new(1002, 100));
+        let sell = marketable_limit(&Num::from(10), Side::Sell, &offset_bps, false);
+        assert_eq!(sell, Num::new(998, 100));
+
+        // sub-penny below a dollar
+        let cheap = marketable_limit(&Num::new(1, 2), Side::Buy, &offset_bps, false);
+        assert_eq!(cheap, Num::new(5010, 10_000));
+    }
+
+    fn atr_sizing() -> AtrRiskSizing {
+        AtrRiskSizing {
+            risk_per_trade: Num::new(1, 100),
+            atr_multiple: Num::from(2),
+            atr_period: 14,
+        }
+    }
+
+    #[test]
+    fn atr_sizing_loses_the_risk_at_the_stop() {
+        let sizing = atr_sizing();
+        let (equity, entry, atr) = (Num::from(10_000), Num::from(50), Num::new(5, 4));
+
+        // $100 at risk over a $2.50 stop distance
+        let quantity = sizing.quantity(&equity, &atr, false);
+        assert_eq!(quantity, Num::from(40));
+
+        let stop = sizing.stop_price(&entry, &atr);
+        assert_eq!((entry - stop) * quantity, Num::from(100));
+    }
+
+    #[test]
+    fn atr_sizing_without_volatility_is_empty() {
+        let quantity = atr_sizing().quantity(&Num::from(10_000), &Num::from(0), false);
+        assert!(quantity.is_zero());
+    }
+}
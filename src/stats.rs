@@ -1,36 +1,275 @@
-use apca::data::v2::bars;
+use std::str::FromStr;
+
+use num_decimal::Num;
 use ta::{
-    indicators::{BollingerBands, BollingerBandsOutput, RelativeStrengthIndex},
-    Next,
+    indicators::{
+        AverageTrueRange, BollingerBands, BollingerBandsOutput, ExponentialMovingAverage,
+        RelativeStrengthIndex, SimpleMovingAverage,
+    },
+    DataItem, Next,
 };
 
+use crate::{bars, Symbol};
+
+/// Converts the number for use in calculations. Numbers that don't fit become NaN instead of
+/// panicking.
+pub(crate) fn num_to_f64(n: &Num) -> f64 {
+    n.to_f64().unwrap_or(f64::NAN)
+}
+
+/// Converts a calculated number back, to 8 decimals. `None` if it's NaN or infinite.
+pub(crate) fn f64_to_num(n: f64) -> Option<Num> {
+    n.is_finite()
+        .then(|| Num::new((n * 1e8).round() as i64, 100_000_000))
+}
+
+// crypto trades in fractions of a cent, and all quantities can be fractional
+const CRYPTO_PRICE_DECIMALS: usize = 8;
+const QUANTITY_DECIMALS: usize = 8;
+
+/// Formats a price for the logs, in cents for stocks and with as many decimals as needed for
+/// crypto.
+pub(crate) fn format_price(symbol: &Symbol, price: &Num) -> String {
+    match symbol {
+        Symbol::Stock { .. } => fixed_decimals(price, 2),
+        Symbol::Crypto { .. } => trim_decimals(fixed_decimals(price, CRYPTO_PRICE_DECIMALS)),
+    }
+}
+
+/// Formats an indicator that's in the unit of the price, like a moving average, the same way as
+/// the price.
+pub(crate) fn format_price_level(symbol: &Symbol, level: f64) -> String {
+    match symbol {
+        Symbol::Stock { .. } => format!("{level:.2}"),
+        Symbol::Crypto { .. } => {
+            trim_decimals(format!("{level:.places$}", places = CRYPTO_PRICE_DECIMALS))
+        }
+    }
+}
+
+/// Formats a quantity for the logs, without the trailing zeros of whole or rounder ones.
+pub(crate) fn format_quantity(quantity: &Num) -> String {
+    trim_decimals(fixed_decimals(quantity, QUANTITY_DECIMALS))
+}
+
+// truncated rather than rounded, so that a quantity never shows up as more than there is
+fn fixed_decimals(n: &Num, places: usize) -> String {
+    let unit = 10u64.pow(places as u32);
+    let Some(scaled) = (n.clone() * Num::from(unit)).trunc().to_i64() else {
+        // too big for any price or quantity
+        return format!("{:.places$}", num_to_f64(n));
+    };
+
+    let sign = if scaled < 0 { "-" } else { "" };
+    let (whole, fraction) = (scaled.unsigned_abs() / unit, scaled.unsigned_abs() % unit);
+    if places == 0 {
+        return format!("{sign}{whole}");
+    }
+
+    format!("{sign}{whole}.{fraction:0places$}")
+}
+
+fn trim_decimals(formatted: String) -> String {
+    if !formatted.contains('.') {
+        return formatted;
+    }
+
+    formatted
+        .trim_end_matches('0')
+        .trim_end_matches('.')
+        .to_string()
+}
+
+/// Which value of each bar the indicators are computed from.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum PriceInput {
+    #[default]
+    Close,
+    /// the average of the high, low and close, which smooths out closes at the edge of a bar
+    Typical,
+    /// the average price of the bar's trades weighted by their size, or the typical price of the
+    /// bars that don't come with it
+    Vwap,
+}
+
+impl PriceInput {
+    /// The value of the bar, or `None` if it isn't a usable float.
+    pub(crate) fn of(self, bar: &bars::Bar) -> Option<f64> {
+        let value = match self {
+            Self::Close => bar.close.to_f64()?,
+            Self::Typical => (bar.high.to_f64()? + bar.low.to_f64()? + bar.close.to_f64()?) / 3.0,
+            Self::Vwap => match &bar.vwap {
+                Some(vwap) => vwap.to_f64()?,
+                None => Self::Typical.of(bar)?,
+            },
+        };
+
+        Some(value).filter(|value| value.is_finite())
+    }
+}
+
+impl FromStr for PriceInput {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "close" => Ok(Self::Close),
+            "typical" => Ok(Self::Typical),
+            "vwap" => Ok(Self::Vwap),
+            _ => Err("expected `close`, `typical` or `vwap`".to_string()),
+        }
+    }
+}
+
+// the oldest `warmup` bars only serve to settle an indicator and are left out of its window.
+// `None` is returned if no bars are left after the warmup, or if any of the prices can't be
+// turned into a float. a single NaN would silently poison the whole indicator otherwise
 pub(crate) trait Statistics {
-    fn bollinger(&self) -> Option<BollingerBandsOutput>;
-    fn rsi(&self) -> Option<f64>;
+    /// The closing prices, or `None` if any of them isn't a usable float.
+    fn closes(&self) -> Option<Vec<f64>> {
+        self.prices(PriceInput::Close)
+    }
+    /// The chosen value of every bar, or `None` if any of them isn't a usable float.
+    fn prices(&self, input: PriceInput) -> Option<Vec<f64>>;
+    fn bollinger(&self, warmup: usize, input: PriceInput) -> Option<BollingerBandsOutput>;
+    fn rsi(&self, warmup: usize, input: PriceInput) -> Option<f64>;
+    #[allow(unused)]
+    fn volume_weighted_rsi(&self, period: usize, input: PriceInput) -> Option<f64>;
+    fn sma(&self, period: usize, input: PriceInput) -> Option<f64>;
+    #[allow(unused)]
+    fn ema(&self, period: usize, input: PriceInput) -> Option<f64>;
+    fn atr(&self, period: usize) -> Option<f64>;
 }
 
-impl Statistics for Vec<bars::Bar> {
-    fn bollinger(&self) -> Option<BollingerBandsOutput> {
-        self.split_last().map(|(last, first)| {
-            let mut bb = BollingerBands::new(self.len(), 2.0).unwrap();
+impl Statistics for [bars::Bar] {
+    fn prices(&self, input: PriceInput) -> Option<Vec<f64>> {
+        self.iter().map(|bar| input.of(bar)).collect()
+    }
 
-            for bar in first {
-                bb.next(bar.close.to_f64().unwrap_or(f64::NAN));
-            }
+    fn bollinger(&self, warmup: usize, input: PriceInput) -> Option<BollingerBandsOutput> {
+        let period = self
+            .len()
+            .checked_sub(warmup)
+            .filter(|period| *period > 0)?;
+
+        let closes = self.prices(input)?;
+        let (last, first) = closes.split_last()?;
 
-            bb.next(last.close.to_f64().unwrap_or(f64::NAN))
-        })
+        let mut bb = BollingerBands::new(period, 2.0).unwrap();
+
+        for close in first {
+            bb.next(*close);
+        }
+
+        Some(bb.next(*last))
     }
 
-    fn rsi(&self) -> Option<f64> {
-        self.split_last().map(|(last, first)| {
-            let mut bb = RelativeStrengthIndex::new(self.len()).unwrap();
+    fn rsi(&self, warmup: usize, input: PriceInput) -> Option<f64> {
+        let period = self
+            .len()
+            .checked_sub(warmup)
+            .filter(|period| *period > 0)?;
+
+        let closes = self.prices(input)?;
+        let (last, first) = closes.split_last()?;
+
+        let mut rsi = RelativeStrengthIndex::new(period).unwrap();
 
-            for bar in first {
-                bb.next(bar.close.to_f64().unwrap_or(f64::NAN));
+        for close in first {
+            rsi.next(*close);
+        }
+
+        Some(rsi.next(*last))
+    }
+
+    fn volume_weighted_rsi(&self, period: usize, input: PriceInput) -> Option<f64> {
+        if period == 0 || self.len() <= period {
+            return None;
+        }
+
+        let window = &self[self.len() - period - 1..];
+        let closes = window.prices(input)?;
+
+        let mut gains = 0.0;
+        let mut losses = 0.0;
+        let mut total_volume = 0.0;
+
+        // each change in price counts as much as the volume that moved it
+        for (pair, bar) in closes.windows(2).zip(&window[1..]) {
+            let change = pair[1] - pair[0];
+            let volume = num_to_f64(&bar.volume);
+
+            if change > 0.0 {
+                gains += change * volume;
+            } else {
+                losses -= change * volume;
             }
+            total_volume += volume;
+        }
+
+        if total_volume == 0.0 {
+            return None;
+        }
 
-            bb.next(last.close.to_f64().unwrap_or(f64::NAN))
-        })
+        let average_gain = gains / total_volume;
+        let average_loss = losses / total_volume;
+
+        if average_loss == 0.0 {
+            return Some(100.0);
+        }
+
+        Some(100.0 - 100.0 / (1.0 + average_gain / average_loss))
+    }
+
+    fn sma(&self, period: usize, input: PriceInput) -> Option<f64> {
+        if period == 0 || self.len() < period {
+            return None;
+        }
+
+        let mut sma = SimpleMovingAverage::new(period).unwrap();
+
+        self[self.len() - period..]
+            .prices(input)?
+            .into_iter()
+            .map(|close| sma.next(close))
+            .last()
+    }
+
+    fn ema(&self, period: usize, input: PriceInput) -> Option<f64> {
+        if period == 0 || self.len() < period {
+            return None;
+        }
+
+        let mut ema = ExponentialMovingAverage::new(period).unwrap();
+
+        self.prices(input)?
+            .into_iter()
+            .map(|close| ema.next(close))
+            .last()
+    }
+
+    fn atr(&self, period: usize) -> Option<f64> {
+        if period == 0 || self.len() < period {
+            return None;
+        }
+
+        let mut atr = AverageTrueRange::new(period).unwrap();
+
+        let mut last = None;
+        for bar in self {
+            last = Some(atr.next(&data_item(bar)?));
+        }
+        last
     }
 }
+
+fn data_item(bar: &bars::Bar) -> Option<DataItem> {
+    DataItem::builder()
+        .open(bar.open.to_f64()?)
+        .high(bar.high.to_f64()?)
+        .low(bar.low.to_f64()?)
+        .close(bar.close.to_f64()?)
+        .volume(num_to_f64(&bar.volume))
+        .build()
+        .ok()
+}
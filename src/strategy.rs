@@ -0,0 +1,733 @@
+use std::{ops::Range, str::FromStr, time::Duration};
+
+use apca::api::v2::order::{Amount, Side};
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use num_decimal::Num;
+use ta::indicators::BollingerBandsOutput;
+
+use crate::{
+    bars, sizing,
+    stats::{format_price, format_price_level, num_to_f64, PriceInput, Statistics},
+    wait::saturating_std,
+    Position, Symbol,
+};
+
+pub(crate) trait Strategy {
+    /// The fewest bars a symbol needs before it's worth evaluating.
+    fn min_bars(&self) -> usize {
+        1
+    }
+
+    /// Decides whether to trade the symbol, given its recent bars and current price as of `now`.
+    /// `fractional` is whether it can be traded in fractions of a share, which partial sells have
+    /// to go by.
+    fn evaluate(
+        &self,
+        symbol: &Symbol,
+        position: Option<&Position>,
+        bars: &[bars::Bar],
+        price: &Num,
+        now: DateTime<Utc>,
+        fractional: bool,
+    ) -> Option<(Side, Amount)>;
+
+    /// The indicators the strategy looks at for the symbol, by name, for reporting them.
+    fn indicator_values(
+        &self,
+        _symbol: &Symbol,
+        _bars: &[bars::Bar],
+        _price: &Num,
+    ) -> Vec<(&'static str, f64)> {
+        Vec::new()
+    }
+
+    /// The conditions the decision about the symbol comes down to, by name and whether they hold,
+    /// to tell why it was or wasn't traded.
+    fn rationale(
+        &self,
+        _symbol: &Symbol,
+        _position: Option<&Position>,
+        _bars: &[bars::Bar],
+        _price: &Num,
+        _now: DateTime<Utc>,
+    ) -> Vec<(&'static str, bool)> {
+        Vec::new()
+    }
+
+    /// The decision about the symbol together with the conditions it came down to. Strategies
+    /// whose rationale repeats the work of evaluating can work both out at once.
+    fn decide(
+        &self,
+        symbol: &Symbol,
+        position: Option<&Position>,
+        bars: &[bars::Bar],
+        price: &Num,
+        now: DateTime<Utc>,
+        fractional: bool,
+    ) -> (Option<(Side, Amount)>, Vec<(&'static str, bool)>) {
+        (
+            self.evaluate(symbol, position, bars, price, now, fractional),
+            self.rationale(symbol, position, bars, price, now),
+        )
+    }
+
+    /// The rungs of a profit ladder that a sell at this price is for. They're remembered on the
+    /// position, so that the same rung isn't sold at again.
+    fn ladder_rungs(&self, _position: &Position, _price: &Num) -> Vec<usize> {
+        Vec::new()
+    }
+
+    /// Whether the bars of a longer period agree with buying the symbol. Only asked when there is
+    /// a longer period to look at.
+    fn confirms_entry(&self, _symbol: &Symbol, _bars: &[bars::Bar]) -> bool {
+        true
+    }
+
+    /// How much of the equity a single position may be worth, in percent, unless told otherwise.
+    fn max_concentration_pct(&self) -> Num {
+        Num::from(10)
+    }
+
+    /// The fraction of the equity that's split between `--max-positions` when it's given without
+    /// `--risk-fraction`. Strategies without one only size by equity when told both.
+    fn risk_fraction(&self) -> Option<Num> {
+        None
+    }
+}
+
+/// How long a position may be held before it's sold no matter what.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum HoldLimit {
+    /// wall clock time since the position was bought
+    Time(Duration),
+    /// time since the position was bought that the market was open for, so that a limit of hours
+    /// isn't used up overnight
+    TradingTime(Duration),
+    /// market closes the position was held through. only reached when positions are held overnight
+    TradingDays(usize),
+}
+
+impl HoldLimit {
+    fn exceeded(&self, position: &Position, now: DateTime<Utc>) -> bool {
+        let held = saturating_std(now - position.timestamp);
+        match *self {
+            Self::Time(limit) => held > limit,
+            Self::TradingTime(limit) => held.saturating_sub(position.closed_for) > limit,
+            Self::TradingDays(limit) => position.sessions_held >= limit,
+        }
+    }
+}
+
+/// How far the price may move away from the buy in price before a position is closed.
+#[derive(Debug, Clone)]
+pub(crate) struct ProfitTargets {
+    /// sell once the price is this many percent above the buy in price
+    pub(crate) take_profit_pct: Num,
+    /// sell once the price is this many percent below the buy in price
+    pub(crate) stop_loss_pct: Num,
+}
+
+impl ProfitTargets {
+    pub(crate) fn target_price(&self, buy_in_price: &Num) -> Num {
+        buy_in_price.clone() * (Num::from(100) + self.take_profit_pct.clone()) / Num::from(100)
+    }
+
+    pub(crate) fn stop_price(&self, buy_in_price: &Num) -> Num {
+        buy_in_price.clone() * (Num::from(100) - self.stop_loss_pct.clone()) / Num::from(100)
+    }
+}
+
+// profit limits used to be given as the range of price to buy in price ratios to hold within,
+// e.g. `0.9..1.5` is a 10% stop loss and a 50% take profit
+impl From<Range<Num>> for ProfitTargets {
+    fn from(profit_limit: Range<Num>) -> Self {
+        Self {
+            take_profit_pct: (profit_limit.end - Num::from(1)) * Num::from(100),
+            stop_loss_pct: (Num::from(1) - profit_limit.start) * Num::from(100),
+        }
+    }
+}
+
+/// Which of the mean reversion signals have to agree before buying.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum EntryRule {
+    /// oversold according to the rsi
+    RsiOnly,
+    /// below the lower bollinger band
+    BollingerOnly,
+    #[default]
+    Both,
+    Either,
+}
+
+impl EntryRule {
+    fn holds(self, oversold: bool, below_band: bool) -> bool {
+        match self {
+            Self::RsiOnly => oversold,
+            Self::BollingerOnly => below_band,
+            Self::Both => oversold && below_band,
+            Self::Either => oversold || below_band,
+        }
+    }
+}
+
+impl FromStr for EntryRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rsi" => Ok(Self::RsiOnly),
+            "bollinger" => Ok(Self::BollingerOnly),
+            "both" => Ok(Self::Both),
+            "either" => Ok(Self::Either),
+            _ => Err("expected `rsi`, `bollinger`, `both` or `either`".to_string()),
+        }
+    }
+}
+
+/// Buys when the price drops below the lower bollinger band while oversold (or whichever of the two
+/// `entry_rule` asks for), and sells once it's above the upper band while overbought, or when the
+/// position was held for too long or hit its profit target or stop loss. Parts of a position are
+/// sold as it climbs the rungs of the profit ladder.
+pub(crate) struct MeanReversion {
+    pub(crate) rsi_range: Range<f64>,
+    pub(crate) hold_limit: HoldLimit,
+    pub(crate) profit_targets: ProfitTargets,
+    /// the fraction of a position to sell when it first reaches the profit target.
+    /// the rest rides with a trailing stop, `stop_loss_pct` below the highest price since.
+    /// `None` sells everything right away
+    pub(crate) scale_out: Option<Num>,
+    /// the gains, in percent of the buy in price, to sell part of a position at, along with the
+    /// fraction of the position as it was bought to sell there
+    pub(crate) profit_ladder: Vec<(f64, Num)>,
+    /// how many of the oldest bars are only used to settle the rsi
+    pub(crate) rsi_warmup: usize,
+    /// how many of the oldest bars are left out of the bollinger bands
+    pub(crate) bollinger_warmup: usize,
+    /// how many bars each indicator needs to have left after its warmup
+    pub(crate) min_evaluation_bars: usize,
+    /// derive the rsi range from how volatile the symbol is instead of using `rsi_range`
+    pub(crate) adaptive_rsi: bool,
+    pub(crate) entry_rule: EntryRule,
+    /// buy only while the rsi of the longer period is at least this high, i.e. buy the dips of an
+    /// uptrend
+    pub(crate) confirm_rsi_min: f64,
+    /// the value of each bar the indicators are computed from
+    pub(crate) price_input: PriceInput,
+    pub(crate) cache: IndicatorCache,
+    /// the highest price of each position that was scaled out of, since it was
+    pub(crate) trailing_highs: DashMap<Symbol, Num>,
+}
+
+// the atr is measured over this many bars for the adaptive rsi range
+const ADAPTIVE_RSI_ATR_PERIOD: usize = 14;
+
+// the rsi range to use for a symbol with the given atr to price ratio.
+// calm markets get a narrow range and volatile ones a wide range, so that neither overtrades
+fn adaptive_rsi_range(volatility: f64) -> Range<f64> {
+    const CALM: f64 = 0.01;
+    const VOLATILE: f64 = 0.04;
+
+    let t = ((volatility - CALM) / (VOLATILE - CALM)).clamp(0.0, 1.0);
+    let low = 35.0 - 15.0 * t;
+
+    low..(100.0 - low)
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Indicators {
+    bb: BollingerBandsOutput,
+    rsi: f64,
+    atr: Option<f64>,
+}
+
+// what a mean reversion decision comes down to
+#[derive(Debug, Clone)]
+struct Conditions {
+    owned: bool,
+    oversold: bool,
+    below_lower_band: bool,
+    overbought: bool,
+    above_upper_band: bool,
+    held_too_long: bool,
+    take_profit: bool,
+    stop_loss: bool,
+    scaled_out: bool,
+    /// what's left after scaling out fell too far from its high
+    trailing_stop: bool,
+    /// the rungs of the profit ladder reached that haven't been sold at yet
+    new_rungs: Vec<usize>,
+}
+
+impl Conditions {
+    fn named(&self) -> Vec<(&'static str, bool)> {
+        vec![
+            ("owned", self.owned),
+            ("oversold", self.oversold),
+            ("below_lower_band", self.below_lower_band),
+            ("overbought", self.overbought),
+            ("above_upper_band", self.above_upper_band),
+            ("held_too_long", self.held_too_long),
+            ("take_profit", self.take_profit),
+            ("stop_loss", self.stop_loss),
+            ("scaled_out", self.scaled_out),
+            ("trailing_stop", self.trailing_stop),
+            ("new_rungs", !self.new_rungs.is_empty()),
+        ]
+    }
+}
+
+/// The indicators of each symbol, along with the time of the last bar they were computed from.
+/// They only change when a new bar comes in, which is a lot less often than we scan.
+pub(crate) type IndicatorCache = DashMap<Symbol, (DateTime<Utc>, Indicators)>;
+
+impl MeanReversion {
+    fn indicators(&self, symbol: &Symbol, bars: &[bars::Bar]) -> Option<Indicators> {
+        let last_bar = bars.last()?.time;
+
+        if let Some(cached) = self.cache.get(symbol) {
+            if cached.0 == last_bar {
+                return Some(cached.1.clone());
+            }
+        }
+
+        let indicators = Indicators {
+            bb: bars.bollinger(self.bollinger_warmup, self.price_input)?,
+            rsi: bars.rsi(self.rsi_warmup, self.price_input)?,
+            atr: bars.atr(ADAPTIVE_RSI_ATR_PERIOD),
+        };
+
+        self.cache
+            .insert(symbol.clone(), (last_bar, indicators.clone()));
+
+        Some(indicators)
+    }
+
+    // the rsi range to hold the symbol to, or `None` if it's adaptive and there's no atr yet
+    fn rsi_range(&self, atr: Option<f64>, price: &Num) -> Option<Range<f64>> {
+        if self.adaptive_rsi {
+            Some(adaptive_rsi_range(atr? / num_to_f64(price)))
+        } else {
+            Some(self.rsi_range.clone())
+        }
+    }
+
+    fn conditions(
+        &self,
+        symbol: &Symbol,
+        position: Option<&Position>,
+        bars: &[bars::Bar],
+        price: &Num,
+        now: DateTime<Utc>,
+    ) -> Option<Conditions> {
+        let price_float = num_to_f64(price);
+        let Indicators { bb, rsi, atr } = self.indicators(symbol, bars)?;
+        let rsi_range = self.rsi_range(atr, price)?;
+
+        let (take_profit, stop_loss) =
+            position
+                .filter(|pos| !pos.buy_in_price.is_zero())
+                .map_or((false, false), |pos| {
+                    (
+                        *price >= self.profit_targets.target_price(&pos.buy_in_price),
+                        *price < self.profit_targets.stop_price(&pos.buy_in_price),
+                    )
+                });
+
+        let owned = position.map_or(false, |pos| !pos.owned.is_zero());
+        let scaled_out = position.map_or(false, |pos| pos.scaled_out);
+
+        Some(Conditions {
+            owned,
+            oversold: rsi < rsi_range.start,
+            below_lower_band: price_float < bb.lower,
+            overbought: rsi > rsi_range.end,
+            above_upper_band: price_float > bb.upper,
+            held_too_long: position.map_or(false, |pos| self.hold_limit.exceeded(pos, now)),
+            take_profit,
+            stop_loss,
+            scaled_out,
+            trailing_stop: match position {
+                Some(pos) if owned && scaled_out => self.trailing_stop(symbol, pos, price),
+                _ => {
+                    self.trailing_highs.remove(symbol);
+                    false
+                }
+            },
+            new_rungs: position.map_or_else(Vec::new, |pos| self.new_rungs(pos, price)),
+        })
+    }
+
+    // whether the price fell past the trailing stop of the rest of a position that was scaled out
+    // of. the stop starts out below the profit target the position was scaled out at, and follows
+    // the price up from there
+    fn trailing_stop(&self, symbol: &Symbol, position: &Position, price: &Num) -> bool {
+        let mut high = self
+            .trailing_highs
+            .entry(symbol.clone())
+            .or_insert_with(|| self.profit_targets.target_price(&position.buy_in_price));
+        if *price > *high {
+            *high = price.clone();
+        }
+
+        *price < self.profit_targets.stop_price(&high)
+    }
+
+    // the rungs of the profit ladder the price is at or past that haven't been sold at yet
+    fn new_rungs(&self, position: &Position, price: &Num) -> Vec<usize> {
+        if position.buy_in_price.is_zero() {
+            return Vec::new();
+        }
+
+        let gain_pct = num_to_f64(
+            &((price.clone() - position.buy_in_price.clone()) * Num::from(100)
+                / position.buy_in_price.clone()),
+        );
+
+        self.profit_ladder
+            .iter()
+            .enumerate()
+            .filter(|(idx, (pct, _))| gain_pct >= *pct && !position.hit_rungs.contains(idx))
+            .map(|(idx, _)| idx)
+            .collect()
+    }
+
+    // the fraction of the whole position that the rungs sell together
+    fn ladder_fraction(&self, rungs: &[usize]) -> Num {
+        rungs.iter().fold(Num::from(0), |sum, idx| {
+            sum + self.profit_ladder[*idx].1.clone()
+        })
+    }
+
+    // the fraction of what's left of the position to sell for the rungs. the fractions of the
+    // ladder are of the whole position, so they're scaled up by what was already sold
+    fn rungs_fraction(&self, position: &Position, rungs: &[usize]) -> Num {
+        let remaining = Num::from(1) - self.ladder_fraction(&position.hit_rungs);
+        if remaining <= Num::from(0) {
+            return Num::from(1);
+        }
+
+        self.ladder_fraction(rungs) / remaining
+    }
+}
+
+impl Strategy for MeanReversion {
+    fn min_bars(&self) -> usize {
+        let min_bars = self.rsi_warmup.max(self.bollinger_warmup) + self.min_evaluation_bars.max(1);
+
+        if self.adaptive_rsi {
+            min_bars.max(ADAPTIVE_RSI_ATR_PERIOD)
+        } else {
+            min_bars
+        }
+    }
+
+    fn ladder_rungs(&self, position: &Position, price: &Num) -> Vec<usize> {
+        self.new_rungs(position, price)
+    }
+
+    fn indicator_values(
+        &self,
+        symbol: &Symbol,
+        bars: &[bars::Bar],
+        price: &Num,
+    ) -> Vec<(&'static str, f64)> {
+        let Some(Indicators { bb, rsi, atr }) = self.indicators(symbol, bars) else {
+            return Vec::new();
+        };
+        let rsi_range = match (self.adaptive_rsi, atr) {
+            (true, Some(atr)) => adaptive_rsi_range(atr / num_to_f64(price)),
+            _ => self.rsi_range.clone(),
+        };
+
+        vec![
+            ("bb_lower", bb.lower),
+            ("bb_average", bb.average),
+            ("bb_upper", bb.upper),
+            ("rsi", rsi),
+            ("rsi_low", rsi_range.start),
+            ("rsi_high", rsi_range.end),
+        ]
+    }
+
+    fn confirms_entry(&self, _symbol: &Symbol, bars: &[bars::Bar]) -> bool {
+        bars.rsi(self.rsi_warmup, self.price_input)
+            .map_or(false, |rsi| rsi >= self.confirm_rsi_min)
+    }
+
+    fn rationale(
+        &self,
+        symbol: &Symbol,
+        position: Option<&Position>,
+        bars: &[bars::Bar],
+        price: &Num,
+        now: DateTime<Utc>,
+    ) -> Vec<(&'static str, bool)> {
+        self.conditions(symbol, position, bars, price, now)
+            .map_or_else(Vec::new, |conditions| conditions.named())
+    }
+
+    fn evaluate(
+        &self,
+        symbol: &Symbol,
+        position: Option<&Position>,
+        bars: &[bars::Bar],
+        price: &Num,
+        now: DateTime<Utc>,
+        fractional: bool,
+    ) -> Option<(Side, Amount)> {
+        let Indicators { bb, rsi, atr } = self.indicators(symbol, bars)?;
+        let rsi_range = self.rsi_range(atr, price)?;
+
+        tracing::debug!(
+            "{:<5} | (${}) | bb {} < {} < {} | rsi {:.2} ({:.0}..{:.0})",
+            symbol,
+            format_price(symbol, price),
+            format_price_level(symbol, bb.lower),
+            format_price_level(symbol, bb.average),
+            format_price_level(symbol, bb.upper),
+            rsi,
+            rsi_range.start,
+            rsi_range.end
+        );
+
+        let Conditions {
+            owned,
+            oversold,
+            below_lower_band,
+            overbought,
+            above_upper_band,
+            held_too_long,
+            take_profit,
+            stop_loss,
+            scaled_out,
+            trailing_stop,
+            new_rungs: rungs,
+        } = self.conditions(symbol, position, bars, price, now)?;
+        let all_owned = position.map(|pos| pos.owned.clone()).unwrap_or_default();
+
+        if !owned && self.entry_rule.holds(oversold, below_lower_band) {
+            Some((Side::Buy, Amount::quantity(1)))
+        } else if owned && !rungs.is_empty() && !held_too_long && !stop_loss {
+            let quantity = sizing::fraction_of(
+                all_owned.clone(),
+                self.rungs_fraction(position.unwrap(), &rungs),
+                fractional,
+            );
+
+            // too small of a position to split up
+            if quantity.is_zero() {
+                return Some((Side::Sell, Amount::quantity(all_owned)));
+            }
+
+            Some((Side::Sell, Amount::quantity(quantity)))
+        } else if owned && take_profit && !scaled_out && self.scale_out.is_some() {
+            let quantity = sizing::fraction_of(
+                all_owned.clone(),
+                self.scale_out.clone().unwrap(),
+                fractional,
+            );
+
+            // too small of a position to split up
+            if quantity.is_zero() {
+                return Some((Side::Sell, Amount::quantity(all_owned)));
+            }
+
+            Some((Side::Sell, Amount::quantity(quantity)))
+        } else if owned
+            && (held_too_long
+                || stop_loss
+                || trailing_stop
+                // once scaled out, the rest rides past the profit target
+                || (take_profit && !scaled_out)
+                || (overbought && above_upper_band))
+        {
+            Some((Side::Sell, Amount::quantity(all_owned)))
+        } else {
+            None
+        }
+    }
+}
+
+/// Buys when the fast moving average crosses above the slow one (a golden cross), and sells when
+/// it crosses back below (a death cross).
+pub(crate) struct MovingAverageCrossover {
+    pub(crate) fast: usize,
+    pub(crate) slow: usize,
+    pub(crate) price_input: PriceInput,
+}
+
+/// The moving averages as of the latest bar and the one before it, as a crossover is only told
+/// apart from a trend by where the averages were before.
+struct Crossover {
+    fast: f64,
+    slow: f64,
+    previous_fast: f64,
+    previous_slow: f64,
+}
+
+impl Crossover {
+    fn golden(&self) -> bool {
+        self.previous_fast <= self.previous_slow && self.fast > self.slow
+    }
+
+    fn death(&self) -> bool {
+        self.previous_fast >= self.previous_slow && self.fast < self.slow
+    }
+}
+
+impl MovingAverageCrossover {
+    fn crossover(&self, bars: &[bars::Bar]) -> Option<Crossover> {
+        let (_, previous_bars) = bars.split_last()?;
+
+        Some(Crossover {
+            fast: bars.sma(self.fast, self.price_input)?,
+            slow: bars.sma(self.slow, self.price_input)?,
+            previous_fast: previous_bars.sma(self.fast, self.price_input)?,
+            previous_slow: previous_bars.sma(self.slow, self.price_input)?,
+        })
+    }
+
+    fn conditions(position: Option<&Position>, crossover: &Crossover) -> Vec<(&'static str, bool)> {
+        vec![
+            ("owned", position.map_or(false, |pos| !pos.owned.is_zero())),
+            ("golden_cross", crossover.golden()),
+            ("death_cross", crossover.death()),
+        ]
+    }
+
+    fn decision(
+        symbol: &Symbol,
+        position: Option<&Position>,
+        price: &Num,
+        crossover: &Crossover,
+    ) -> Option<(Side, Amount)> {
+        tracing::debug!(
+            "{:<5} | (${}) | sma {} / {} (was {} / {})",
+            symbol,
+            format_price(symbol, price),
+            format_price_level(symbol, crossover.fast),
+            format_price_level(symbol, crossover.slow),
+            format_price_level(symbol, crossover.previous_fast),
+            format_price_level(symbol, crossover.previous_slow)
+        );
+
+        let all_owned = position.map(|pos| pos.owned.clone()).unwrap_or_default();
+
+        if all_owned.is_zero() && crossover.golden() {
+            Some((Side::Buy, Amount::quantity(1)))
+        } else if !all_owned.is_zero() && crossover.death() {
+            Some((Side::Sell, Amount::quantity(all_owned)))
+        } else {
+            None
+        }
+    }
+}
+
+impl Strategy for MovingAverageCrossover {
+    fn min_bars(&self) -> usize {
+        // one extra bar to know where the averages were before the latest one
+        self.fast.max(self.slow) + 1
+    }
+
+    fn indicator_values(
+        &self,
+        _symbol: &Symbol,
+        bars: &[bars::Bar],
+        _price: &Num,
+    ) -> Vec<(&'static str, f64)> {
+        [
+            ("sma_fast", bars.sma(self.fast, self.price_input)),
+            ("sma_slow", bars.sma(self.slow, self.price_input)),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| Some((name, value?)))
+        .collect()
+    }
+
+    fn rationale(
+        &self,
+        _symbol: &Symbol,
+        position: Option<&Position>,
+        bars: &[bars::Bar],
+        _price: &Num,
+        _now: DateTime<Utc>,
+    ) -> Vec<(&'static str, bool)> {
+        self.crossover(bars)
+            .map(|crossover| Self::conditions(position, &crossover))
+            .unwrap_or_default()
+    }
+
+    fn evaluate(
+        &self,
+        symbol: &Symbol,
+        position: Option<&Position>,
+        bars: &[bars::Bar],
+        price: &Num,
+        _now: DateTime<Utc>,
+        _fractional: bool,
+    ) -> Option<(Side, Amount)> {
+        let crossover = self.crossover(bars)?;
+        Self::decision(symbol, position, price, &crossover)
+    }
+
+    fn decide(
+        &self,
+        symbol: &Symbol,
+        position: Option<&Position>,
+        bars: &[bars::Bar],
+        price: &Num,
+        _now: DateTime<Utc>,
+        _fractional: bool,
+    ) -> (Option<(Side, Amount)>, Vec<(&'static str, bool)>) {
+        match self.crossover(bars) {
+            Some(crossover) => (
+                Self::decision(symbol, position, price, &crossover),
+                Self::conditions(position, &crossover),
+            ),
+            None => (None, Vec::new()),
+        }
+    }
+}
+
+/// Buys every symbol the first time it's seen and never sells it. It's the baseline that any other
+/// strategy has to beat to be worth trading. It always runs with equity sizing, which gives each
+/// position the same slice of the equity.
+pub(crate) struct BuyAndHold;
+
+impl Strategy for BuyAndHold {
+    // the positions are as big as an even split of the whole equity makes them
+    fn max_concentration_pct(&self) -> Num {
+        Num::from(100)
+    }
+
+    fn risk_fraction(&self) -> Option<Num> {
+        Some(Num::from(1))
+    }
+
+    fn rationale(
+        &self,
+        _symbol: &Symbol,
+        position: Option<&Position>,
+        _bars: &[bars::Bar],
+        _price: &Num,
+        _now: DateTime<Utc>,
+    ) -> Vec<(&'static str, bool)> {
+        vec![("seen", position.is_some())]
+    }
+
+    fn evaluate(
+        &self,
+        _symbol: &Symbol,
+        position: Option<&Position>,
+        _bars: &[bars::Bar],
+        _price: &Num,
+        _now: DateTime<Utc>,
+        _fractional: bool,
+    ) -> Option<(Side, Amount)> {
+        // a position that was bought and sold off some other way isn't bought again. the equity
+        // sizing decides how much
+        position.is_none().then(|| (Side::Buy, Amount::notional(0)))
+    }
+}
@@ -1,26 +1,92 @@
 use std::{ops::Add, time::Duration};
 
 use apca::api::v2::clock::{self, Clock};
-use chrono::{DateTime, Local, Utc};
+use chrono::{DateTime, NaiveTime, Utc};
+use chrono_tz::Tz;
 use tokio::time::{Interval, MissedTickBehavior};
 
 use crate::backend::Backend;
 
+// where the market hours are given in, unless told otherwise
+pub(crate) const EXCHANGE_TZ: Tz = chrono_tz::America::New_York;
+
+// closes that the market stays shut for longer than this after are weekends or holidays, rather
+// than just a night
+const LONG_BREAK_HOURS: i64 = 24;
+
+// the clock is asked for again once it's this old, even in the middle of a session
+const DEFAULT_MAX_CLOCK_AGE_MINUTES: i64 = 60;
+
 pub(crate) enum MarketStatus {
     Open,
     AboutToClose,
 }
 
+/// Where the current time comes from, so that the open and close logic can be tested.
+pub(crate) trait TimeSource: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// The actual time.
+pub(crate) struct SystemTime;
+
+impl TimeSource for SystemTime {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// Follows tokio's clock, so that it moves along with paused time.
+#[cfg(test)]
+#[derive(Clone, Copy)]
+pub(crate) struct PausedTime {
+    start: DateTime<Utc>,
+    instant: tokio::time::Instant,
+}
+
+#[cfg(test)]
+impl PausedTime {
+    pub(crate) fn starting_at(start: &str) -> Self {
+        Self {
+            start: start.parse().unwrap(),
+            instant: tokio::time::Instant::now(),
+        }
+    }
+}
+
+#[cfg(test)]
+impl TimeSource for PausedTime {
+    fn now(&self) -> DateTime<Utc> {
+        self.start + chrono::Duration::from_std(self.instant.elapsed()).unwrap()
+    }
+}
+
 pub(crate) struct Ticker {
     interval: Interval,
     clock: Clock,
+    /// when `clock` was asked for
+    fetched: DateTime<Utc>,
+    /// when the current or next session opens, as far as we know
+    session_open: DateTime<Utc>,
+    max_clock_age: chrono::Duration,
     open_and_ready: bool,
+    time: Box<dyn TimeSource>,
+    /// the timezone the market hours are logged in
+    display_tz: Tz,
 }
 
 impl Ticker {
     pub(crate) async fn new(
         backend: &dyn Backend,
         period: Duration,
+    ) -> Result<Self, apca::RequestError<clock::GetError>> {
+        Self::with_time_source(backend, period, Box::new(SystemTime)).await
+    }
+
+    pub(crate) async fn with_time_source(
+        backend: &dyn Backend,
+        period: Duration,
+        time: Box<dyn TimeSource>,
     ) -> Result<Self, apca::RequestError<clock::GetError>> {
         let mut interval = tokio::time::interval(period);
         interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
@@ -30,24 +96,58 @@ impl Ticker {
         Ok(Self {
             interval,
             clock,
+            fetched: time.now(),
+            session_open: if clock.open {
+                clock.timestamp
+            } else {
+                clock.next_open
+            },
+            max_clock_age: chrono::Duration::minutes(DEFAULT_MAX_CLOCK_AGE_MINUTES),
             open_and_ready: clock.open,
+            time,
+            display_tz: EXCHANGE_TZ,
         })
     }
 
+    /// Logs the market hours in the timezone instead of the exchange's.
+    pub(crate) fn display_in(&mut self, tz: Tz) {
+        self.display_tz = tz;
+    }
+
+    /// Asks for the clock again whenever it's older than `age`, so that a program that runs for
+    /// days doesn't drift away from the market hours.
+    pub(crate) fn refetch_clock_after(&mut self, age: chrono::Duration) {
+        self.max_clock_age = age;
+    }
+
+    /// The time of day at the exchange, which is what trading windows are given in.
+    pub(crate) fn exchange_time(&self) -> NaiveTime {
+        self.time.now().with_timezone(&EXCHANGE_TZ).time()
+    }
+
     pub(crate) async fn wait_for_open_or_tick(&mut self, backend: &dyn Backend) -> MarketStatus {
-        let now = Utc::now();
+        let now = self.time.now();
+
+        if now.signed_duration_since(self.fetched) > self.max_clock_age {
+            self.clock = backend.clock_now().await;
+            self.fetched = now;
+
+            // the day has to be ended, even if it's late. a clock from before the open is only
+            // behind, the session hasn't even started as far as it knows
+            if self.open_and_ready && !self.clock.open && self.clock.timestamp >= self.session_open
+            {
+                tracing::warn!("The market closed earlier than expected, catching up");
+                self.open_and_ready = false;
+                return MarketStatus::AboutToClose;
+            }
+        }
 
         // `self.clock` was created yesterday, probably while the market was closed.
         // Because of that, it's `open` field isn't going to be accurate.
         // `self.open_and_ready` should be up-to-date. We maintain it ourselves to avoid constant
         // requests for the clock.
         if self.open_and_ready {
-            let time_left = self
-                .clock
-                .next_close
-                .signed_duration_since(now)
-                .to_std()
-                .unwrap();
+            let time_left = saturating_std(self.clock.next_close.signed_duration_since(now));
 
             // gives us plenty of time to tick and still be able to execute some final logic
             let about_to_close = time_left <= self.interval.period() * 2;
@@ -69,45 +169,322 @@ impl Ticker {
         // today's close, but `next_open` will be for tomorrow.
         // If we started with a closed market, both `next_open` and `next_close` will be for today.
         if (self.clock.open || self.clock.next_open < now) && now < self.clock.next_close {
-            let time_left = self
-                .clock
-                .next_close
-                .signed_duration_since(now)
-                .add(chrono::Duration::seconds(1))
-                .to_std()
-                .unwrap();
+            let time_left = saturating_std(
+                self.clock
+                    .next_close
+                    .signed_duration_since(now)
+                    .add(chrono::Duration::seconds(1)),
+            );
 
             tokio::time::sleep(time_left).await;
         }
 
         // now we can get the clock information for tomorrow
         self.clock = backend.clock_now().await;
+        self.session_open = self.clock.next_open;
 
         // we should only be here if the day ended
         assert!(!self.clock.open);
 
-        let next_open: DateTime<_> = self.clock.next_open.with_timezone(&chrono_tz::EST);
-        let next_close: DateTime<_> = self.clock.next_close.with_timezone(&chrono_tz::EST);
+        let next_open: DateTime<_> = self.clock.next_open.with_timezone(&self.display_tz);
+        let next_close: DateTime<_> = self.clock.next_close.with_timezone(&self.display_tz);
 
         tracing::info!(
             "Sleeping until the market opens on {} - {}",
-            next_open.format("%A %d/%m/%Y at %I:%M %P EST"),
-            next_close.format("%I:%M %P EST")
+            next_open.format("%A %d/%m/%Y at %I:%M %P %Z"),
+            next_close.format("%I:%M %P %Z")
         );
 
-        tokio::time::sleep(
-            self.clock
-                .next_open
-                .signed_duration_since(Utc::now())
-                .to_std()
-                .unwrap(),
-        )
+        tokio::time::sleep(saturating_std(
+            self.clock.next_open.signed_duration_since(self.time.now()),
+        ))
         .await;
 
         tracing::info!("Sleep over");
 
+        // the clock is only as old as the session, it was already up to date about it
+        self.fetched = self.time.now();
+
         self.open_and_ready = true;
 
         MarketStatus::Open
     }
 }
+
+/// Whether the market stays closed for longer than a night after the current session, e.g. over a
+/// weekend or a holiday.
+pub(crate) async fn long_break_ahead(backend: &dyn Backend) -> bool {
+    // the ticker's clock is from before the session, its next open is the one that already happened
+    long_break_after(&backend.clock_now().await)
+}
+
+// how long the market stays closed after the current or latest session is compared against a night
+fn long_break_after(clock: &Clock) -> bool {
+    let closes = if clock.open {
+        clock.next_close
+    } else {
+        clock.timestamp
+    };
+
+    clock.next_open.signed_duration_since(closes) > chrono::Duration::hours(LONG_BREAK_HOURS)
+}
+
+// a time that has already passed is no time at all. the clock can be a little behind ours, or
+// stale, and that shouldn't bring the whole program down
+pub(crate) fn saturating_std(duration: chrono::Duration) -> Duration {
+    duration.to_std().unwrap_or(Duration::ZERO)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use apca::api::v2::clock::Clock;
+    use chrono::{DateTime, Utc};
+
+    use super::{long_break_after, MarketStatus, PausedTime, Ticker, TimeSource};
+    use crate::backend::{Backend, CompressedMarket, TestBackend};
+
+    const PERIOD: Duration = Duration::from_secs(90);
+
+    fn time(time: &str) -> DateTime<Utc> {
+        time.parse().unwrap()
+    }
+
+    fn clock(open: bool, current: &str, next_open: &str, next_close: &str) -> Clock {
+        // the fields can't be set directly outside of apca
+        serde_json::from_value(serde_json::json!({
+            "is_open": open,
+            "timestamp": current,
+            "next_open": next_open,
+            "next_close": next_close,
+        }))
+        .unwrap()
+    }
+
+    // ticks until the market is about to close, making sure it was open the whole time
+    async fn run_until_close(ticker: &mut Ticker, backend: &dyn Backend) {
+        for _ in 0..1000 {
+            match ticker.wait_for_open_or_tick(backend).await {
+                MarketStatus::Open => {}
+                MarketStatus::AboutToClose => return,
+            }
+        }
+        panic!("the market never closed");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn stale_clock_closes_instead_of_panicking() {
+        // the clock still says open, but its close is already behind us
+        let time_source = PausedTime::starting_at("2023-06-05T20:00:05Z");
+        let backend = TestBackend::with_clocks([clock(
+            true,
+            "2023-06-05T19:59:00Z",
+            "2023-06-06T13:30:00Z",
+            "2023-06-05T20:00:00Z",
+        )]);
+
+        let mut ticker = Ticker::with_time_source(&backend, PERIOD, Box::new(time_source))
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            ticker.wait_for_open_or_tick(&backend).await,
+            MarketStatus::AboutToClose
+        ));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn started_while_open() {
+        let time_source = PausedTime::starting_at("2023-06-05T14:00:00Z");
+        let backend = TestBackend::with_clocks([clock(
+            true,
+            "2023-06-05T14:00:00Z",
+            "2023-06-06T13:30:00Z",
+            "2023-06-05T20:00:00Z",
+        )]);
+
+        let mut ticker = Ticker::with_time_source(&backend, PERIOD, Box::new(time_source))
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            ticker.wait_for_open_or_tick(&backend).await,
+            MarketStatus::Open
+        ));
+
+        run_until_close(&mut ticker, &backend).await;
+
+        let time_left = time("2023-06-05T20:00:00Z") - time_source.now();
+        assert!(time_left >= chrono::Duration::zero());
+        assert!(time_left <= chrono::Duration::from_std(PERIOD * 2).unwrap());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn started_while_closed() {
+        let time_source = PausedTime::starting_at("2023-06-05T12:00:00Z");
+        let backend = TestBackend::with_clocks([clock(
+            false,
+            "2023-06-05T12:00:00Z",
+            "2023-06-05T13:30:00Z",
+            "2023-06-05T20:00:00Z",
+        )]);
+
+        let mut ticker = Ticker::with_time_source(&backend, PERIOD, Box::new(time_source))
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            ticker.wait_for_open_or_tick(&backend).await,
+            MarketStatus::Open
+        ));
+
+        // it should have waited for the open, and not for the close
+        let now = time_source.now();
+        assert!(now >= time("2023-06-05T13:30:00Z"));
+        assert!(now < time("2023-06-05T20:00:00Z"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn next_open_is_tomorrow_but_next_close_is_today() {
+        let time_source = PausedTime::starting_at("2023-06-05T19:00:00Z");
+        let backend = TestBackend::with_clocks([
+            clock(
+                true,
+                "2023-06-05T19:00:00Z",
+                "2023-06-06T13:30:00Z",
+                "2023-06-05T20:00:00Z",
+            ),
+            clock(
+                false,
+                "2023-06-05T20:00:01Z",
+                "2023-06-06T13:30:00Z",
+                "2023-06-06T20:00:00Z",
+            ),
+        ]);
+
+        let mut ticker = Ticker::with_time_source(&backend, PERIOD, Box::new(time_source))
+            .await
+            .unwrap();
+
+        run_until_close(&mut ticker, &backend).await;
+
+        // the rest of today has to pass before tomorrow's open is waited for
+        assert!(matches!(
+            ticker.wait_for_open_or_tick(&backend).await,
+            MarketStatus::Open
+        ));
+        assert!(time_source.now() >= time("2023-06-06T13:30:00Z"));
+
+        run_until_close(&mut ticker, &backend).await;
+        assert!(time_source.now() < time("2023-06-06T20:00:00Z"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn clock_from_before_the_open_isnt_an_early_close() {
+        let time_source = PausedTime::starting_at("2023-06-05T12:00:00Z");
+        // the clock that is asked for again during the session still hasn't caught up to the open
+        let backend = TestBackend::with_clocks([clock(
+            false,
+            "2023-06-05T12:00:00Z",
+            "2023-06-05T13:30:00Z",
+            "2023-06-05T20:00:00Z",
+        )]);
+
+        let mut ticker = Ticker::with_time_source(&backend, PERIOD, Box::new(time_source))
+            .await
+            .unwrap();
+        ticker.refetch_clock_after(chrono::Duration::minutes(10));
+
+        run_until_close(&mut ticker, &backend).await;
+
+        let time_left = time("2023-06-05T20:00:00Z") - time_source.now();
+        assert!(time_left <= chrono::Duration::from_std(PERIOD * 2).unwrap());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn compressed_market_goes_through_whole_days() {
+        let minutes = chrono::Duration::minutes;
+        let first_open = time("2023-06-05T13:30:00Z");
+        let time_source = PausedTime::starting_at("2023-06-05T13:00:00Z");
+        let backend = TestBackend::with_market(
+            CompressedMarket {
+                first_open,
+                open_for: minutes(20),
+                closed_for: minutes(10),
+            },
+            Box::new(time_source),
+        );
+
+        let mut ticker = Ticker::with_time_source(&backend, PERIOD, Box::new(time_source))
+            .await
+            .unwrap();
+
+        for day in 0..3 {
+            let opens = first_open + minutes(30 * day);
+
+            assert!(matches!(
+                ticker.wait_for_open_or_tick(&backend).await,
+                MarketStatus::Open
+            ));
+            assert!(time_source.now() >= opens);
+
+            run_until_close(&mut ticker, &backend).await;
+            assert!(time_source.now() < opens + minutes(20));
+        }
+    }
+
+    #[test]
+    fn weekends_are_long_breaks_but_nights_arent() {
+        // a thursday and a friday, a little before the close
+        let thursday = clock(
+            true,
+            "2023-06-08T19:55:00Z",
+            "2023-06-09T13:30:00Z",
+            "2023-06-08T20:00:00Z",
+        );
+        let friday = clock(
+            true,
+            "2023-06-09T19:55:00Z",
+            "2023-06-12T13:30:00Z",
+            "2023-06-09T20:00:00Z",
+        );
+
+        assert!(!long_break_after(&thursday));
+        assert!(long_break_after(&friday));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn old_clock_is_asked_for_again() {
+        let time_source = PausedTime::starting_at("2023-06-05T14:00:00Z");
+        let backend = TestBackend::with_clocks([
+            clock(
+                true,
+                "2023-06-05T14:00:00Z",
+                "2023-06-06T13:30:00Z",
+                "2023-06-05T20:00:00Z",
+            ),
+            // the market closed early, which the first clock didn't know about
+            clock(
+                false,
+                "2023-06-05T14:30:00Z",
+                "2023-06-06T13:30:00Z",
+                "2023-06-06T20:00:00Z",
+            ),
+        ]);
+
+        let mut ticker = Ticker::with_time_source(&backend, PERIOD, Box::new(time_source))
+            .await
+            .unwrap();
+        ticker.refetch_clock_after(chrono::Duration::minutes(30));
+
+        run_until_close(&mut ticker, &backend).await;
+        assert!(time_source.now() < time("2023-06-05T15:00:00Z"));
+
+        assert!(matches!(
+            ticker.wait_for_open_or_tick(&backend).await,
+            MarketStatus::Open
+        ));
+        assert!(time_source.now() >= time("2023-06-06T13:30:00Z"));
+    }
+}